@@ -37,9 +37,10 @@ impl BenchRuleAct {
 
             eff: helpers::effect_builder()
             .remove_obj(|req: &mut RequestedObj<u32>| {
-                req.rand_tags(0).unwrap().get(0).unwrap().clone()
+                *req.rand_tags(0).unwrap().first().unwrap()
             })
             .crate_obj(|_| Box::new(BenchO::new(helpers::IdGen::next_u32_id())))
+            .produces::<BenchO>()
             //.crate_obj(|_| Box::new(StopObj { tag: helpers::IdGen::next_u32_id() }))
             .increase_untagged::<StopObj>(1)
             .build(),
@@ -102,11 +103,11 @@ impl BenchComRule {
                     let co = req.set_ref(0).unwrap();
                     let ct: &u32 = co.obj_tag();
                     let v = vec![
-                        SendWrapper::new(Box::new(BenchO::new(helpers::IdGen::next_u32_id())), ct.clone())
+                        SendWrapper::new(Box::new(BenchO::new(helpers::IdGen::next_u32_id())), *ct)
                     ];
-                    let pobj = Box::new(SendMsg::<u32>::new(helpers::IdGen::next_u32_id(), v));
-                    pobj
+                    Box::new(SendMsg::<u32>::new(helpers::IdGen::next_u32_id(), v))
                 })
+                .produces::<SendMsg<u32>>()
                 .build(),
         }
     }
@@ -119,7 +120,7 @@ struct BenchO {
 }
 impl BenchO {
     pub fn new(tg: u32) -> Self {
-        Self { tg: tg }
+        Self { tg }
     }
 }
 
@@ -134,7 +135,7 @@ fn criterion_benchmark(c: &mut Criterion<RxxOPS>) {
     for ((elems, name), e_time) in elements.iter().zip(name.iter()).zip(est_time.iter()) {
         group.throughput(Throughput::Elements(loop_count as u64));
         group.measurement_time(Duration::from_secs(*e_time));
-        group.bench_with_input(format!("{name}"), elems, |b,elems| {
+        group.bench_with_input(name.to_string(), elems, |b,elems| {
             let obj_count = *elems as usize;
             let (ta, tb) = (helpers::IdGen::next_u32_id(), helpers::IdGen::next_u32_id());
             b.iter_batched_ref( 
@@ -3,6 +3,7 @@ extern crate proc_macro;
 
 use std::borrow::Borrow;
 
+use proc_macro2::TokenStream;
 use quote::{quote, ToTokens};
 
 use syn::{parse::Parse, AngleBracketedGenericArguments, DeriveInput, Ident};
@@ -85,7 +86,27 @@ pub fn iobj_macro_derive(input: proc_macro::TokenStream) -> proc_macro::TokenStr
     }).into()
 }
 
-#[proc_macro_derive(IRule, attributes(condition, effect, obj_tag_type, obj_unit_type))]
+/// 找到带有 `attr` 属性的字段，由字段名生成方法，没有该字段时返回 `default`
+fn attr_fn<F>(ast: &DeriveInput, attr: &str, default: TokenStream, method: F) -> TokenStream
+where F: FnOnce(Ident) -> TokenStream {
+    let field = if let syn::Data::Struct(s) = ast.data.borrow() {
+        if let syn::Fields::Named(fields) = &s.fields {
+            fields.named.iter().find(|f| {
+                f.attrs.iter().any(|a| a.path().is_ident(attr))
+            })
+        } else {
+            None
+        }
+    } else {
+        None
+    };
+    field.map_or(default, |f| {
+        let member = f.ident.clone().unwrap_or_else(|| panic!("{}属性不完整", attr));
+        method(member)
+    })
+}
+
+#[proc_macro_derive(IRule, attributes(condition, effect, priority, rate, duration, probability, obj_tag_type, obj_unit_type))]
 pub fn irule_macro_derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let ast: DeriveInput = syn::parse(input).unwrap();
     let name = &ast.ident;
@@ -153,6 +174,11 @@ pub fn irule_macro_derive(input: proc_macro::TokenStream) -> proc_macro::TokenSt
     let eff = eff_field.ident.expect("effect属性不完整");
     let eff_type = eff_field.ty;
 
+    let priority_fn = attr_fn(&ast, "priority", quote! {}, |m| quote! { fn priority(&self) -> i32 { self.#m } });
+    let rate_fn = attr_fn(&ast, "rate", quote! {}, |m| quote! { fn rate(&self) -> Option<f64> { Into::<Option<f64>>::into(self.#m) } });
+    let duration_fn = attr_fn(&ast, "duration", quote! {}, |m| quote! { fn duration(&self) -> f64 { Into::<f64>::into(self.#m) } });
+    let probability_fn = attr_fn(&ast, "probability", quote! {}, |m| quote! { fn probability(&self) -> f64 { Into::<f64>::into(self.#m) } });
+
     (quote! {
         impl #impl_generics meme::core::IRule for #name #ty_generics #where_clause {
             type ObjTag = #obj_tag_type;
@@ -161,6 +187,10 @@ pub fn irule_macro_derive(input: proc_macro::TokenStream) -> proc_macro::TokenSt
             type Effect = #eff_type;
             fn condition(&self) -> &Self::Condition { &self.#cond }
             fn effect(&self) -> &Self::Effect { &self.#eff }
+            #priority_fn
+            #rate_fn
+            #duration_fn
+            #probability_fn
         }
     }).into()
 }
@@ -1,11 +1,13 @@
 // Copyright 2024 Junshuang Hu
 use ahash::{AHashMap, AHashSet};
 use krnl::scalar::Scalar;
+use rand::Rng;
 use rand::seq::IteratorRandom;
 use rand::seq::SliceRandom;
 
-use crate::errors::MemError;
+use crate::errors::{AmountError, MemError};
 use crate::helpers;
+use crate::objs::uid::UuidTag;
 use crate::lib_info::log_target;
 use crate::rules::BasicCondition;
 use crate::rules::BasicEffect;
@@ -25,7 +27,9 @@ pub type ArcObj<T, U = u32> = Arc<dyn IObj<Tag = T, Unit = U> + Send + Sync>;
 pub type PRule<T, OT = T, U = u32, OU = U, E = BasicEffect<OT, OU>, C = BasicCondition<OT, OU>> 
             = Box<dyn IRule<Tag = T, ObjTag = OT, Unit = U, ObjUnit = OU, Effect = E, Condition = C> + Send + Sync>;
 pub type UntaggedPresences<U> = Vec<UntaggedPresence<U>>;
-pub type TaggedPresences<T> = Vec<TaggedPresence<T>>;
+pub type TaggedPresences<T, U = u32> = Vec<TaggedPresence<T, U>>;
+pub type ObjPredicate<T, U> = fn(&dyn IObj<Tag = T, Unit = U>) -> bool;
+pub type ObjWeight<T, U> = fn(&dyn IObj<Tag = T, Unit = U>) -> f64;
 
 pub type ObjsCrateFn<T, U> = fn(&mut RequestedObj<T, U>) -> Vec<PObj<T, U>>;
 pub type ObjCrateFn<T, U> = fn(&mut RequestedObj<T, U>) -> PObj<T, U>;
@@ -67,6 +71,15 @@ pub trait ITaggedStore<Tag, Value> {
     /// 添加 `(t, v)`  
     /// 如果已存在 `(t, v_old)` 则替换并返回 `(t, v_old)`
     fn add_or_update(&mut self, t: Tag, v: Value) -> Option<Value>;
+    /// 添加 `(t, v)`  
+    /// 如果已存在 `t` 则不做修改并返回 `Err(v)`
+    fn try_add(&mut self, t: Tag, v: Value) -> Result<(), Value> {
+        if self.contains(&t) {
+            return Err(v);
+        }
+        self.add_or_update(t, v);
+        Ok(())
+    }
 
     fn remove(&mut self, t: &Tag) -> Option<Value>;
     /// 批量删除  
@@ -75,49 +88,67 @@ pub trait ITaggedStore<Tag, Value> {
     /// 批量删除  
     /// 按顺序移除并返回 `ts` 中 `tag` 对应的 `value`，不存在的会被跳过
     fn remove_batch_skip(&mut self, ts: &[Tag]) -> Vec<Value>;
+
+    /// 按类型索引的 `ty` 类型对象的 tag，用于随机选择  
+    /// 返回 [`None`] 表示没有按类型的索引，需要遍历所有对象
+    fn tags_of(&self, _ty: &TypeId) -> Option<&[Tag]> {
+        None
+    }
 }
 
 pub trait IUntaggedStore<Ty, Unit: Scalar> {
+    /// 是否有数量大于 `0` 的 untagged `ty` 对象，只有 tagged `ty` 对象时为 `false`
     fn contains_u(&self, ty: &Ty) -> bool;
+    /// 有数量记录的类型数
     fn len_u(&self) -> usize;
     fn is_empty_u(&self) -> bool;
 
     fn iter_u<'a>(&'a self) -> impl Iterator<Item = &'a Unit> where Unit: 'a;
     fn iter_mut_u<'a>(&'a mut self) -> impl Iterator<Item = &'a mut Unit> where Unit: 'a;
 
+    /// untagged `ty` 对象的数量，没有该类型的记录时返回 [`None`]
     fn get_u(&self, ty: &Ty) -> Option<Unit>;
-    /// 增加 `ty` 对象的数量  
-    /// 如果已有 `ty` 对象 将增加 `amount` 单位并返回 `true`  
-    /// 如果不存在则插入 `amount` 单位的 `ty` 并返回 `false`
-    fn increase(&mut self, ty: &Ty, amount: Unit) -> bool;
-    /// 减少 `ty` 对象的数量  
-    /// 如果已有足够的 `ty` 对象 将减少 `amount` 单位并返回 `true`  
-    /// 如果不存在 `ty` 或者数量不足则返回 `false`
-    fn decrease(&mut self, ty: &Ty, amount: Unit) -> bool;
+    /// 增加 `amount` 单位的 untagged `ty` 对象，返回增加后的 untagged 数量  
+    /// 该类型的总数量超出 `Unit` 的范围时不做修改并返回 [`AmountError::Overflow`]
+    fn increase(&mut self, ty: &Ty, amount: Unit) -> Result<Unit, AmountError<Ty, Unit>>;
+    /// 减少 `amount` 单位的 untagged `ty` 对象，返回减少后的 untagged 数量  
+    /// 不存在 `ty` 或者 untagged 数量不足时不做修改并返回错误，tagged 对象不会被计入
+    fn decrease(&mut self, ty: &Ty, amount: Unit) -> Result<Unit, AmountError<Ty, Unit>>;
+    /// 移除所有 untagged `ty` 对象并返回移除的数量，没有该类型的记录时返回 [`None`]  
+    /// tagged 对象不受影响，没有 tagged `ty` 对象时同时移除该类型的记录
     fn remove_u(&mut self, ty: &Ty)-> Option<Unit>;
 }
 
+/// 对象数量的三种视图：tagged（按 [`IObj::obj_amount`] 计）、untagged 以及两者之和（total）  
+/// 位置（[`IObjStat::pos_of`]、[`IObjStat::tid_at`]）对三种视图相同，`amounts*` 按位置给出数量  
+/// 规则的数量条件只按 untagged 数量检查，与 [`IUntaggedStore::decrease`] 一致  
+/// 不兼容变更：此前数量条件按总数量检查，tagged 对象也能满足 [`crate::helpers::ConditionBuilder::some_untagged`]，
+/// 现在 untagged 条件忽略 tagged 对象，依赖 tagged 对象的规则需要改用 [`crate::helpers::ConditionBuilder::rand_tagged`] 等 tagged 条件
 /// todo: 保证高效实现下的一致性
 pub trait IObjStat<Unit: Scalar> {
     fn pos_of(&self, ty: &ObjType) -> Option<usize>;
     fn type_count(&self) -> usize;
     fn tid_at(&self, pos: usize) -> Option<&TypeId>;
 
-    fn amounts(&self) -> impl Iterator<Item = &Unit>;
+    fn amounts(&self) -> impl Iterator<Item = Unit>;
     fn amount_of(&self, ty: &ObjType) -> Option<Unit>;
-    fn amount_of_many(&self, tys: &[ObjType]) -> Vec<&Unit>;
+    fn amount_of_many(&self, tys: &[ObjType]) -> Vec<Unit>;
 
-    fn amounts_u(&self) -> impl Iterator<Item = &Unit>;
+    fn amounts_t(&self) -> impl Iterator<Item = Unit>;
+    fn amount_of_t(&self, ty: &ObjType) -> Option<Unit>;
+    fn amount_of_many_t(&self, tys: &[ObjType]) -> Vec<Unit>;
+
+    fn amounts_u(&self) -> impl Iterator<Item = Unit>;
     fn amount_of_u(&self, ty: &ObjType) -> Option<Unit>;
-    fn amount_of_many_u(&self, tys: &[ObjType]) -> Vec<&Unit>;
-
-    // /// 该方法用于表示是否存在对象的更改  
-    // /// 如果对象被更改（或可能更改）则返回 true，否之返回 false  
-    // /// 使用 [`IObjStat::dismiss()`] 来确认已处理更改，使该方法返回 false
-    // fn modified(&self) -> bool;
-    // /// 该方法用于确认外部已经处理了对象更改  
-    // /// 置 [`IObjStat::modified()`] 为 false
-    // fn dismiss(&mut self);
+    fn amount_of_many_u(&self, tys: &[ObjType]) -> Vec<Unit>;
+
+    /// 该方法用于表示是否存在对象的更改  
+    /// 如果对象被更改（或可能更改）则返回 true，否之返回 false  
+    /// 使用 [`IObjStat::dismiss()`] 来确认已处理更改，使该方法返回 false
+    fn modified(&self) -> bool;
+    /// 该方法用于确认外部已经处理了对象更改  
+    /// 置 [`IObjStat::modified()`] 为 false
+    fn dismiss(&mut self);
 }
 
 /// Effect 由规则产生，但是由膜解释，故在此处类型不受限  
@@ -138,17 +169,35 @@ pub trait IObjStat<Unit: Scalar> {
 /// todo: 引入两个接口，提供在 Effect 中使用 Condition 在检查时选择的对象的 tag 的能力 -ok
 pub trait IRuleEffect {
     type Effect: Clone;
-    fn from_builder(effs: Option<Vec<Self::Effect>>) -> Self;
+    fn from_builder(effs: Option<Vec<Self::Effect>>, sigs: Vec<EffectSignature>) -> Self;
     fn effects(&self) -> &Option<Vec<Self::Effect>>;
+    /// 每个操作声明的产生与消耗的对象类型，与 [`IRuleEffect::effects`] 按位置对应
+    fn signatures(&self) -> &[EffectSignature];
+
+    /// 所有操作声明产生的对象类型（不重复）
+    fn produces(&self) -> Vec<ObjType> {
+        self.signatures().iter().flat_map(|s| s.produces.iter()).fold(Vec::new(), |mut acc, t| {
+            if !acc.contains(t) { acc.push(t.clone()); }
+            acc
+        })
+    }
+
+    /// 所有操作声明消耗的对象类型（不重复）
+    fn consumes(&self) -> Vec<ObjType> {
+        self.signatures().iter().flat_map(|s| s.consumes.iter()).fold(Vec::new(), |mut acc, t| {
+            if !acc.contains(t) { acc.push(t.clone()); }
+            acc
+        })
+    }
 }
 
 /// 规则的条件是规则执行需要的对象，每个（对于tagged）或者一定数量的（对于untagged）对象只能用于一次  
 /// 规则执行，这些对象能且仅能被执行的规则修改  
 /// 只记录 Untagged 的需求量，tagged 对象需要即时计算  
 pub trait ICondition<Tag: Clone + Hash + Eq, Unit: Scalar>: Clone {
-    fn from_builder(uts: Option<UntaggedPresences<Unit>>, tgs: Option<TaggedPresences<Tag>>, skip_take: bool) -> Self;
+    fn from_builder(uts: Option<UntaggedPresences<Unit>>, tgs: Option<TaggedPresences<Tag, Unit>>, skip_take: bool) -> Self;
     fn untagged(&self) -> &Option<UntaggedPresences<Unit>>;
-    fn tagged(&self) -> &Option<TaggedPresences<Tag>>;
+    fn tagged(&self) -> &Option<TaggedPresences<Tag, Unit>>;
     fn skip_take(&self) -> bool;
 }
 
@@ -161,6 +210,17 @@ pub trait IRule: IObj {
     type Effect: IRuleEffect;
     fn condition(&self) -> &Self::Condition;
     fn effect(&self) -> &Self::Effect; // todo: 令 Effect 只能修改 Condition 选中的对象 -ok
+    /// 规则优先级，数值越大优先级越高  
+    /// 同一步中只有可执行规则里优先级最高的那些规则会被执行
+    fn priority(&self) -> i32 { 0 }
+    /// 动力学速率常数，只用于随机模拟模式（见 [`crate::mems::stochastic`]），没有速率的规则在这些模式中不会执行
+    fn rate(&self) -> Option<f64> { None }
+    /// 规则执行一次经过的模拟时间，极大并行模式中一步的时长为本步执行了的规则的最长时长
+    fn duration(&self) -> f64 { 1.0 }
+    /// 条件满足时执行的概率，由膜的随机数生成器抽取，默认为 `1`（总是执行）  
+    /// 抽取在检查条件时进行，早于按优先级的筛选：没有抽中的规则视为条件不满足，
+    /// 不会压制优先级更低的规则，也不占用对象
+    fn probability(&self) -> f64 { 1.0 }
 }
 
 /// todo: 保证高效实现下的一致性
@@ -170,17 +230,39 @@ where OT: Clone + Hash + Eq, U: Scalar, C: ICondition<OT, U> {
     fn tag_at(&self, pos: usize) -> Option<T>;
     fn effect_at(&self, pos: usize) -> Option<&E>;
     fn condition_at(&self, pos: usize) -> Option<&C>;
+    fn priority_at(&self, pos: usize) -> Option<i32>;
+    fn rate_at(&self, pos: usize) -> Option<f64>;
+    fn duration_at(&self, pos: usize) -> Option<f64>;
+    fn probability_at(&self, pos: usize) -> Option<f64>;
     fn conditions_count(&self) -> usize;
 
     fn conditions<'a>(&'a self) -> impl Iterator<Item = &'a C> where C: 'a;
     fn req_of_types(&self) -> &AHashMap<TypeId, U>;
+
+    /// 本次检查中是否可以跳过第 `pos` 条规则，被跳过的规则视为条件不满足  
+    /// 用于增量检查（见 [`crate::rules::BasicRuleStore::check_on_changes_with`]），默认不跳过
+    fn skip_at(&self, _pos: usize) -> bool {
+        false
+    }
+
+    /// 按规则的概率抽取本步是否执行，概率不小于 `1` 时不消耗随机数  
+    /// 在优先级筛选（[`ExecutableRules::retain_top_priority`]、[`ExecutableRules::retain_unsuppressed`]）之前调用
+    fn draw_at<R>(&self, pos: usize, rng: &mut R) -> bool
+    where R: Rng + ?Sized {
+        self.probability_at(pos).is_none_or(|p| p >= 1.0 || rng.gen::<f64>() < p)
+    }
     
     /// 默认的检查方式可以分离出能并行应用的规则子集（不保证最大）  
     /// 如果不需要提前知道无冲突并行子集（即不需要冲突避免）  
     /// 可以使用 [`IRuleStat::check_on_simple`]
     fn check_on<OS>(&mut self, os: &OS) -> ExecutableRules<OT> 
     where OS: ITaggedStore<OT, PObj<OT, U>> + IUntaggedStore<TypeId, U> + IObjStat<U> {
-        let mut rng = rand::thread_rng();
+        self.check_on_with(os, &mut rand::thread_rng())
+    }
+
+    /// 使用给定的随机数生成器的 [`IRuleStat::check_on`]
+    fn check_on_with<OS, R>(&mut self, os: &OS, rng: &mut R) -> ExecutableRules<OT> 
+    where OS: ITaggedStore<OT, PObj<OT, U>> + IUntaggedStore<TypeId, U> + IObjStat<U>, R: Rng + ?Sized {
         let mut released_amount = vec![U::zero(); os.type_count()];
         let mut used_tgs: AHashMap<OT, (usize, bool)> = AHashMap::new(); 
       
@@ -192,13 +274,13 @@ where OT: Clone + Hash + Eq, U: Scalar, C: ICondition<OT, U> {
             .enumerate()
             .filter_map(|(i, c)| {
                 let mut tag_satisfied = true;
-                let mut amount_satisfied = true;
+                let mut amount_satisfied = !self.skip_at(i);
                 let mut choosed: AHashSet<OT> = AHashSet::new();
                 let mut choosed_each = None;
                 
-                if let Some(uts) = c.untagged() {
+                if let Some(uts) = c.untagged().as_ref().filter(|_| amount_satisfied) {
                     for u in uts {
-                        if  os.amount_of(&u.ty).is_none_or(|a| a < u.amount) {
+                        if  os.amount_of_u(&u.ty).is_none_or(|a| a < u.amount) {
                             amount_satisfied = false;
                             break;
                         }
@@ -221,14 +303,7 @@ where OT: Clone + Hash + Eq, U: Scalar, C: ICondition<OT, U> {
                                     choosed.insert(tg.clone());
                                 },
                                 TaggedPresenceInfo::RandTags((ty, c)) => {
-                                    let choosed_new = os.iter()// todo: 从没重复的tag中选择 -ok
-                                        .filter_map(|o| {
-                                            if o.obj_type() == *ty && !choosed.contains(o.obj_tag()) {
-                                                Some(o.obj_tag().clone())
-                                            } else {
-                                                None
-                                            }
-                                        }).choose_multiple(&mut rng, *c);
+                                    let choosed_new = choose_tagged(os, ty, *c, &choosed, t.select.as_ref(), rng); // todo: 从没重复的tag中选择 -ok
                                     if choosed_new.len() != *c {
                                         tag_satisfied = false;
                                         break;
@@ -247,7 +322,7 @@ where OT: Clone + Hash + Eq, U: Scalar, C: ICondition<OT, U> {
                     }
                 }
 
-                if tag_satisfied && amount_satisfied {
+                if tag_satisfied && amount_satisfied && self.draw_at(i, rng) { // 没有抽中的规则与不满足条件的规则相同
                     let einfo = ExecutableInfo { 
                         rule_index: i, 
                         rand_tags: choosed_each,
@@ -286,13 +361,13 @@ where OT: Clone + Hash + Eq, U: Scalar, C: ICondition<OT, U> {
                 }
             }).collect::<Vec<_>>();
 
-        let conflict_tys = os.amounts()// 计算存在竞争的untagged对象类型
+        let conflict_tys = os.amounts_u()// 计算存在竞争的untagged对象类型
             .zip(released_amount.iter())
             .enumerate()
             .filter_map(|(i, (a, r))| {
                 let tid = os.tid_at(i).unwrap();
                 if let Some(req) = self.req_of_types().get(tid) {
-                    if *req > *a + *r { 
+                    if *req > a + *r { 
                         return Some(*tid);
                     }
                     return None;
@@ -334,18 +409,22 @@ where OT: Clone + Hash + Eq, U: Scalar, C: ICondition<OT, U> {
     
     fn check_on_simple<OS>(&mut self, os: &OS) -> ExecutableRules<OT> 
     where OS: ITaggedStore<OT, PObj<OT, U>> + IUntaggedStore<TypeId, U> + IObjStat<U> {
-        let mut rng = rand::thread_rng();
+        self.check_on_simple_with(os, &mut rand::thread_rng())
+    }
+
+    fn check_on_simple_with<OS, R>(&mut self, os: &OS, rng: &mut R) -> ExecutableRules<OT> 
+    where OS: ITaggedStore<OT, PObj<OT, U>> + IUntaggedStore<TypeId, U> + IObjStat<U>, R: Rng + ?Sized {
         let conflict_executable = self.conditions() 
             .enumerate()
             .filter_map(|(i, c)| {
                 let mut tag_satisfied = true;
-                let mut amount_satisfied = true;
+                let mut amount_satisfied = !self.skip_at(i);
                 let mut choosed: AHashSet<OT> = AHashSet::new();
                 let mut choosed_each = None;
                 
-                if let Some(uts) = c.untagged() {
+                if let Some(uts) = c.untagged().as_ref().filter(|_| amount_satisfied) {
                     for u in uts {
-                        if  os.amount_of(&u.ty).is_none_or(|a| a < u.amount) {
+                        if  os.amount_of_u(&u.ty).is_none_or(|a| a < u.amount) {
                             amount_satisfied = false;
                             break;
                         }
@@ -368,14 +447,7 @@ where OT: Clone + Hash + Eq, U: Scalar, C: ICondition<OT, U> {
                                     choosed.insert(tg.clone());
                                 },
                                 TaggedPresenceInfo::RandTags((ty, c)) => {
-                                    let choosed_new = os.iter()// todo: 从没重复的tag中选择 -ok
-                                        .filter_map(|o| {
-                                            if o.obj_type() == *ty && !choosed.contains(o.obj_tag()) {
-                                                Some(o.obj_tag().clone())
-                                            } else {
-                                                None
-                                            }
-                                        }).choose_multiple(&mut rng, *c);
+                                    let choosed_new = choose_tagged(os, ty, *c, &choosed, t.select.as_ref(), rng); // todo: 从没重复的tag中选择 -ok
                                     if choosed_new.len() != *c {
                                         tag_satisfied = false;
                                         break;
@@ -394,7 +466,7 @@ where OT: Clone + Hash + Eq, U: Scalar, C: ICondition<OT, U> {
                     }
                 }
 
-                if tag_satisfied && amount_satisfied {
+                if tag_satisfied && amount_satisfied && self.draw_at(i, rng) { // 没有抽中的规则与不满足条件的规则相同
                     let einfo = ExecutableInfo { 
                         rule_index: i, 
                         rand_tags: choosed_each,
@@ -415,7 +487,11 @@ where OT: Clone + Hash + Eq, U: Scalar, C: ICondition<OT, U> {
 
     fn check_on_tagged<OS>(&mut self, os: &OS) -> ExecutableRules<OT> 
     where OS: ITaggedStore<OT, PObj<OT, U>> + IObjStat<U> {
-        let mut rng = rand::thread_rng();
+        self.check_on_tagged_with(os, &mut rand::thread_rng())
+    }
+
+    fn check_on_tagged_with<OS, R>(&mut self, os: &OS, rng: &mut R) -> ExecutableRules<OT> 
+    where OS: ITaggedStore<OT, PObj<OT, U>> + IObjStat<U>, R: Rng + ?Sized {
         let mut used_tgs: AHashMap<OT, (usize, bool)> = AHashMap::new(); 
       
         let mut conflict_executable = VecDeque::<ExecutableInfo<OT>>::new();
@@ -425,13 +501,13 @@ where OT: Clone + Hash + Eq, U: Scalar, C: ICondition<OT, U> {
         let mut executable = self.conditions() 
             .enumerate()
             .filter_map(|(i, c)| {
-                let mut tag_satisfied = true;
+                let mut tag_satisfied = !self.skip_at(i);
                 let mut choosed: AHashSet<OT> = AHashSet::new();
                 let mut choosed_each = None;
            
                 let (mut tag_set, mut tag_rand) = (None, None);
                 
-                if let Some(tgs) = c.tagged() {
+                if let Some(tgs) = c.tagged().as_ref().filter(|_| tag_satisfied) {
                     for t in tgs {
                         match &t.info {
                             TaggedPresenceInfo::OfTag(tg) => {
@@ -445,15 +521,7 @@ where OT: Clone + Hash + Eq, U: Scalar, C: ICondition<OT, U> {
                                 choosed.insert(tg.clone());
                             },
                             TaggedPresenceInfo::RandTags((ty, c)) => {
-                                let choosed_new = os.iter()// todo: 从没重复的tag中选择 -ok
-                                    .filter_map(|o| {
-                                        if o.obj_type() == *ty && !choosed.contains(o.obj_tag()) {
-                                            Some(o.obj_tag().clone())
-                                        } else {
-                                            None
-                                        }
-                                    })
-                                    .choose_multiple(&mut rng, *c);
+                                let choosed_new = choose_tagged(os, ty, *c, &choosed, t.select.as_ref(), rng); // todo: 从没重复的tag中选择 -ok
                                 if choosed_new.len() != *c {
                                     tag_satisfied = false;
                                     break;
@@ -471,7 +539,7 @@ where OT: Clone + Hash + Eq, U: Scalar, C: ICondition<OT, U> {
                     }
                 }
             
-                if tag_satisfied  {
+                if tag_satisfied && self.draw_at(i, rng) {
                     let einfo = ExecutableInfo { 
                         rule_index: i, 
                         rand_tags: choosed_each,
@@ -527,6 +595,11 @@ where OT: Clone + Hash + Eq, U: Scalar, C: ICondition<OT, U> {
 
     fn check_on_untagged<OS>(&self, os: &OS) -> ExecutableRules<OT>
     where OS: IUntaggedStore<OT, U> + IObjStat<U> {
+        self.check_on_untagged_with(os, &mut rand::thread_rng())
+    }
+
+    fn check_on_untagged_with<OS, R>(&self, os: &OS, rng: &mut R) -> ExecutableRules<OT>
+    where OS: IUntaggedStore<OT, U> + IObjStat<U>, R: Rng + ?Sized {
        
         let mut released_amount = vec![U::zero(); os.type_count()];
        
@@ -535,18 +608,18 @@ where OT: Clone + Hash + Eq, U: Scalar, C: ICondition<OT, U> {
         let mut executable = self.conditions() 
             .enumerate()
             .filter_map(|(i, c)| {
-                let mut amount_satisfied = true;
+                let mut amount_satisfied = !self.skip_at(i);
            
-                if let Some(uts) = c.untagged() {
+                if let Some(uts) = c.untagged().as_ref().filter(|_| amount_satisfied) {
                     for u in uts {
-                        if  os.amount_of(&u.ty).is_none_or(|a| a < u.amount) {
+                        if  os.amount_of_u(&u.ty).is_none_or(|a| a < u.amount) {
                             amount_satisfied = false;
                             break;
                         }
                     }
                 }
 
-                if amount_satisfied {
+                if amount_satisfied && self.draw_at(i, rng) {
                     let einfo = ExecutableInfo { 
                         rule_index: i, 
                         rand_tags: None,
@@ -566,13 +639,13 @@ where OT: Clone + Hash + Eq, U: Scalar, C: ICondition<OT, U> {
                 }
             }).collect::<Vec<_>>();
 
-        let conflict_tys = os.amounts()// 计算存在竞争的untagged对象类型
+        let conflict_tys = os.amounts_u()// 计算存在竞争的untagged对象类型
             .zip(released_amount.iter())
             .enumerate()
             .filter_map(|(i, (a, r))| {
                 let tid = os.tid_at(i).unwrap();
                 if let Some(req) = self.req_of_types().get(tid) {
-                    if *req > *a + *r { 
+                    if *req > a + *r { 
                         return Some(*tid);
                     }
                     return None;
@@ -608,14 +681,19 @@ where OT: Clone + Hash + Eq, U: Scalar, C: ICondition<OT, U> {
             conflict_executable: if conflict_executable.is_empty() { None } else { Some(conflict_executable) },
         }
     }
-    /// 动态执行 `rule_indexes` 中的规则，如果 `rule_indexes` 为 [`None`] 则尝试执行所有规则
+    /// 动态执行 `rule_indexes` 中的规则，如果 `rule_indexes` 为 [`None`] 则尝试执行按概率抽中的所有规则
     /// todo: 在分配rand时出现问题 -ok， 原因：在迭代器上enumerate 然而 迭代器中Condition并非顺序
-    fn dynamic_execute<OS, F>(&mut self, os: &mut OS, rules_info: Option<VecDeque<ExecutableInfo<OT>>>, mut handler: F)
+    fn dynamic_execute<OS, F>(&mut self, os: &mut OS, rules_info: Option<VecDeque<ExecutableInfo<OT>>>, handler: F)
     where OS: ITaggedStore<OT, PObj<OT, U>> + IUntaggedStore<TypeId, U> + IObjStat<U>, F: FnMut(&mut OS, Option<T>, Option<&E>, DynamicRequest<OT>) {
-        let mut rng = rand::thread_rng();
+        self.dynamic_execute_with(os, rules_info, &mut rand::thread_rng(), handler)
+    }
+
+    fn dynamic_execute_with<OS, F, R>(&mut self, os: &mut OS, rules_info: Option<VecDeque<ExecutableInfo<OT>>>, rng: &mut R, mut handler: F)
+    where OS: ITaggedStore<OT, PObj<OT, U>> + IUntaggedStore<TypeId, U> + IObjStat<U>, F: FnMut(&mut OS, Option<T>, Option<&E>, DynamicRequest<OT>), R: Rng + ?Sized {
 
         let mut rinfo = rules_info.unwrap_or({
             let mut tmp = (0..self.conditions_count())
+            .filter(|i| self.draw_at(*i, rng))
             .map(|i| ExecutableInfo {
                     rule_index: i, 
                     rand_tags: None,
@@ -623,7 +701,7 @@ where OT: Clone + Hash + Eq, U: Scalar, C: ICondition<OT, U> {
                     skip_take: false
                 })
             .collect::<VecDeque<_>>();
-            tmp.make_contiguous().shuffle(&mut rng);
+            tmp.make_contiguous().shuffle(rng);
             tmp
         });
     
@@ -636,7 +714,7 @@ where OT: Clone + Hash + Eq, U: Scalar, C: ICondition<OT, U> {
            
             if let Some(uts) = c.untagged() {
                 for u in uts {
-                    if os.amount_of(&u.ty).is_none_or(|a| a < u.amount ) {
+                    if os.amount_of_u(&u.ty).is_none_or(|a| a < u.amount ) {
                         return;
                     }
                 }
@@ -660,15 +738,7 @@ where OT: Clone + Hash + Eq, U: Scalar, C: ICondition<OT, U> {
                                     continue;
                                 }
                             }
-                            let choosed_new = os.iter()
-                            .filter_map(|o| {
-                                if o.obj_type() == *ty && !choosed.contains(o.obj_tag()) {
-                                    Some(o.obj_tag().clone())
-                                } else {
-                                    None
-                                }
-                            })
-                            .choose_multiple(&mut rng, *c);
+                            let choosed_new = choose_tagged(os, ty, *c, &choosed, t.select.as_ref(), rng);
 
                             if choosed_new.len() != *c {
                                 return;
@@ -712,8 +782,26 @@ pub trait IMem: IObj {
         }
     }
 
+    /// 运行到模拟时间不小于 `t`，中途停止时返回停止的状态  
+    /// 某一步没有推进模拟时间时返回 [`EmuStatus::Stopped`]，否则时长为零的规则会使其一直停留在同一时间
+    fn run_until_time(&mut self, t: f64) -> EmuStatus {
+        while self.time() < t {
+            let before = self.time();
+            let loop_state = self.evolve();
+            if loop_state != EmuStatus::Continue {
+                return loop_state;
+            }
+            if self.time() <= before {
+                return EmuStatus::Stopped;
+            }
+        }
+        EmuStatus::Continue
+    }
+
     fn ready(&self) -> bool;
     fn evolve(&mut self) -> EmuStatus;
+    /// 模拟时间
+    fn time(&self) -> f64;
 }
 
 pub trait EffectHandler<Effect> {
@@ -743,12 +831,54 @@ OT: Send + Sync, U: Send + Sync {
     IncreaseObjUntagged((ObjType, U)),
     DecreaseObjUntagged((ObjType, U)),
     RemoveObjUntagged(ObjType),
+    SendObjUntagged((ObjType, U, MemTarget)),
+    /// 经过给定的模拟时间后在本膜中出现创建的对象
+    ScheduleObj((ObjCrateFn<OT, U>, f64)),
+    /// 经过给定的模拟时间后在本膜中增加 untagged 对象
+    ScheduleUntagged((ObjType, U, f64)),
     DissolveMem,
     Pause,
     Stop
 }
 
-#[derive(Debug, PartialEq)]
+/// 影响中一个操作产生和消耗的对象类型的声明  
+/// 操作只是函数指针，无法从中得知会创建哪些对象，因此由规则的作者声明，供分析工具使用
+#[derive(Debug, Clone, Default)]
+pub struct EffectSignature {
+    pub produces: Vec<ObjType>,
+    pub consumes: Vec<ObjType>,
+    /// 操作可能产生任意类型的对象（如转发收到的对象），调试构建中不检查它的产物
+    pub any_product: bool
+}
+
+impl EffectSignature {
+    pub fn producing(ty: ObjType) -> Self {
+        Self { produces: vec![ty], consumes: Vec::new(), any_product: false }
+    }
+
+    pub fn consuming(ty: ObjType) -> Self {
+        Self { produces: Vec::new(), consumes: vec![ty], any_product: false }
+    }
+
+    pub fn is_declared(&self) -> bool {
+        self.any_product || !self.produces.is_empty() || !self.consumes.is_empty()
+    }
+
+    pub fn declares_product(&self, tid: &TypeId) -> bool {
+        self.any_product || self.produces.iter().any(|t| t.tid == *tid)
+    }
+}
+
+/// 对象发送的目标膜，由膜层级解释  
+/// `In` 中为子膜的标签（label）而不是子膜的 tag，存在多个同标签子膜时由膜层级选择其一
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum MemTarget {
+    Here,
+    Out,
+    In(usize)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum EmuStatus {
     Pause,
     Continue,
@@ -756,6 +886,52 @@ pub enum EmuStatus {
     EmuError
 }
 
+/// 从 `os` 中不在 `choosed` 里的 `ty` 类型对象中随机选择 `n` 个，可选对象不足时返回的数量少于 `n`  
+/// 给出权重时按权重不放回地选择（以 `u^(1/w)` 为键取最大的 `n` 个）  
+/// `os` 有按类型的索引（见 [`ITaggedStore::tags_of`]）时只访问该类型的对象，没有筛选条件和权重时只需 `O(n + |choosed|)`
+pub fn choose_tagged<OT, U, OS, R>(os: &OS, ty: &ObjType, n: usize, choosed: &AHashSet<OT>, select: Option<&ObjSelect<OT, U>>, rng: &mut R) -> Vec<OT>
+where OT: Clone + Hash + Eq, U: Scalar, OS: ITaggedStore<OT, PObj<OT, U>>, R: Rng + ?Sized {
+    let Some(tags) = os.tags_of(&ty.tid) else {
+        let candidates = os.iter()
+            .filter(|o| o.obj_type() == *ty && !choosed.contains(o.obj_tag()));
+        return choose_from(candidates, n, select, rng);
+    };
+    if select.is_none() {
+        // 多取 |choosed| 个再去掉已选择的，结果仍是均匀的
+        let k = (n + choosed.len()).min(tags.len());
+        return rand::seq::index::sample(rng, tags.len(), k)
+            .into_iter()
+            .map(|i| &tags[i])
+            .filter(|t| !choosed.contains(*t))
+            .take(n)
+            .cloned()
+            .collect();
+    }
+    let candidates = tags.iter()
+        .filter(|t| !choosed.contains(*t))
+        .filter_map(|t| os.get(t));
+    choose_from(candidates, n, select, rng)
+}
+
+fn choose_from<'a, OT, U, I, R>(candidates: I, n: usize, select: Option<&ObjSelect<OT, U>>, rng: &mut R) -> Vec<OT>
+where OT: Clone + Hash + Eq + 'a, U: Scalar + 'a, I: Iterator<Item = &'a PObj<OT, U>>, R: Rng + ?Sized {
+    let candidates = candidates.filter(|o| select.is_none_or(|s| s.accepts(&***o)));
+    match select.and_then(|s| s.weight) {
+        Some(weight) => {
+            let mut keyed = candidates
+                .filter_map(|o| {
+                    let w = weight(&**o);
+                    (w > 0.0).then(|| (rng.gen_range(f64::EPSILON..1.0f64).powf(1.0 / w), o.obj_tag().clone()))
+                })
+                .collect::<Vec<_>>();
+            keyed.sort_by(|a, b| b.0.total_cmp(&a.0));
+            keyed.truncate(n);
+            keyed.into_iter().map(|(_, t)| t).collect()
+        },
+        None => candidates.map(|o| o.obj_tag().clone()).choose_multiple(rng, n),
+    }
+}
+
 #[derive(Debug, Clone)] 
 pub enum TaggedPresenceInfo<Tag> {
     OfTag(Tag),
@@ -767,24 +943,49 @@ pub enum UseBy {
     None, Tag, Ref, Take
 }
 
+/// 随机选择 tagged 对象时的筛选条件与权重
+#[derive(Debug, Clone)]
+pub struct ObjSelect<T, U> {
+    /// 只从满足条件的对象中选择
+    pub filter: Option<ObjPredicate<T, U>>,
+    /// 按权重不放回地选择，权重不大于 `0` 的对象不会被选中
+    pub weight: Option<ObjWeight<T, U>>
+}
+
+impl<T, U> Default for ObjSelect<T, U> {
+    fn default() -> Self {
+        Self { filter: None, weight: None }
+    }
+}
+
+impl<T, U> ObjSelect<T, U> {
+    pub fn accepts(&self, o: &dyn IObj<Tag = T, Unit = U>) -> bool {
+        self.filter.is_none_or(|f| f(o))
+    }
+}
+
 #[derive(Debug, Clone)] // todo: 标记 take -ok
-pub struct TaggedPresence<Tag> {
+pub struct TaggedPresence<Tag, U = u32> {
     pub info: TaggedPresenceInfo<Tag>,
-    pub use_by: UseBy
+    pub use_by: UseBy,
+    /// 只用于 [`TaggedPresenceInfo::RandTags`]
+    pub select: Option<ObjSelect<Tag, U>>
 }
 
-impl<Tag> TaggedPresence<Tag> {
+impl<Tag, U> TaggedPresence<Tag, U> {
     pub fn of_tag(tag: Tag, use_by: UseBy) -> Self {
         Self {
             info: TaggedPresenceInfo::OfTag(tag),
-            use_by
+            use_by,
+            select: None
         }
     }
 
     pub fn rand_tags(tag_info: (ObjType, usize), use_by: UseBy) -> Self {
         Self {
             info: TaggedPresenceInfo::RandTags(tag_info),
-            use_by
+            use_by,
+            select: None
         }
     }
 }
@@ -799,7 +1000,9 @@ pub struct UntaggedPresence<Unit: Scalar> {
 #[derive(Debug, Clone)]
 pub struct ObjType {
     pub group: &'static TypeGroup,
-    pub tid: TypeId
+    pub tid: TypeId,
+    /// 类型名，只用于显示
+    pub name: &'static str
 }
 
 impl PartialEq for ObjType {
@@ -810,14 +1013,22 @@ impl PartialEq for ObjType {
 
 impl ObjType {
     pub fn new<T: IObj + ?Sized + 'static>(group: &'static TypeGroup) ->Self {
-        Self { group, tid: TypeId::of::<T>() }
+        Self { group, tid: TypeId::of::<T>(), name: std::any::type_name::<T>() }
     }
 
     pub fn default_group<T: IObj + ?Sized + 'static>() -> Self {
-        Self { group: &crate::core::DEFAULT_GROUP, tid: TypeId::of::<T>() }
+        Self { group: &crate::core::DEFAULT_GROUP, tid: TypeId::of::<T>(), name: std::any::type_name::<T>() }
+    }
+
+    /// 去掉路径与泛型参数的类型名
+    pub fn short_name(&self) -> &'static str {
+        let base = self.name.split('<').next().unwrap_or(self.name);
+        base.rsplit("::").next().unwrap_or(base)
     }
 }
 
+/// 可按位置访问的哈希表，插入的元素位于末尾，更新不改变位置  
+/// 移除使用交换删除（见 [`IndexMap::remove_full`]），因此移除后元素的顺序不再是插入的顺序
 #[derive(Debug)]
 pub struct IndexMap<K, V>
 where K: Hash + Eq + Clone, V: Send + Sync {
@@ -895,22 +1106,25 @@ where K: Hash + Eq + Clone, V: Send + Sync {
     }
 
     pub fn remove(&mut self, key: &K) ->  Option<V> {
-        if let Some(pos) = self.map.remove(key) {
-            let ret = self.data_v.remove(pos).1;
-            for i in pos..self.data_v.len() {
-                let k = &self.data_v[i].0;
-                if let Some(old_p) = self.map.get_mut(k) {
-                    *old_p -= 1;
-                }
+        self.remove_full(key).map(|(_, v)| v)
+    }
+
+    /// 移除 `key` 并返回它原来的位置，`O(1)`  
+    /// 最后一个元素移到被移除的位置，其余元素的位置不变，与本表按位置对应的数据需要做相同的交换删除
+    pub fn remove_full(&mut self, key: &K) -> Option<(usize, V)> {
+        let pos = self.map.remove(key)?;
+        let ret = self.data_v.swap_remove(pos).1;
+        if let Some((moved, _)) = self.data_v.get(pos) {
+            if let Some(p) = self.map.get_mut(moved) {
+                *p = pos;
             }
-            return Some(ret);
         }
-        None
+        Some((pos, ret))
     }
 
+    /// 按顺序依次移除 `keys`，不存在的返回 [`None`]
     pub fn remove_batch(&mut self, keys: &[K]) -> Vec<Option<(K, V)>> {
-        let indexes = keys.iter().map(|k| self.map.get(k).cloned().unwrap_or(self.data_v.len())).collect::<Vec<_>>();
-        helpers::vec_batch_remove(&mut self.data_v, &indexes)
+        keys.iter().map(|k| self.remove(k).map(|v| (k.clone(), v))).collect()
     }
 
     pub fn len(&self) -> usize {
@@ -922,6 +1136,77 @@ where K: Hash + Eq + Clone, V: Send + Sync {
     }
 }
 
+/// 对象存储中发生了变化的类型与 tag，用于增量地检查规则  
+/// tagged 对象的变化同时记录它的 tag 和类型，无法得知具体变化时（例如可变地遍历所有对象）记为全部变化
+#[derive(Debug, Clone)]
+pub struct ObjChanges<T> {
+    types: AHashSet<TypeId>,
+    tags: AHashSet<T>,
+    all: bool
+}
+
+impl<T> Default for ObjChanges<T> {
+    fn default() -> Self {
+        Self { types: AHashSet::new(), tags: AHashSet::new(), all: false }
+    }
+}
+
+impl<T> ObjChanges<T>
+where T: Hash + Eq {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_type(&mut self, ty: TypeId) {
+        if !self.all {
+            self.types.insert(ty);
+        }
+    }
+
+    pub fn record_tag(&mut self, tag: T, ty: TypeId) {
+        if !self.all {
+            self.tags.insert(tag);
+            self.types.insert(ty);
+        }
+    }
+
+    pub fn record_all(&mut self) {
+        self.all = true;
+        self.types.clear();
+        self.tags.clear();
+    }
+
+    pub fn clear(&mut self) {
+        self.all = false;
+        self.types.clear();
+        self.tags.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.all && self.types.is_empty() && self.tags.is_empty()
+    }
+
+    pub fn is_all(&self) -> bool {
+        self.all
+    }
+
+    pub fn touches_type(&self, ty: &TypeId) -> bool {
+        self.all || self.types.contains(ty)
+    }
+
+    pub fn touches_tag(&self, tag: &T) -> bool {
+        self.all || self.tags.contains(tag)
+    }
+
+    pub fn types(&self) -> impl Iterator<Item = &TypeId> {
+        self.types.iter()
+    }
+
+    pub fn tags(&self) -> impl Iterator<Item = &T> {
+        self.tags.iter()
+    }
+}
+
 #[derive(Debug)]
 pub struct ExecutableInfo<T> {
     pub rule_index: usize,
@@ -936,6 +1221,52 @@ pub struct ExecutableRules<T> {
     pub conflict_executable: Option<VecDeque<ExecutableInfo<T>>>,
 }
 
+impl<T> ExecutableRules<T> {
+    pub fn is_empty(&self) -> bool {
+        self.parallel_executable.is_none() && self.conflict_executable.is_none()
+    }
+
+    /// 只保留优先级最高的可执行规则  
+    /// `priority_of` 由规则下标得到优先级，被移除的规则在本步中不会执行
+    pub fn retain_top_priority<F>(&mut self, priority_of: F)
+    where F: Fn(usize) -> i32 {
+        let top = self.parallel_executable.iter()
+            .chain(self.conflict_executable.iter())
+            .flatten()
+            .map(|e| priority_of(e.rule_index))
+            .max();
+        if let Some(top) = top {
+            for q in [&mut self.parallel_executable, &mut self.conflict_executable] {
+                if let Some(v) = q {
+                    v.retain(|e| priority_of(e.rule_index) == top);
+                }
+                if q.as_ref().is_some_and(|v| v.is_empty()) {
+                    *q = None;
+                }
+            }
+        }
+    }
+
+    /// 按规则之间的优先关系（强优先）筛选：有优先于它的规则可执行时，规则在本步中不会执行
+    /// `outranks(i, j)` 表示下标为 `i` 的规则优先于下标为 `j` 的规则
+    pub fn retain_unsuppressed<F>(&mut self, outranks: F)
+    where F: Fn(usize, usize) -> bool {
+        let all = self.parallel_executable.iter()
+            .chain(self.conflict_executable.iter())
+            .flatten()
+            .map(|e| e.rule_index)
+            .collect::<Vec<_>>();
+        for q in [&mut self.parallel_executable, &mut self.conflict_executable] {
+            if let Some(v) = q {
+                v.retain(|e| !all.iter().any(|i| outranks(*i, e.rule_index)));
+            }
+            if q.as_ref().is_some_and(|v| v.is_empty()) {
+                *q = None;
+            }
+        }
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct RequestTyped<T> {
    pub set: Option<Vec<T>>,
@@ -996,6 +1327,8 @@ pub struct RequestedObj<'a, T, U = u32> {
     pub refr: Option<RequestTyped<&'a PObj<T, U>>>,
     pub take: Option<RequestTyped<PObj<T, U>>>,
     pub tag: Option<RequestTyped<T>>,
+    /// 膜的 tag 生成器分出的通道，用于给新建的对象分配 tag
+    pub ids: helpers::TagLane
 }
 
 impl<'a, T, U> RequestedObj<'a, T, U> {
@@ -1005,10 +1338,25 @@ impl<'a, T, U> RequestedObj<'a, T, U> {
         tag: Option<RequestTyped<T>>,
         ) -> Self {
         Self {
-            refr, take, tag
+            refr, take, tag, ids: helpers::TagLane::default()
         }
     }
 
+    pub fn with_ids(mut self, ids: helpers::TagLane) -> Self {
+        self.ids = ids;
+        self
+    }
+
+    /// 由膜的 tag 生成器生成新的 tag
+    pub fn next_tag<N: TryFrom<u64>>(&mut self) -> N {
+        self.ids.next_tag()
+    }
+
+    /// 由膜的 tag 生成器生成 uuid，膜设置了种子时结果可以复现
+    pub fn next_uuid(&mut self) -> UuidTag {
+        self.ids.next_uuid()
+    }
+
     pub fn set_ref_all(&self) -> Option<&Vec<&PObj<T, U>>> {
         self.refr.as_ref().and_then(|r| r.set.as_ref() )
     }
@@ -1,6 +1,10 @@
 // Copyright 2024 Junshuang Hu
 use std::fmt;
 
+use log::{log, Level};
+
+use crate::lib_info::log_target;
+
 pub struct MemError<T> {
     pub info: String,
     pub data: Option<T>
@@ -43,4 +47,34 @@ impl<T> MemError<T> {
             data: None
         }
     }
-}
\ No newline at end of file
+}
+
+/// 对象数量的增减错误，出错时数量不会被修改
+#[derive(Debug, Clone, PartialEq)]
+pub enum AmountError<Ty, U> {
+    /// 没有该类型的 untagged 对象
+    Missing(Ty),
+    /// 减少的数量多于现有的数量：untagged 对象的减少，或移除 tagged 对象时的 tagged 数量
+    Underflow { ty: Ty, present: U, requested: U },
+    /// 增加 untagged 数量或加入 tagged 对象后该类型的总数量超出数量类型的范围
+    Overflow { ty: Ty, present: U, added: U }
+}
+
+impl<Ty: fmt::Debug, U: fmt::Display> fmt::Display for AmountError<Ty, U> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AmountError::Missing(ty) => write!(f, "no untagged objs of type {:?}", ty),
+            AmountError::Underflow { ty, present, requested } => 
+                write!(f, "trying to decrease untagged objs of type {:?} by {} but only {} present", ty, requested, present),
+            AmountError::Overflow { ty, present, added } => 
+                write!(f, "increasing objs of type {:?} by {} overflows the amount {}", ty, added, present),
+        }
+    }
+}
+
+impl<Ty: fmt::Debug, U: fmt::Display> AmountError<Ty, U> {
+    /// 记录到异常日志，用于无法把错误交给调用者的地方
+    pub fn log(&self) {
+        log!(target: log_target::Mem::Exceptions.into(), Level::Error, "{}.", self);
+    }
+}
@@ -0,0 +1,3 @@
+// Copyright 2024 Junshuang Hu
+pub mod pli;
+pub mod dot;
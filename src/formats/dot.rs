@@ -0,0 +1,220 @@
+// Copyright 2024 Junshuang Hu
+//! Graphviz DOT 导出
+//!
+//! - [`mem_tree`]：膜层级的树结构，通道连接的膜之间以虚线相连
+//! - [`channel_graph`]：独立运行的膜之间的通道连接
+//! - [`rule_graph`]：规则与对象类型的二部图，边来自规则的条件与影响
+
+use std::any::TypeId;
+use std::fmt::{Debug, Write};
+use std::hash::Hash;
+
+use ahash::AHashMap;
+use krnl::scalar::Scalar;
+
+use crate::core::{ICondition, IObj, IRuleEffect, IRuleStat, ITaggedStore, MemTarget, ObjType, OperationEffect, PObj, TaggedPresenceInfo, TypeGroup, UseBy};
+use crate::mems::basic::BasicMem;
+use crate::mems::hierarchy::MemHierarchy;
+use crate::objs::com::{ObjChannel, RChannel, SChannel};
+use crate::objs::BasicObjStore;
+use crate::rules::BasicRuleStore;
+
+/// 对象类型在图中显示的名字，未登记的类型使用 [`ObjType::short_name`]
+#[derive(Debug, Default, Clone)]
+pub struct TypeNames {
+    names: AHashMap<TypeId, String>
+}
+
+impl TypeNames {
+    pub fn new() -> Self {
+        Self { names: AHashMap::new() }
+    }
+
+    pub fn with(mut self, ty: &ObjType, name: &str) -> Self {
+        self.insert(ty, name);
+        self
+    }
+
+    pub fn insert(&mut self, ty: &ObjType, name: &str) {
+        self.names.insert(ty.tid, name.to_string());
+    }
+
+    pub fn name_of(&self, ty: &ObjType) -> String {
+        self.names.get(&ty.tid).cloned().unwrap_or_else(|| ty.short_name().to_string())
+    }
+}
+
+fn esc(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// 膜中通道端点的 `(连接标识, tag)`
+fn channel_ends<OT, U>(os: &BasicObjStore<OT, U>) -> Vec<(usize, String)>
+where OT: Clone + Hash + Eq + Send + Sync + Debug + 'static, U: Scalar {
+    os.iter()
+        .filter(|o| *o.obj_type().group == TypeGroup::Com)
+        .filter_map(|o| {
+            let a = o.as_any();
+            a.downcast_ref::<ObjChannel<OT, U>>().map(|c| c.link())
+                .or_else(|| a.downcast_ref::<SChannel<OT, U, PObj<OT, U>>>().map(|c| c.link()))
+                .or_else(|| a.downcast_ref::<RChannel<OT, U, PObj<OT, U>>>().map(|c| c.link()))
+                .map(|l| (l, format!("{:?}", o.obj_tag())))
+        })
+        .collect()
+}
+
+/// 为共享同一连接的端点所在的膜添加虚线边，`ends` 中为 `(膜节点号, 连接标识, tag)`
+fn write_channel_edges(out: &mut String, mut ends: Vec<(usize, usize, String)>) {
+    ends.sort_by_key(|e| (e.1, e.0));
+    for w in ends.windows(2) {
+        if w[0].1 == w[1].1 {
+            let _ = writeln!(
+                out, "    m{} -> m{} [dir=none, style=dashed, label=\"{} ~ {}\"];",
+                w[0].0, w[1].0, esc(&w[0].2), esc(&w[1].2)
+            );
+        }
+    }
+}
+
+/// 膜层级的树，溶解的膜不会出现，`label_name` 给出膜标签的显示名
+pub fn mem_tree<T, OT, RT, U, F>(h: &MemHierarchy<T, OT, RT, U>, label_name: F) -> String
+where
+T: Clone + Hash + Eq + Debug + 'static,
+OT: Clone + Hash + Eq + Send + Sync + Debug + 'static,
+RT: Clone + Hash + Eq + Send + Sync + Debug + 'static,
+U: Scalar,
+F: Fn(usize) -> String {
+    let mut out = String::from("digraph membranes {\n    node [shape=box];\n");
+    let mut ends = Vec::new();
+    for i in h.alive() {
+        let n = h.node(i).unwrap();
+        let _ = writeln!(
+            out, "    m{} [label=\"{}\\n'{}\"];",
+            i, esc(&format!("{:?}", n.mem.obj_tag())), esc(&label_name(n.label()))
+        );
+        ends.extend(channel_ends(n.mem.objs()).into_iter().map(|(l, t)| (i, l, t)));
+    }
+    for i in h.alive() {
+        for c in h.node(i).unwrap().children() {
+            let _ = writeln!(out, "    m{i} -> m{c};");
+        }
+    }
+    write_channel_edges(&mut out, ends);
+    out.push_str("}\n");
+    out
+}
+
+/// 独立运行的膜之间的通道连接，节点按 `mems` 中的顺序编号
+pub fn channel_graph<T, OT, RT, U>(mems: &[&BasicMem<T, OT, RT, U>]) -> String
+where
+T: Clone + Hash + Eq + Debug + 'static,
+OT: Clone + Hash + Eq + Send + Sync + Debug + 'static,
+RT: Clone + Hash + Eq + Send + Sync + Debug + 'static,
+U: Scalar {
+    let mut out = String::from("digraph channels {\n    node [shape=box];\n");
+    let mut ends = Vec::new();
+    for (i, m) in mems.iter().enumerate() {
+        let _ = writeln!(out, "    m{} [label=\"{}\"];", i, esc(&format!("{:?}", m.obj_tag())));
+        ends.extend(channel_ends(m.objs()).into_iter().map(|(l, t)| (i, l, t)));
+    }
+    write_channel_edges(&mut out, ends);
+    out.push_str("}\n");
+    out
+}
+
+/// 规则-对象类型二部图
+/// 条件中的对象指向规则（untagged 为实线，随机选择的 tagged 对象为虚线，指定 tag 的对象为菱形节点），
+/// 规则指向其影响增加（`+`）、减少（`-`）或发送的对象类型，以及声明创建（`new`）或移除（`del`）的 tagged 对象类型
+pub fn rule_graph<RT, OT, U>(rules: &BasicRuleStore<RT, OT, U>, names: &TypeNames) -> String
+where
+RT: Clone + Hash + Eq + Send + Sync + Debug + 'static,
+OT: Clone + Hash + Eq + Send + Sync + Debug + 'static,
+U: Scalar {
+    let mut out = String::from("digraph rules {\n    rankdir=LR;\n");
+    let mut tys: AHashMap<TypeId, usize> = AHashMap::new();
+    let mut tagged: AHashMap<String, usize> = AHashMap::new();
+    let mut edges = String::new();
+
+    let mut ty_node = |out: &mut String, ty: &ObjType| -> usize {
+        let n = tys.len();
+        *tys.entry(ty.tid).or_insert_with(|| {
+            let _ = writeln!(out, "    t{} [shape=ellipse, label=\"{}\"];", n, esc(&names.name_of(ty)));
+            n
+        })
+    };
+
+    for (i, r) in rules.rules().enumerate() {
+        let _ = writeln!(out, "    r{} [shape=box, label=\"{}\"];", i, esc(&format!("{:?}", r.obj_tag())));
+        let Some(c) = rules.condition_at(i) else { continue; };
+        for u in c.untagged().iter().flatten() {
+            let t = ty_node(&mut out, &u.ty);
+            let _ = writeln!(edges, "    t{t} -> r{i} [label=\"{}\"];", u.amount);
+        }
+        for p in c.tagged().iter().flatten() {
+            let style = if p.use_by == UseBy::Take { "bold" } else { "dashed" };
+            match &p.info {
+                TaggedPresenceInfo::OfTag(tg) => {
+                    let name = format!("{tg:?}");
+                    let n = tagged.len();
+                    let o = *tagged.entry(name.clone()).or_insert_with(|| {
+                        let _ = writeln!(out, "    o{} [shape=diamond, label=\"{}\"];", n, esc(&name));
+                        n
+                    });
+                    let _ = writeln!(edges, "    o{o} -> r{i} [style={style}];");
+                },
+                TaggedPresenceInfo::RandTags((ty, n)) => {
+                    let t = ty_node(&mut out, ty);
+                    let _ = writeln!(edges, "    t{t} -> r{i} [style={style}, label=\"{n}\"];");
+                }
+            }
+        }
+        let Some(eff) = rules.effect_at(i) else { continue; };
+        for (k, e) in eff.effects().iter().flatten().enumerate() {
+            // 创建与移除 tagged 对象的操作只能通过声明得知类型
+            if let Some(sig) = eff.signatures().get(k) {
+                let tagged_op = matches!(
+                    e,
+                    OperationEffect::CreateObj(_) | OperationEffect::CreateObjs(_) | OperationEffect::ScheduleObj(_) |
+                    OperationEffect::RemoveObj(_) | OperationEffect::RemoveObjs(_)
+                );
+                if tagged_op {
+                    for ty in sig.produces.iter() {
+                        let t = ty_node(&mut out, ty);
+                        let _ = writeln!(edges, "    r{i} -> t{t} [style=bold, label=\"new\"];");
+                    }
+                    for ty in sig.consumes.iter() {
+                        let t = ty_node(&mut out, ty);
+                        let _ = writeln!(edges, "    r{i} -> t{t} [style=bold, color=red, label=\"del\"];");
+                    }
+                }
+            }
+            match e {
+                OperationEffect::IncreaseObjUntagged((ty, a)) => {
+                    let t = ty_node(&mut out, ty);
+                    let _ = writeln!(edges, "    r{i} -> t{t} [label=\"+{a}\"];");
+                },
+                OperationEffect::DecreaseObjUntagged((ty, a)) => {
+                    let t = ty_node(&mut out, ty);
+                    let _ = writeln!(edges, "    r{i} -> t{t} [color=red, label=\"-{a}\"];");
+                },
+                OperationEffect::SendObjUntagged((ty, a, target)) => {
+                    let t = ty_node(&mut out, ty);
+                    let to = match target {
+                        MemTarget::Here => String::from("here"),
+                        MemTarget::Out => String::from("out"),
+                        MemTarget::In(l) => format!("in {l}"),
+                    };
+                    let _ = writeln!(edges, "    r{i} -> t{t} [style=dashed, label=\"+{a} ({to})\"];");
+                },
+                OperationEffect::ScheduleUntagged((ty, a, d)) => {
+                    let t = ty_node(&mut out, ty);
+                    let _ = writeln!(edges, "    r{i} -> t{t} [style=dotted, label=\"+{a} (after {d})\"];");
+                },
+                _ => {}
+            }
+        }
+    }
+    out.push_str(&edges);
+    out.push_str("}\n");
+    out
+}
@@ -0,0 +1,682 @@
+// Copyright 2024 Junshuang Hu
+//! P-Lingua 导入（类细胞膜系统子集）
+//!
+//! 支持的语法：
+//! ```text
+//! @model<transition>                      // 可选，模型名不做检查
+//! def main()
+//! {
+//!     @mu = [[]'2 []'3]'1;                // 膜结构，标签可以是标识符或整数
+//!     @ms(2) = a, b*3;                    // 初始多重集，也可以用 += 追加，# 表示空多重集
+//!     [a, b*2 --> c, (d, out), (e, in 3)]'2;   // 演化与通信规则
+//!     [a]'2 --> b;                        // 溶解规则：消耗 a，产生 b，溶解膜 2
+//!     [a]'2 --> b[]'2;                    // 送出规则：消耗 a，向父膜送出 b
+//!     a[]'2 --> [b]'2;                    // 送入规则：消耗父膜中的 a，向膜 2 送入 b
+//!     [a --> b]'2 > [a --> c]'2;          // 优先关系，可以连写 r1 > r2 > r3，只能用于同一个膜的规则
+//! }
+//! ```
+//! `->` 与 `-->` 均可作为箭头，对象可以带常量下标，如 `a{1,2}`，注释支持 `//` 与 `/* */`
+//! 在多处出现的相同规则视为同一条规则，优先关系取传递闭包
+//!
+//! 导入得到的膜使用 [`EvolveMode::MaximalMultiset`]，按 P 系统的极大并行语义演化，优先关系为强优先
+//! 未绑定的对象名映射到符号类型池，默认为容量 [`SYMBOL_CAPACITY`] 的 [`crate::objs::symbol::Symbol`] 类型，
+//! 超出时导入失败，需要用 [`PliImporter::bind`] 把多出的对象名映射到自定义类型，
+//! 或用 [`PliImporter::symbol_pool`] 提供更大的类型池  
+//! 每个导入器从池的第一个类型开始分配，并发的导入互不影响，但不同导入器得到的模型共用相同的类型，
+//! 不应放入同一个系统中
+
+use std::any::TypeId;
+use std::path::Path;
+
+use ahash::AHashMap;
+use krnl::scalar::Scalar;
+
+use crate::core::{IObj, MemTarget, ObjType};
+use crate::errors::MemError;
+use crate::formats::dot::TypeNames;
+use crate::mems::basic::{BasicMem, PBasicRule};
+use crate::mems::hierarchy::MemHierarchy;
+use crate::mems::stochastic::EvolveMode;
+use crate::objs::symbol::{symbol_type, SYMBOL_CAPACITY};
+use crate::rules::multiset::MultisetRule;
+
+pub type PliSystem<U> = MemHierarchy<u32, u32, u32, U>;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Ident(String),
+    Int(u64),
+    Directive(String),
+    Arrow,
+    PlusEq,
+    Punct(char)
+}
+
+fn err(line: usize, info: String) -> MemError<usize> {
+    MemError { info: format!("line {line}: {info}"), data: Some(line) }
+}
+
+fn lex(src: &str) -> Result<Vec<(Tok, usize)>, MemError<usize>> {
+    let cs = src.chars().collect::<Vec<_>>();
+    let mut toks = Vec::new();
+    let (mut i, mut line) = (0, 1);
+    while i < cs.len() {
+        let c = cs[i];
+        match c {
+            '\n' => { line += 1; i += 1; },
+            c if c.is_whitespace() => { i += 1; },
+            '/' if cs.get(i + 1) == Some(&'/') => {
+                while i < cs.len() && cs[i] != '\n' { i += 1; }
+            },
+            '/' if cs.get(i + 1) == Some(&'*') => {
+                i += 2;
+                while i < cs.len() && !(cs[i] == '*' && cs.get(i + 1) == Some(&'/')) {
+                    if cs[i] == '\n' { line += 1; }
+                    i += 1;
+                }
+                if i >= cs.len() {
+                    return Err(err(line, "unterminated comment".to_string()));
+                }
+                i += 2;
+            },
+            '-' if cs.get(i + 1) == Some(&'>') => { toks.push((Tok::Arrow, line)); i += 2; },
+            '-' if cs.get(i + 1) == Some(&'-') && cs.get(i + 2) == Some(&'>') => { toks.push((Tok::Arrow, line)); i += 3; },
+            '+' if cs.get(i + 1) == Some(&'=') => { toks.push((Tok::PlusEq, line)); i += 2; },
+            '@' => {
+                let s = i + 1;
+                i = s;
+                while i < cs.len() && (cs[i].is_alphanumeric() || cs[i] == '_') { i += 1; }
+                toks.push((Tok::Directive(cs[s..i].iter().collect()), line));
+            },
+            c if c.is_ascii_digit() => {
+                let s = i;
+                while i < cs.len() && cs[i].is_ascii_digit() { i += 1; }
+                let v = cs[s..i].iter().collect::<String>().parse::<u64>()
+                    .map_err(|e| err(line, e.to_string()))?;
+                toks.push((Tok::Int(v), line));
+            },
+            c if c.is_alphabetic() || c == '_' => {
+                let s = i;
+                while i < cs.len() && (cs[i].is_alphanumeric() || cs[i] == '_') { i += 1; }
+                toks.push((Tok::Ident(cs[s..i].iter().collect()), line));
+            },
+            '[' | ']' | '\'' | '(' | ')' | '{' | '}' | ',' | ';' | '*' | '=' | '<' | '>' | '#' => {
+                toks.push((Tok::Punct(c), line));
+                i += 1;
+            },
+            _ => return Err(err(line, format!("unsupported character {c:?}")))
+        }
+    }
+    Ok(toks)
+}
+
+type Multiset = Vec<(String, u64)>;
+
+#[derive(Debug, PartialEq)]
+enum RuleKind {
+    /// 作用于标签为该值的膜
+    Local,
+    /// 作用于含有该标签子膜的膜
+    Parent
+}
+
+#[derive(Debug)]
+struct RuleSpec {
+    label: usize,
+    kind: RuleKind,
+    lhs: Multiset,
+    rhs: Vec<(String, u64, MemTarget)>,
+    dissolve: bool,
+    line: usize
+}
+
+impl RuleSpec {
+    fn same_rule(&self, other: &RuleSpec) -> bool {
+        self.label == other.label && self.kind == other.kind && self.lhs == other.lhs &&
+        self.rhs == other.rhs && self.dissolve == other.dissolve
+    }
+
+    fn same_mem(&self, other: &RuleSpec) -> bool {
+        self.label == other.label && self.kind == other.kind
+    }
+}
+
+#[derive(Debug, Default)]
+struct Parsed {
+    /// 先序排列的 `(标签, 父膜下标)`
+    structure: Vec<(usize, Option<usize>)>,
+    multisets: Vec<(usize, Multiset, usize)>,
+    rules: Vec<RuleSpec>,
+    /// `(优先的规则, 被压制的规则)`，已取传递闭包
+    priorities: Vec<(usize, usize)>,
+    labels: Vec<String>
+}
+
+struct Parser {
+    toks: Vec<(Tok, usize)>,
+    pos: usize,
+    out: Parsed
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Tok> {
+        self.toks.get(self.pos).map(|t| &t.0)
+    }
+
+    fn line(&self) -> usize {
+        self.toks.get(self.pos).or(self.toks.last()).map_or(0, |t| t.1)
+    }
+
+    fn next(&mut self) -> Result<Tok, MemError<usize>> {
+        let t = self.toks.get(self.pos).map(|t| t.0.clone())
+            .ok_or_else(|| err(self.line(), "unexpected end of file".to_string()))?;
+        self.pos += 1;
+        Ok(t)
+    }
+
+    fn is_punct(&self, c: char) -> bool {
+        self.peek() == Some(&Tok::Punct(c))
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), MemError<usize>> {
+        let line = self.line();
+        match self.next()? {
+            Tok::Punct(p) if p == c => Ok(()),
+            t => Err(err(line, format!("expected '{c}' but found {t:?}")))
+        }
+    }
+
+    fn expect_arrow(&mut self) -> Result<(), MemError<usize>> {
+        let line = self.line();
+        match self.next()? {
+            Tok::Arrow => Ok(()),
+            t => Err(err(line, format!("expected '-->' but found {t:?}")))
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, MemError<usize>> {
+        let line = self.line();
+        match self.next()? {
+            Tok::Ident(s) => Ok(s),
+            t => Err(err(line, format!("expected identifier but found {t:?}")))
+        }
+    }
+
+    fn int(&mut self) -> Result<u64, MemError<usize>> {
+        let line = self.line();
+        match self.next()? {
+            Tok::Int(v) => Ok(v),
+            t => Err(err(line, format!("expected integer but found {t:?}")))
+        }
+    }
+
+    fn intern_label(&mut self, name: String) -> usize {
+        if let Some(p) = self.out.labels.iter().position(|l| *l == name) {
+            p
+        } else {
+            self.out.labels.push(name);
+            self.out.labels.len() - 1
+        }
+    }
+
+    /// 标签名可以是标识符或整数
+    fn label_name(&mut self) -> Result<usize, MemError<usize>> {
+        let line = self.line();
+        let name = match self.next()? {
+            Tok::Ident(s) => s,
+            Tok::Int(v) => v.to_string(),
+            t => return Err(err(line, format!("expected membrane label but found {t:?}")))
+        };
+        Ok(self.intern_label(name))
+    }
+
+    /// `'label`
+    fn label(&mut self) -> Result<usize, MemError<usize>> {
+        self.expect('\'')?;
+        self.label_name()
+    }
+
+    /// `a`, `a*3`, `a{1,2}*3`
+    fn obj(&mut self) -> Result<(String, u64), MemError<usize>> {
+        let mut name = self.ident()?;
+        if self.is_punct('{') {
+            self.pos += 1;
+            let mut idx = Vec::new();
+            loop {
+                let line = self.line();
+                match self.next()? {
+                    Tok::Int(v) => idx.push(v.to_string()),
+                    Tok::Ident(s) => idx.push(s),
+                    t => return Err(err(line, format!("unsupported object index {t:?}")))
+                }
+                if self.is_punct(',') {
+                    self.pos += 1;
+                } else {
+                    break;
+                }
+            }
+            self.expect('}')?;
+            name = format!("{name}{{{}}}", idx.join(","));
+        }
+        let mut amount = 1;
+        if self.is_punct('*') {
+            self.pos += 1;
+            amount = self.int()?;
+        }
+        Ok((name, amount))
+    }
+
+    /// 逗号分隔的对象，`#` 表示空多重集
+    fn multiset(&mut self) -> Result<Multiset, MemError<usize>> {
+        if self.is_punct('#') {
+            self.pos += 1;
+            return Ok(Vec::new());
+        }
+        let mut ms = vec![self.obj()?];
+        while self.is_punct(',') {
+            self.pos += 1;
+            ms.push(self.obj()?);
+        }
+        Ok(ms)
+    }
+
+    /// 演化规则右侧，`(a, out)` 与 `(a, in h)` 表示通信
+    fn rhs(&mut self) -> Result<Vec<(String, u64, MemTarget)>, MemError<usize>> {
+        let mut items = Vec::new();
+        loop {
+            if self.is_punct('#') {
+                self.pos += 1;
+            } else if self.is_punct('(') {
+                self.pos += 1;
+                let (o, a) = self.obj()?;
+                self.expect(',')?;
+                let line = self.line();
+                let target = match self.ident()?.as_str() {
+                    "out" => MemTarget::Out,
+                    "here" => MemTarget::Here,
+                    "in" => MemTarget::In(self.label_name()?),
+                    t => return Err(err(line, format!("unsupported target {t:?}")))
+                };
+                self.expect(')')?;
+                items.push((o, a, target));
+            } else {
+                let (o, a) = self.obj()?;
+                items.push((o, a, MemTarget::Here));
+            }
+            if self.is_punct(',') {
+                self.pos += 1;
+            } else {
+                return Ok(items);
+            }
+        }
+    }
+
+    /// `[ ... ]'label`，返回本膜的下标
+    fn structure(&mut self, parent: Option<usize>) -> Result<usize, MemError<usize>> {
+        self.expect('[')?;
+        let pos = self.out.structure.len();
+        self.out.structure.push((0, parent));
+        while self.is_punct('[') {
+            self.structure(Some(pos))?;
+        }
+        self.expect(']')?;
+        self.out.structure[pos].0 = self.label()?;
+        Ok(pos)
+    }
+
+    fn rule(&mut self) -> Result<RuleSpec, MemError<usize>> {
+        let line = self.line();
+        let spec = if self.is_punct('[') {
+            self.pos += 1;
+            let lhs = self.multiset()?;
+            if self.peek() == Some(&Tok::Arrow) {
+                // [u --> v]'h
+                self.pos += 1;
+                let rhs = self.rhs()?;
+                self.expect(']')?;
+                let label = self.label()?;
+                RuleSpec { label, kind: RuleKind::Local, lhs, rhs, dissolve: false, line }
+            } else {
+                // [u]'h --> v  或  [u]'h --> v[]'h
+                self.expect(']')?;
+                let label = self.label()?;
+                self.expect_arrow()?;
+                let v = self.multiset()?;
+                if self.is_punct('[') {
+                    self.expect('[')?;
+                    self.expect(']')?;
+                    if self.label()? != label {
+                        return Err(err(line, "membrane labels of a send-out rule differ".to_string()));
+                    }
+                    let rhs = v.into_iter().map(|(o, a)| (o, a, MemTarget::Out)).collect();
+                    RuleSpec { label, kind: RuleKind::Local, lhs, rhs, dissolve: false, line }
+                } else {
+                    let rhs = v.into_iter().map(|(o, a)| (o, a, MemTarget::Here)).collect();
+                    RuleSpec { label, kind: RuleKind::Local, lhs, rhs, dissolve: true, line }
+                }
+            }
+        } else {
+            // u[]'h --> [v]'h
+            let lhs = self.multiset()?;
+            self.expect('[')?;
+            self.expect(']')?;
+            let label = self.label()?;
+            self.expect_arrow()?;
+            self.expect('[')?;
+            let v = self.multiset()?;
+            self.expect(']')?;
+            if self.label()? != label {
+                return Err(err(line, "membrane labels of a send-in rule differ".to_string()));
+            }
+            let rhs = v.into_iter().map(|(o, a)| (o, a, MemTarget::In(label))).collect();
+            RuleSpec { label, kind: RuleKind::Parent, lhs, rhs, dissolve: false, line }
+        };
+        Ok(spec)
+    }
+
+    /// 相同的规则只保留一条，返回规则的下标
+    fn intern_rule(&mut self, spec: RuleSpec) -> usize {
+        if let Some(p) = self.out.rules.iter().position(|r| r.same_rule(&spec)) {
+            p
+        } else {
+            self.out.rules.push(spec);
+            self.out.rules.len() - 1
+        }
+    }
+
+    /// `r1 > r2 > ... ;`
+    fn rules(&mut self) -> Result<(), MemError<usize>> {
+        let first = self.rule()?;
+        let mut prev = self.intern_rule(first);
+        while self.is_punct('>') {
+            self.pos += 1;
+            let line = self.line();
+            let spec = self.rule()?;
+            if !self.out.rules[prev].same_mem(&spec) {
+                return Err(err(line, "priorities can only relate rules of the same membrane".to_string()));
+            }
+            let next = self.intern_rule(spec);
+            self.out.priorities.push((prev, next));
+            prev = next;
+        }
+        self.expect(';')
+    }
+
+    /// 传递闭包，规则优先于自身时出错
+    fn close_priorities(&mut self) -> Result<(), MemError<usize>> {
+        let mut ps = std::mem::take(&mut self.out.priorities);
+        loop {
+            let extra = ps.iter()
+                .flat_map(|(a, b)| ps.iter().filter(move |(c, _)| c == b).map(move |(_, d)| (*a, *d)))
+                .filter(|p| !ps.contains(p))
+                .collect::<Vec<_>>();
+            if extra.is_empty() {
+                break;
+            }
+            ps.extend(extra);
+            ps.sort();
+            ps.dedup();
+        }
+        if let Some((a, _)) = ps.iter().find(|(a, b)| a == b) {
+            return Err(err(self.out.rules[*a].line, "cyclic priorities between rules".to_string()));
+        }
+        self.out.priorities = ps;
+        Ok(())
+    }
+
+    fn statement(&mut self) -> Result<(), MemError<usize>> {
+        let line = self.line();
+        match self.peek().cloned() {
+            Some(Tok::Directive(d)) if d == "mu" => {
+                self.pos += 1;
+                self.expect('=')?;
+                if !self.out.structure.is_empty() {
+                    return Err(err(line, "membrane structure defined twice".to_string()));
+                }
+                self.structure(None)?;
+                self.expect(';')
+            },
+            Some(Tok::Directive(d)) if d == "ms" => {
+                self.pos += 1;
+                self.expect('(')?;
+                let label = self.label_name()?;
+                self.expect(')')?;
+                match self.next()? {
+                    Tok::PlusEq | Tok::Punct('=') => {},
+                    t => return Err(err(line, format!("expected '=' but found {t:?}")))
+                }
+                let ms = self.multiset()?;
+                self.out.multisets.push((label, ms, line));
+                self.expect(';')
+            },
+            Some(Tok::Directive(d)) => Err(err(line, format!("unsupported directive @{d}"))),
+            _ => self.rules()
+        }
+    }
+
+    fn file(&mut self) -> Result<(), MemError<usize>> {
+        if self.peek() == Some(&Tok::Directive("model".to_string())) {
+            self.pos += 1;
+            self.expect('<')?;
+            self.ident()?;
+            self.expect('>')?;
+        }
+        let line = self.line();
+        if self.ident()? != "def" || self.ident()? != "main" {
+            return Err(err(line, "expected 'def main()', only the main module is supported".to_string()));
+        }
+        self.expect('(')?;
+        self.expect(')')?;
+        self.expect('{')?;
+        while !self.is_punct('}') {
+            self.statement()?;
+        }
+        self.expect('}')?;
+        if let Some(t) = self.peek() {
+            return Err(err(self.line(), format!("unexpected {t:?} after the main module")));
+        }
+        if self.out.structure.is_empty() {
+            return Err(err(line, "missing membrane structure @mu".to_string()));
+        }
+        self.close_priorities()
+    }
+}
+
+/// 导入得到的模型，包含膜层级以及标签和符号的名字
+#[derive(Debug)]
+pub struct PliModel<U = u32>
+where U: Scalar {
+    system: PliSystem<U>,
+    labels: Vec<String>,
+    symbols: AHashMap<String, ObjType>
+}
+
+impl<U> PliModel<U>
+where U: Scalar {
+    pub fn system(&self) -> &PliSystem<U> {
+        &self.system
+    }
+
+    pub fn system_mut(&mut self) -> &mut PliSystem<U> {
+        &mut self.system
+    }
+
+    pub fn into_system(self) -> PliSystem<U> {
+        self.system
+    }
+
+    /// 标签名对应的标签，即 [`crate::mems::hierarchy::MemNode::label`]
+    pub fn label_of(&self, name: &str) -> Option<usize> {
+        self.labels.iter().position(|l| l == name)
+    }
+
+    pub fn label_name(&self, label: usize) -> Option<&str> {
+        self.labels.get(label).map(|s| s.as_str())
+    }
+
+    /// 对象名对应的类型
+    pub fn obj_type(&self, name: &str) -> Option<&ObjType> {
+        self.symbols.get(name)
+    }
+
+    pub fn symbols(&self) -> impl Iterator<Item = (&String, &ObjType)> {
+        self.symbols.iter()
+    }
+
+    /// 用于 DOT 导出的对象名
+    pub fn type_names(&self) -> TypeNames {
+        self.symbols.iter().fold(TypeNames::new(), |n, (name, ty)| n.with(ty, name))
+    }
+}
+
+/// P-Lingua 导入器
+/// 对象名默认映射到 [`crate::objs::symbol::Symbol`] 类型池，
+/// 可以用 [`PliImporter::bind`] 将对象名映射到自定义类型，以便在规则中与其他对象一起使用
+#[derive(Debug)]
+pub struct PliImporter {
+    symbols: AHashMap<String, ObjType>,
+    pool: Option<Vec<ObjType>>,
+    pool_next: usize
+}
+
+impl Default for PliImporter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PliImporter {
+    pub fn new() -> Self {
+        Self { symbols: AHashMap::new(), pool: None, pool_next: 0 }
+    }
+
+    /// 用 `pool` 代替默认的符号类型池，未绑定的对象名依次映射到其中的类型，`pool` 中的类型应当互不相同  
+    /// 导入的对象名数量不超过 `pool` 的长度
+    pub fn symbol_pool(mut self, pool: Vec<ObjType>) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+
+    pub fn bind<O: IObj + 'static>(mut self, name: &str) -> Self {
+        self.symbols.insert(name.to_string(), ObjType::default_group::<O>());
+        self
+    }
+
+    fn resolve(&mut self, name: &str, line: usize) -> Result<ObjType, MemError<usize>> {
+        if let Some(ty) = self.symbols.get(name) {
+            return Ok(ty.clone());
+        }
+        let next = match self.pool.as_ref() {
+            Some(pool) => pool.get(self.pool_next).cloned(),
+            None => symbol_type(self.pool_next),
+        };
+        let capacity = self.pool.as_ref().map_or(SYMBOL_CAPACITY, Vec::len);
+        let ty = next.ok_or_else(|| err(line, format!(
+            "too many objects (at most {capacity} unbound), bind {name:?} with PliImporter::bind or use a larger symbol pool"
+        )))?;
+        self.pool_next += 1;
+        self.symbols.insert(name.to_string(), ty.clone());
+        Ok(ty)
+    }
+
+    /// 合并同类对象
+    fn multiset<U: Scalar>(&mut self, ms: &[(String, u64)], line: usize) -> Result<Vec<(ObjType, U)>, MemError<usize>> {
+        let mut res: Vec<(ObjType, U)> = Vec::new();
+        for (o, a) in ms {
+            let ty = self.resolve(o, line)?;
+            if let Some(e) = res.iter_mut().find(|e| e.0 == ty) {
+                e.1 += a.cast::<U>();
+            } else {
+                res.push((ty, a.cast::<U>()));
+            }
+        }
+        Ok(res)
+    }
+
+    pub fn import_file<U: Scalar, P: AsRef<Path>>(self, path: P) -> Result<PliModel<U>, MemError<usize>> {
+        let src = std::fs::read_to_string(path).map_err(|e| MemError { info: e.to_string(), data: None })?;
+        self.import(&src)
+    }
+
+    pub fn import<U: Scalar>(mut self, src: &str) -> Result<PliModel<U>, MemError<usize>> {
+        let mut parser = Parser { toks: lex(src)?, pos: 0, out: Parsed::default() };
+        parser.file()?;
+        let parsed = parser.out;
+        let labels_used = |label: usize| parsed.structure.iter().any(|(l, _)| *l == label);
+
+        let mut untagged: Vec<Vec<(TypeId, U)>> = vec![Vec::new(); parsed.structure.len()];
+        for (label, ms, line) in parsed.multisets.iter() {
+            if !labels_used(*label) {
+                return Err(err(*line, format!("unknown membrane label {:?}", parsed.labels[*label])));
+            }
+            let ms = self.multiset::<U>(ms, *line)?;
+            for (i, _) in parsed.structure.iter().enumerate().filter(|(_, (l, _))| l == label) {
+                untagged[i].extend(ms.iter().map(|(ty, a)| (ty.tid, *a)));
+            }
+        }
+
+        let mut rules: Vec<Vec<PBasicRule<u32, u32, U>>> = Vec::new();
+        rules.resize_with(parsed.structure.len(), Vec::new);
+        // 每个膜中由规则下标到规则标签的映射
+        let mut tags: Vec<AHashMap<usize, u32>> = vec![AHashMap::new(); parsed.structure.len()];
+        for (ri, r) in parsed.rules.iter().enumerate() {
+            if !labels_used(r.label) {
+                return Err(err(r.line, format!("unknown membrane label {:?}", parsed.labels[r.label])));
+            }
+            let lhs = self.multiset::<U>(&r.lhs, r.line)?;
+            let mut rhs = Vec::new();
+            for (o, a, target) in r.rhs.iter() {
+                if let MemTarget::In(l) = target {
+                    if !labels_used(*l) {
+                        return Err(err(r.line, format!("unknown membrane label {:?}", parsed.labels[*l])));
+                    }
+                }
+                rhs.push((self.resolve(o, r.line)?, a.cast::<U>(), target.clone()));
+            }
+            let owners = match r.kind {
+                RuleKind::Local => parsed.structure.iter().enumerate()
+                    .filter(|(_, (l, _))| *l == r.label)
+                    .map(|(i, _)| i)
+                    .collect::<Vec<_>>(),
+                RuleKind::Parent => {
+                    let mut ps = parsed.structure.iter()
+                        .filter(|(l, _)| *l == r.label)
+                        .filter_map(|(_, p)| *p)
+                        .collect::<Vec<_>>();
+                    ps.sort();
+                    ps.dedup();
+                    ps
+                }
+            };
+            if owners.is_empty() {
+                return Err(err(r.line, "objects can not be sent into the skin membrane".to_string()));
+            }
+            if r.dissolve && owners.iter().any(|i| parsed.structure[*i].1.is_none()) {
+                return Err(err(r.line, "the skin membrane can not be dissolved".to_string()));
+            }
+            for i in owners {
+                let tag = rules[i].len() as u32;
+                rules[i].push(Box::new(MultisetRule::new(tag, &lhs, &rhs, r.dissolve, 0)));
+                tags[i].insert(ri, tag);
+            }
+        }
+
+        let mut mems = untagged.into_iter().zip(rules).zip(tags).enumerate().map(|(i, ((u, r), t))| {
+            let mut m = BasicMem::new(i as u32, false);
+            m.init(Vec::new(), u, r);
+            m.set_mode(EvolveMode::MaximalMultiset);
+            for (hi, lo) in parsed.priorities.iter() {
+                if let (Some(h), Some(l)) = (t.get(hi), t.get(lo)) {
+                    m.add_priority(*h, *l);
+                }
+            }
+            m
+        }).collect::<Vec<_>>().into_iter();
+
+        let mut system = MemHierarchy::new(0, mems.next().unwrap(), parsed.structure[0].0);
+        for (m, (label, parent)) in mems.zip(parsed.structure.iter().skip(1)) {
+            system.add_child(parent.unwrap(), m, *label);
+        }
+        Ok(PliModel { system, labels: parsed.labels, symbols: self.symbols })
+    }
+}
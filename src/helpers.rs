@@ -8,18 +8,22 @@ use krnl::scalar::Scalar;
 use log::Level;
 use log::log;
 
-use crate::core::{ICondition, IObj, IRuleEffect, ObjCrateFn, ObjRemoveFn, ObjType, ObjsCrateFn, ObjsRemoveFn, OperationEffect, TaggedPresence, TaggedPresences, UntaggedPresence, UntaggedPresences, UseBy};
+use crate::core::{EffectSignature, ICondition, IObj, IRuleEffect, MemTarget, ObjCrateFn, ObjPredicate, ObjSelect, ObjWeight, ObjRemoveFn, ObjType, ObjsCrateFn, ObjsRemoveFn, OperationEffect, TaggedPresence, TaggedPresenceInfo, TaggedPresences, UntaggedPresence, UntaggedPresences, UseBy};
 use crate::gpu;
 use crate::lib_info::log_target;
+use crate::objs::uid::UuidTag;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 
 #[derive(Debug)]
 pub struct EffectBuilder<E> {
     effs: Option<Vec<E>>,
+    sigs: Vec<EffectSignature>
 }
 
 impl<E> EffectBuilder<E> {
     pub fn new() -> Self {
-        Self { effs: None }
+        Self { effs: None, sigs: Vec::new() }
     }
 }
 
@@ -32,56 +36,138 @@ impl<E> Default for EffectBuilder<E> {
 impl<T, U> EffectBuilder<OperationEffect<T, U>>
 where T: Send + Sync, U: Send + Sync {
 
-    pub fn add_op(mut self, op: OperationEffect<T, U>) -> Self {
+    fn push(mut self, op: OperationEffect<T, U>, sig: EffectSignature) -> Self {
         let e = self.effs.get_or_insert(Vec::new());
         e.push(op);
+        self.sigs.push(sig);
         self
     }
 
-    pub fn crate_objs(mut self, f: ObjsCrateFn<T, U>) -> Self {
-        let e = self.effs.get_or_insert(Vec::new());
-        e.push(OperationEffect::CreateObjs(f));
-        self
+    pub fn add_op(self, op: OperationEffect<T, U>) -> Self {
+        self.push(op, EffectSignature::default())
     }
 
-    pub fn crate_obj(mut self, f: ObjCrateFn<T, U>) -> Self {
-        let e = self.effs.get_or_insert(Vec::new());
-        e.push(OperationEffect::CreateObj(f));
-        self
+    pub fn crate_objs(self, f: ObjsCrateFn<T, U>) -> Self {
+        self.push(OperationEffect::CreateObjs(f), EffectSignature::default())
     }
 
-    pub fn remove_obj(mut self, f: ObjRemoveFn<T, U>) -> Self {
-        let e = self.effs.get_or_insert(Vec::new());
-        e.push(OperationEffect::RemoveObj(f));
-        self
+    pub fn crate_obj(self, f: ObjCrateFn<T, U>) -> Self {
+        self.push(OperationEffect::CreateObj(f), EffectSignature::default())
     }
 
-    pub fn remove_objs(mut self, f: ObjsRemoveFn<T, U>) -> Self {
-        let e = self.effs.get_or_insert(Vec::new());
-        e.push(OperationEffect::RemoveObjs(f));
-        self
+    pub fn remove_obj(self, f: ObjRemoveFn<T, U>) -> Self {
+        self.push(OperationEffect::RemoveObj(f), EffectSignature::default())
     }
 
-    pub fn increase_untagged<O: IObj +'static>(mut self, amount: U) -> Self {
-        let e = self.effs.get_or_insert(Vec::new());
-        e.push(OperationEffect::IncreaseObjUntagged((ObjType::default_group::<O>(), amount)));
+    pub fn remove_objs(self, f: ObjsRemoveFn<T, U>) -> Self {
+        self.push(OperationEffect::RemoveObjs(f), EffectSignature::default())
+    }
+
+    pub fn increase_untagged<O: IObj +'static>(self, amount: U) -> Self {
+        self.increase_untagged_of(ObjType::default_group::<O>(), amount)
+    }
+
+    pub fn decrease_untagged<O: IObj +'static>(self, amount: U) -> Self {
+        self.decrease_untagged_of(ObjType::default_group::<O>(), amount)
+    }
+
+    pub fn increase_untagged_of(self, ty: ObjType, amount: U) -> Self {
+        let sig = EffectSignature::producing(ty.clone());
+        self.push(OperationEffect::IncreaseObjUntagged((ty, amount)), sig)
+    }
+
+    pub fn decrease_untagged_of(self, ty: ObjType, amount: U) -> Self {
+        let sig = EffectSignature::consuming(ty.clone());
+        self.push(OperationEffect::DecreaseObjUntagged((ty, amount)), sig)
+    }
+
+    /// 将 `amount` 单位的 `O` 发送到 `target` 膜，发送在膜层级的一步结束后生效
+    pub fn send_untagged<O: IObj +'static>(self, amount: U, target: MemTarget) -> Self {
+        self.send_untagged_of(ObjType::default_group::<O>(), amount, target)
+    }
+
+    pub fn send_untagged_of(self, ty: ObjType, amount: U, target: MemTarget) -> Self {
+        let sig = EffectSignature::producing(ty.clone());
+        self.push(OperationEffect::SendObjUntagged((ty, amount, target)), sig)
+    }
+
+    /// 经过模拟时间 `delay` 后在本膜中出现 `f` 创建的对象
+    pub fn crate_obj_after(self, f: ObjCrateFn<T, U>, delay: f64) -> Self {
+        self.push(OperationEffect::ScheduleObj((f, delay)), EffectSignature::default())
+    }
+
+    /// 经过模拟时间 `delay` 后在本膜中增加 `amount` 单位的 `O`
+    pub fn increase_untagged_after<O: IObj +'static>(self, amount: U, delay: f64) -> Self {
+        self.increase_untagged_after_of(ObjType::default_group::<O>(), amount, delay)
+    }
+
+    pub fn increase_untagged_after_of(self, ty: ObjType, amount: U, delay: f64) -> Self {
+        let sig = EffectSignature::producing(ty.clone());
+        self.push(OperationEffect::ScheduleUntagged((ty, amount, delay)), sig)
+    }
+
+    /// 溶解所在的膜，膜内对象在膜层级的一步结束后移入父膜
+    pub fn dissolve_mem(self) -> Self {
+        self.push(OperationEffect::DissolveMem, EffectSignature::default())
+    }
+
+    pub fn stop_mem(self) -> Self {
+        self.push(OperationEffect::Stop, EffectSignature::default())
+    }
+
+    /// 声明最后添加的操作会产生 `O` 类型的对象  
+    /// 调试构建中会检查创建对象的操作（包括延迟创建）只产生声明过的类型，没有声明的创建操作创建任何对象都会 panic，
+    /// 产生的类型无法预先得知时使用 [`EffectBuilder::produces_any`]
+    /// 
+    /// # 例子
+    /// ```
+    /// use meme_derive::IObj;
+    /// use meme::rules::BasicEffect;
+    /// 
+    /// #[derive(IObj, Debug)]
+    /// struct TestObj {
+    ///     #[tag]
+    ///     tag: i32
+    /// }
+    /// 
+    /// let eff = meme::helpers::effect_builder()
+    ///           .crate_obj(|_| Box::new(TestObj { tag: 0 }))
+    ///           .produces::<TestObj>()
+    ///           .build::<BasicEffect<i32>>();
+    /// ```
+    pub fn produces<O: IObj + 'static>(self) -> Self {
+        self.produces_of(ObjType::default_group::<O>())
+    }
+
+    pub fn produces_of(mut self, ty: ObjType) -> Self {
+        if let Some(sig) = self.sigs.last_mut() {
+            sig.produces.push(ty);
+        }
         self
     }
 
-    pub fn decrease_untagged<O: IObj +'static>(mut self, amount: U) -> Self {
-        let e = self.effs.get_or_insert(Vec::new());
-        e.push(OperationEffect::DecreaseObjUntagged((ObjType::default_group::<O>(), amount)));
+    /// 声明最后添加的操作可能产生任意类型的对象，如转发收到的对象
+    pub fn produces_any(mut self) -> Self {
+        if let Some(sig) = self.sigs.last_mut() {
+            sig.any_product = true;
+        }
         self
     }
 
-    pub fn stop_mem(mut self) -> Self {
-        let e = self.effs.get_or_insert(Vec::new());
-        e.push(OperationEffect::Stop);
+    /// 声明最后添加的操作会消耗（移除） `O` 类型的对象
+    pub fn consumes<O: IObj + 'static>(self) -> Self {
+        self.consumes_of(ObjType::default_group::<O>())
+    }
+
+    pub fn consumes_of(mut self, ty: ObjType) -> Self {
+        if let Some(sig) = self.sigs.last_mut() {
+            sig.consumes.push(ty);
+        }
         self
     }
 
     pub fn build<RE: IRuleEffect<Effect = OperationEffect<T, U>>>(&mut self) -> RE {
-        RE::from_builder(self.effs.take())
+        RE::from_builder(self.effs.take(), std::mem::take(&mut self.sigs))
     }
 }
 
@@ -92,7 +178,7 @@ where T: Send + Sync, U: Send + Sync {
 pub struct ConditionBuilder<T = u32, U = u32>
 where T: Clone + Hash + Eq, U: Scalar{
     of_type: Option<UntaggedPresences<U>>,
-    of_tag: Option<TaggedPresences<T>>,
+    of_tag: Option<TaggedPresences<T, U>>,
     last_added_is_otg: bool,
     skip_take: bool
 }
@@ -113,6 +199,7 @@ impl<T: Clone + Hash + Eq, U: Scalar> ConditionBuilder<T, U> {
         }
     }
     
+    /// 要求至少 `amount` 单位的 untagged `Obj` 对象，tagged 对象不计入
     pub fn some_untagged<Obj: IObj + ?Sized + 'static>(mut self, amount: U) -> Self {
         let oty = self.of_type.get_or_insert(Vec::new());
         oty.push(UntaggedPresence {
@@ -124,6 +211,18 @@ impl<T: Clone + Hash + Eq, U: Scalar> ConditionBuilder<T, U> {
         self
     }
 
+    /// 与 [`ConditionBuilder::some_untagged`] 相同，但对象类型在运行时给出
+    pub fn some_untagged_of(mut self, ty: ObjType, amount: U) -> Self {
+        let oty = self.of_type.get_or_insert(Vec::new());
+        oty.push(UntaggedPresence {
+            ty,
+            amount,
+            take: false
+        });
+        self.last_added_is_otg = false;
+        self
+    }
+
     /// 选取指定tag的对象
     pub fn the_tagged(mut self, tag: T) -> Self {
         let otg = self.of_tag.get_or_insert(Vec::new());
@@ -142,9 +241,8 @@ impl<T: Clone + Hash + Eq, U: Scalar> ConditionBuilder<T, U> {
         self
     }
 
-    /// 随机选择tagged对象，随机选择会有开销（`O(n)`，`n` 为对象数量），如果选择失败的情况较多，   
-    /// 可以使用[`ConditionBuilder::some_untagged`]来要求该类对象数量满足  
-    /// 因为untagged会被优先检查，失败时提前返回，避免选择的开销，例如：  
+    /// 随机选择tagged对象，可选的对象不足 `count` 个时条件不满足  
+    /// tagged 对象不计入 [`ConditionBuilder::some_untagged`] 的数量，例如：  
     /// 
     /// # 例子
     /// ```
@@ -158,8 +256,7 @@ impl<T: Clone + Hash + Eq, U: Scalar> ConditionBuilder<T, U> {
     /// }
     /// 
     /// let cond = meme::helpers::condition_builder()
-    ///            .rand_tagged::<TestObj>(3)
-    ///            .some_untagged::<TestObj>(3)
+    ///            .rand_tagged::<TestObj>(3).by_ref()
     ///            .build::<BasicCondition<i32>>();
     /// ```
     pub fn rand_tagged<Obj: IObj + 'static>(mut self, count: usize) -> Self {
@@ -169,6 +266,22 @@ impl<T: Clone + Hash + Eq, U: Scalar> ConditionBuilder<T, U> {
         self
     }
 
+    /// 上一个随机选择只从满足 `pred` 的对象中选择，例如只选能量大于 5 的细胞
+    pub fn filter_by(mut self, pred: ObjPredicate<T, U>) -> Self {
+        if let Some(s) = self.last_select() {
+            s.filter = Some(pred);
+        }
+        self
+    }
+
+    /// 上一个随机选择按 `weight` 给出的权重选择对象
+    pub fn weight_by(mut self, weight: ObjWeight<T, U>) -> Self {
+        if let Some(s) = self.last_select() {
+            s.weight = Some(weight);
+        }
+        self
+    }
+
     pub fn by_ref(mut self) -> Self {
         self.set_last_tagged(UseBy::Ref);
         self
@@ -210,6 +323,13 @@ impl<T: Clone + Hash + Eq, U: Scalar> ConditionBuilder<T, U> {
         }
     }
 
+    fn last_select(&mut self) -> Option<&mut ObjSelect<T, U>> {
+        self.of_tag.as_mut()
+            .and_then(|otg| otg.last_mut())
+            .filter(|tg| matches!(tg.info, TaggedPresenceInfo::RandTags(_)))
+            .map(|tg| tg.select.get_or_insert_with(ObjSelect::default))
+    }
+
     fn set_last_untagged(&mut self, is_take: bool){
         if let Some(ref mut oty) = self.of_type {
             if let Some(ty) = oty.last_mut() {
@@ -241,7 +361,7 @@ pub fn effect_builder<E>() -> EffectBuilder<E> {
 
 #[inline]
 pub fn effect_empty<RE: IRuleEffect>() -> RE {
-    RE::from_builder(None)
+    RE::from_builder(None, Vec::new())
 }
 
 // todo: 全局tag生成器 -ok
@@ -256,7 +376,16 @@ pub static ID_GEN: once_cell::sync::Lazy<IdGen> = once_cell::sync::Lazy::new(||
     gen
 });
 
-/// 注意，只能用于同一程序内的ID生成
+/// 由 `seed` 派生第 `k` 个子种子（splitmix64），用于给多个膜或副本分配互不相关的种子
+pub fn derive_seed(seed: u64, k: u64) -> u64 {
+    let mut z = seed.wrapping_add(k.wrapping_add(1).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// 注意，只能用于同一程序内的ID生成  
+/// 计数器是全局的，生成的 ID 与调用顺序有关且不能重置，规则中创建对象时应使用膜的 [`TagGen`]
 pub struct IdGen {
 
 }
@@ -283,8 +412,100 @@ impl IdGen {
     pub fn next_i32_id() -> i32 { 
         COUNTER_I32.fetch_add(1, Ordering::Relaxed) 
     }
+    /// 全局唯一ID生成，用于跨进程交换的对象，随机
+    pub fn next_uuid() -> UuidTag {
+        UuidTag::new()
+    }
+}
+
+/// 单个系统（膜或膜系统）拥有的 tag 生成器，可以重置，也可以指定起始值  
+/// 与 [`IdGen`] 不同，生成的 tag 只取决于该系统自身的运行过程
+#[derive(Debug, Clone, Default)]
+pub struct TagGen {
+    start: u64,
+    next: u64,
+    uuid_seed: u64
+}
+
+impl TagGen {
+    pub fn new() -> Self {
+        Self::starting_at(0)
+    }
+
+    /// 从 `start` 开始生成，不同系统使用不重叠的区间时 tag 在系统之间也不会重复
+    pub fn starting_at(start: u64) -> Self {
+        Self { start, next: start, uuid_seed: 0 }
+    }
+
+    /// 下一个将生成的值
+    pub fn peek(&self) -> u64 {
+        self.next
+    }
+
+    /// 回到起始值
+    pub fn reset(&mut self) {
+        self.next = self.start;
+    }
+
+    /// 重新指定起始值并重置
+    pub fn reseed(&mut self, start: u64) {
+        self.start = start;
+        self.next = start;
+    }
+
+    /// 生成 uuid 使用的种子，见 [`TagLane::next_uuid`]
+    pub fn set_uuid_seed(&mut self, seed: u64) {
+        self.uuid_seed = seed;
+    }
+
+    /// 第 `k` 个（共 `n` 个）并行执行的规则使用的通道，各通道生成的值互不相同
+    pub fn lane(&self, k: usize, n: usize) -> TagLane {
+        TagLane { base: self.next + k as u64, stride: n.max(1) as u64, used: 0, uuid_seed: self.uuid_seed }
+    }
+
+    /// 跳过通道已经生成的值
+    pub fn advance(&mut self, lanes: &[TagLane]) {
+        if let Some(end) = lanes.iter().filter(|l| l.used > 0).map(|l| l.base + (l.used - 1) * l.stride + 1).max() {
+            self.next = self.next.max(end);
+        }
+    }
+}
+
+/// 从 [`TagGen`] 分出的通道，规则的影响通过 [`crate::core::RequestedObj::ids`] 使用  
+/// 并行执行时每条规则使用自己的通道，生成的 tag 与线程的调度无关
+#[derive(Debug, Clone)]
+pub struct TagLane {
+    base: u64,
+    stride: u64,
+    used: u64,
+    uuid_seed: u64
+}
+
+impl Default for TagLane {
+    fn default() -> Self {
+        TagGen::new().lane(0, 1)
+    }
 }
 
+impl TagLane {
+    pub fn next_u64(&mut self) -> u64 {
+        let v = self.base + self.used * self.stride;
+        self.used += 1;
+        v
+    }
+
+    /// 生成 `T` 类型的 tag，超出 `T` 的范围时 panic
+    pub fn next_tag<T: TryFrom<u64>>(&mut self) -> T {
+        let v = self.next_u64();
+        T::try_from(v).unwrap_or_else(|_| panic!("tag {} is out of range of {}", v, std::any::type_name::<T>()))
+    }
+
+    /// 由通道的下一个值和生成器的种子生成 uuid，种子和运行过程相同时结果相同
+    pub fn next_uuid(&mut self) -> UuidTag {
+        let v = self.next_u64();
+        UuidTag::from_rng(&mut StdRng::seed_from_u64(derive_seed(self.uuid_seed, v)))
+    }
+}
 
 /// 创建GPU缓冲区(来自数据)
 pub fn gpu_buffer_from<T: krnl::scalar::Scalar>(data: Vec<T>) -> Option<BufferBase<BufferRepr<T>>> {
@@ -7,5 +7,7 @@ pub mod rules;
 pub mod mems;
 pub mod helpers;
 pub mod gpu;
+pub mod formats;
+pub mod runs;
 
 pub use meme_derive;
\ No newline at end of file
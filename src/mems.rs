@@ -1,7 +1,17 @@
 // Copyright 2024 Junshuang Hu
 pub mod basic;
+pub mod hierarchy;
+pub mod analysis;
+pub mod invariant;
+pub mod conflict;
+pub mod cycle;
+pub mod stats;
+pub mod stochastic;
+pub mod agenda;
+pub mod explore;
+pub mod temporal;
 
-// todo： 膜管理器
+// todo： 膜管理器 -ok
 
 pub struct CPUEnvRegion {
     
@@ -0,0 +1,74 @@
+// Copyright 2024 Junshuang Hu
+//! 计划在未来的模拟时间出现的对象
+//!
+//! 规则的影响可以延迟产生对象（见 [`crate::core::OperationEffect::ScheduleObj`]），
+//! 这些对象按出现的时间保存在膜的日程中，模拟时间到达后加入膜
+
+use std::any::TypeId;
+use std::collections::VecDeque;
+
+use crate::core::PObj;
+
+/// 计划出现的对象
+#[derive(Debug)]
+pub enum Scheduled<OT, U> {
+    Untagged(TypeId, U),
+    Obj(PObj<OT, U>)
+}
+
+/// 按时间排序的日程，时间相同的对象按加入的顺序出现
+#[derive(Debug)]
+pub struct Agenda<OT, U> {
+    items: VecDeque<(f64, Scheduled<OT, U>)>
+}
+
+impl<OT, U> Default for Agenda<OT, U> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<OT, U> Agenda<OT, U> {
+    pub fn new() -> Self {
+        Self { items: VecDeque::new() }
+    }
+
+    /// 计划在时间 `at` 出现 `item`
+    pub fn schedule(&mut self, at: f64, item: Scheduled<OT, U>) {
+        let pos = self.items.partition_point(|(t, _)| *t <= at);
+        self.items.insert(pos, (at, item));
+    }
+
+    /// 最早出现的对象的时间
+    pub fn next_time(&self) -> Option<f64> {
+        self.items.front().map(|(t, _)| *t)
+    }
+
+    /// 取出时间不晚于 `t` 的对象
+    pub fn due(&mut self, t: f64) -> Vec<Scheduled<OT, U>> {
+        let n = self.items.partition_point(|(at, _)| *at <= t);
+        self.items.drain(..n).map(|(_, item)| item).collect()
+    }
+
+    /// 所有计划中的对象及其时间
+    pub fn iter(&self) -> impl Iterator<Item = &(f64, Scheduled<OT, U>)> {
+        self.items.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
+
+impl<OT, U> IntoIterator for Agenda<OT, U> {
+    type Item = (f64, Scheduled<OT, U>);
+    type IntoIter = std::collections::vec_deque::IntoIter<(f64, Scheduled<OT, U>)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.items.into_iter()
+    }
+}
@@ -0,0 +1,265 @@
+// Copyright 2024 Junshuang Hu
+//! 死规则与无用类型分析
+//!
+//! 根据规则的条件和影响中声明的产物（见 [`crate::core::EffectSignature`]）做可达性分析，不运行膜：
+//! 从膜内初始存在的对象类型出发，反复加入可能执行的规则产生的类型，直到不动点
+//! - 死规则：需要的类型既不在初始对象中，也没有任何可能执行的规则会产生
+//! - 无用类型：可能执行的规则会产生，但没有任何可能执行的规则消耗
+//!
+//! 分析是保守的近似：只看类型是否可能出现，不看数量；创建 tagged 对象的操作只按声明的类型计算，
+//! 未声明产物的操作记录在 [`DeadCode::undeclared`] 中；经通道进入膜的对象不在考虑范围内
+//! 规则需要指定 tag 的对象时，若该对象初始不存在，只要同一膜内有可能执行的规则创建对象就认为可能出现
+
+use std::any::TypeId;
+use std::fmt::Debug;
+use std::hash::Hash;
+
+use ahash::{AHashMap, AHashSet};
+use krnl::scalar::Scalar;
+
+use crate::core::{ICondition, IObjStat, IRuleEffect, IRuleStat, ITaggedStore, IUntaggedStore, MemTarget, ObjType, OperationEffect, TaggedPresenceInfo};
+use crate::mems::basic::BasicMem;
+use crate::mems::hierarchy::MemHierarchy;
+use crate::objs::BasicObjStore;
+use crate::rules::BasicRuleStore;
+
+/// 分析结果，`K` 为规则的标识
+#[derive(Debug, Clone)]
+pub struct DeadCode<K> {
+    /// 永远不会执行的规则
+    pub dead_rules: Vec<K>,
+    /// 会被产生但从不被消耗的类型
+    pub unconsumed: Vec<ObjType>,
+    /// 有创建对象的操作但没有声明产物的规则，这些规则可能让分析结果偏悲观
+    pub undeclared: Vec<K>
+}
+
+impl<K> DeadCode<K> {
+    pub fn is_clean(&self) -> bool {
+        self.dead_rules.is_empty() && self.unconsumed.is_empty()
+    }
+}
+
+struct RuleInfo<K, OT> {
+    key: K,
+    needs: Vec<TypeId>,
+    tags: Vec<OT>,
+    consumes: Vec<TypeId>,
+    products: Vec<(ObjType, MemTarget)>,
+    creates: bool,
+    undeclared: bool,
+    dissolves: bool
+}
+
+struct Node<K, OT> {
+    label: usize,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    avail: AHashSet<TypeId>,
+    tags: AHashSet<OT>,
+    creating: bool,
+    rules: Vec<RuleInfo<K, OT>>,
+    live: Vec<bool>
+}
+
+impl<K, OT> Node<K, OT>
+where OT: Hash + Eq {
+    fn enabled(&self, r: &RuleInfo<K, OT>) -> bool {
+        r.needs.iter().all(|t| self.avail.contains(t))
+            && (self.creating || r.tags.iter().all(|t| self.tags.contains(t)))
+    }
+
+    fn may_dissolve(&self) -> bool {
+        self.rules.iter().zip(self.live.iter()).any(|(r, l)| *l && r.dissolves)
+    }
+}
+
+fn collect_rules<K, RT, OT, U, F>(rules: &BasicRuleStore<RT, OT, U>, key: F) -> Vec<RuleInfo<K, OT>>
+where
+RT: Clone + Hash + Eq + Send + Sync + Debug + 'static,
+OT: Clone + Hash + Eq + Send + Sync + Debug + 'static,
+U: Scalar,
+F: Fn(RT) -> K {
+    rules.rules().enumerate().map(|(i, r)| {
+        let mut info = RuleInfo {
+            key: key(r.obj_tag().clone()),
+            needs: Vec::new(), tags: Vec::new(), consumes: Vec::new(), products: Vec::new(),
+            creates: false, undeclared: false, dissolves: false
+        };
+        if let Some(c) = rules.condition_at(i) {
+            for u in c.untagged().iter().flatten() {
+                info.needs.push(u.ty.tid);
+            }
+            for p in c.tagged().iter().flatten() {
+                match &p.info {
+                    TaggedPresenceInfo::OfTag(t) => info.tags.push(t.clone()),
+                    TaggedPresenceInfo::RandTags((ty, _)) => info.needs.push(ty.tid),
+                }
+            }
+        }
+        info.consumes.extend(info.needs.iter().copied());
+        if let Some(e) = rules.effect_at(i) {
+            let sigs = e.signatures();
+            for (k, op) in e.effects().iter().flatten().enumerate() {
+                match op {
+                    OperationEffect::CreateObj(_) | OperationEffect::CreateObjs(_) | OperationEffect::ScheduleObj(_) => {
+                        info.creates = true;
+                        match sigs.get(k).filter(|s| !s.produces.is_empty()) {
+                            Some(s) => info.products.extend(s.produces.iter().map(|t| (t.clone(), MemTarget::Here))),
+                            None => info.undeclared = true,
+                        }
+                    },
+                    OperationEffect::IncreaseObjUntagged((t, _)) | OperationEffect::ScheduleUntagged((t, _, _)) => info.products.push((t.clone(), MemTarget::Here)),
+                    OperationEffect::SendObjUntagged((t, _, target)) => info.products.push((t.clone(), target.clone())),
+                    OperationEffect::DecreaseObjUntagged((t, _)) | OperationEffect::RemoveObjUntagged(t) => info.consumes.push(t.tid),
+                    OperationEffect::DissolveMem => info.dissolves = true,
+                    _ => {}
+                }
+                if let Some(s) = sigs.get(k) {
+                    info.consumes.extend(s.consumes.iter().map(|t| t.tid));
+                }
+            }
+        }
+        info
+    }).collect()
+}
+
+fn node_of<K, T, OT, RT, U, F>(mem: &BasicMem<T, OT, RT, U>, label: usize, parent: Option<usize>, children: Vec<usize>, key: F) -> Node<K, OT>
+where
+T: Clone + Hash + Eq + Debug + 'static,
+OT: Clone + Hash + Eq + Send + Sync + Debug + 'static,
+RT: Clone + Hash + Eq + Send + Sync + Debug + 'static,
+U: Scalar,
+F: Fn(RT) -> K {
+    let objs: &BasicObjStore<OT, U> = mem.objs();
+    let avail = (0..objs.type_count())
+        .filter_map(|i| objs.tid_at(i).copied())
+        .filter(|t| objs.get_u(t).is_some_and(|a| a > U::zero()))
+        .chain(objs.iter().map(|o| o.obj_type().tid))
+        .collect();
+    let tags = objs.iter().map(|o| o.obj_tag().clone()).collect();
+    let rules = collect_rules(mem.rules(), key);
+    let live = vec![false; rules.len()];
+    Node { label, parent, children, avail, tags, creating: false, rules, live }
+}
+
+/// `i` 膜可能的子膜：包括可能溶解的子膜的子膜
+fn reachable_children<K, OT: Hash + Eq>(nodes: &[Node<K, OT>], i: usize, out: &mut Vec<usize>) {
+    for c in nodes[i].children.iter() {
+        out.push(*c);
+        if nodes[*c].may_dissolve() {
+            reachable_children(nodes, *c, out);
+        }
+    }
+}
+
+fn solve<K: Clone, OT: Clone + Hash + Eq>(mut nodes: Vec<Node<K, OT>>) -> DeadCode<K> {
+    let mut types: AHashMap<TypeId, ObjType> = AHashMap::new();
+    loop {
+        let mut changed = false;
+        for n in nodes.iter_mut() {
+            for k in 0..n.rules.len() {
+                if !n.live[k] && n.enabled(&n.rules[k]) {
+                    n.live[k] = true;
+                    changed = true;
+                }
+            }
+        }
+
+        let mut deliveries: Vec<(usize, TypeId)> = Vec::new();
+        let mut creating = Vec::new();
+        let mut merges = Vec::new();
+        for (i, n) in nodes.iter().enumerate() {
+            for r in n.rules.iter().zip(n.live.iter()).filter(|(_, l)| **l).map(|(r, _)| r) {
+                for (ty, target) in r.products.iter() {
+                    let dests = match target {
+                        MemTarget::Here => vec![i],
+                        MemTarget::Out => n.parent.into_iter().collect(),
+                        MemTarget::In(label) => {
+                            let mut cs = Vec::new();
+                            reachable_children(&nodes, i, &mut cs);
+                            cs.retain(|c| nodes[*c].label == *label);
+                            cs
+                        }
+                    };
+                    if !dests.is_empty() {
+                        types.entry(ty.tid).or_insert_with(|| ty.clone());
+                    }
+                    deliveries.extend(dests.into_iter().map(|d| (d, ty.tid)));
+                }
+                if r.creates {
+                    creating.push(i);
+                }
+                if let (true, Some(p)) = (r.dissolves, n.parent) {
+                    merges.push((i, p));
+                }
+            }
+        }
+        for (d, t) in deliveries {
+            changed |= nodes[d].avail.insert(t);
+        }
+        for i in creating {
+            changed |= !std::mem::replace(&mut nodes[i].creating, true);
+        }
+        for (i, p) in merges {
+            let (avail, tags, c) = (nodes[i].avail.clone(), nodes[i].tags.clone(), nodes[i].creating);
+            let parent = &mut nodes[p];
+            for t in avail {
+                changed |= parent.avail.insert(t);
+            }
+            for t in tags {
+                changed |= parent.tags.insert(t);
+            }
+            changed |= c && !std::mem::replace(&mut parent.creating, true);
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    let mut consumed = AHashSet::new();
+    let mut res = DeadCode { dead_rules: Vec::new(), unconsumed: Vec::new(), undeclared: Vec::new() };
+    for n in nodes.iter() {
+        for (r, l) in n.rules.iter().zip(n.live.iter()) {
+            if !*l {
+                res.dead_rules.push(r.key.clone());
+                continue;
+            }
+            consumed.extend(r.consumes.iter().copied());
+            if r.undeclared {
+                res.undeclared.push(r.key.clone());
+            }
+        }
+    }
+    res.unconsumed = types.into_values().filter(|t| !consumed.contains(&t.tid)).collect();
+    res.unconsumed.sort_by_key(|t| t.name);
+    res
+}
+
+/// 分析单个膜，膜被视为与外界隔离：发送到其他膜的对象不会回到该膜
+pub fn analyze_mem<T, OT, RT, U>(mem: &BasicMem<T, OT, RT, U>) -> DeadCode<RT>
+where
+T: Clone + Hash + Eq + Debug + 'static,
+OT: Clone + Hash + Eq + Send + Sync + Debug + 'static,
+RT: Clone + Hash + Eq + Send + Sync + Debug + 'static,
+U: Scalar {
+    solve(vec![node_of(mem, 0, None, Vec::new(), |t| t)])
+}
+
+/// 分析膜层级中所有未溶解的膜，考虑膜之间的发送与溶解，规则以 `(膜的下标, 规则的 tag)` 标识
+/// 发送到环境的对象不算作产生
+pub fn analyze_hierarchy<T, OT, RT, U>(h: &MemHierarchy<T, OT, RT, U>) -> DeadCode<(usize, RT)>
+where
+T: Clone + Hash + Eq + Debug + 'static,
+OT: Clone + Hash + Eq + Send + Sync + Debug + 'static,
+RT: Clone + Hash + Eq + Send + Sync + Debug + 'static,
+U: Scalar {
+    let alive = h.alive().collect::<Vec<_>>();
+    let pos_of = |i: usize| alive.iter().position(|a| *a == i);
+    let nodes = alive.iter().map(|i| {
+        let n = h.node(*i).unwrap();
+        let children = n.children().iter().filter_map(|c| pos_of(*c)).collect();
+        node_of(&n.mem, n.label(), n.parent().and_then(pos_of), children, |t| (*i, t))
+    }).collect();
+    solve(nodes)
+}
@@ -5,10 +5,18 @@ use crate::rules::BasicEffect;
 use crate as meme;
 use crate::core::*;
 use crate::meme_derive::*;
+use crate::mems::agenda::{Agenda, Scheduled};
+use crate::mems::conflict::{Candidate, ConflictResolver, RandomResolver};
+use crate::mems::cycle::{fingerprint, snapshot, CycleDetector, CycleReport, Snapshot};
+use crate::mems::invariant::{Invariant, InvariantViolation};
+use crate::mems::stochastic::{maximal_multiset, poisson, propensity, sample_next, select_tau, stoichiometry, EvolveMode, TauLeap};
+use crate::mems::stats::{diagnose, FailReason, RuleStats};
+use crate::helpers::{derive_seed, TagGen, TagLane};
 use crate::objs::BasicObjStore;
 use crate::rules::BasicRuleStore;
 
 use std::any::TypeId;
+use std::collections::VecDeque;
 use std::fmt::Debug;
 use std::hash::Hash;
 use std::sync::Arc;
@@ -16,9 +24,10 @@ use std::sync::Mutex;
 use std::time::Instant;
 use log::{log, Level};
 
+use ahash::{AHashMap, AHashSet};
 use krnl::scalar::Scalar;
-use rand::seq::SliceRandom;
-use rand::thread_rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use rayon::prelude::*;
 
 pub type PBasicRule<RT, OT, U> = PRule<RT, OT, U, U, BasicEffect<OT, U>, BasicCondition<OT, U>>;
@@ -28,17 +37,26 @@ pub struct EPOut<T,U> {
     pub to_add: Vec<PObj<T,U>>,
     pub to_remove: Vec<T>,
     pub to_inc: Vec<(TypeId, U)>,
-    pub to_dec: Vec<(TypeId, U)>
+    pub to_dec: Vec<(TypeId, U)>,
+    pub to_send: Vec<(MemTarget, TypeId, U)>,
+    pub dissolve: bool,
+    /// 延迟出现的对象及延迟的时间
+    pub to_schedule: Vec<(f64, Scheduled<T, U>)>,
+    pub ids: TagLane
 }
 
 impl<T, U> EPOut<T, U> {
     pub fn new() -> Self {
-        Self { to_add: Vec::new(), to_remove: Vec::new(), to_inc: Vec::new(), to_dec: Vec::new() }
+        Self { 
+            to_add: Vec::new(), to_remove: Vec::new(), to_inc: Vec::new(), to_dec: Vec::new(),
+            to_send: Vec::new(), dissolve: false, to_schedule: Vec::new(), ids: TagLane::default()
+        }
     }
 
     pub fn is_empty(&self) -> bool {
         self.to_add.is_empty() && self.to_remove.is_empty() &&
-        self.to_inc.is_empty() && self.to_dec.is_empty()
+        self.to_inc.is_empty() && self.to_dec.is_empty() &&
+        self.to_send.is_empty() && self.to_schedule.is_empty() && !self.dissolve
     }
 }
 
@@ -54,10 +72,26 @@ U: Scalar {
 
     ready: bool,
     no_parallel: bool,
+    incremental: bool,
 
     objs: BasicObjStore<OT, U>,
     rules: BasicRuleStore<RT, OT, U>,
 
+    outbox: Vec<(MemTarget, TypeId, U)>,
+    dissolving: bool,
+
+    steps: usize,
+    invariants: Vec<Invariant<OT, U>>,
+    violation: Option<InvariantViolation<RT>>,
+    last_fired: Vec<RT>,
+    cycles: Option<CycleDetector<RT, Snapshot<OT, U>>>,
+    stats: Option<AHashMap<RT, RuleStats<OT, U>>>,
+    rng: StdRng,
+    resolver: Box<dyn ConflictResolver<RT>>,
+    tags: TagGen,
+    mode: EvolveMode,
+    time: f64,
+    agenda: Agenda<OT, U>
 }
 
 impl<T, OT, RT, U> BasicMem<T, OT, RT, U>
@@ -68,32 +102,276 @@ RT: Clone + Hash + Eq + Send + Sync + Debug + 'static,
 U: Scalar
 {
     pub fn new(tag: T, no_parallel: bool) -> Self {
+        let mut rng = StdRng::from_entropy();
+        let mut tags = TagGen::new();
+        tags.set_uuid_seed(rng.gen());
         Self {
             tag,
             ready: false,
             no_parallel,
+            incremental: true,
             objs: BasicObjStore::new(),
-            rules:  BasicRuleStore::new()
+            rules:  BasicRuleStore::new(),
+            outbox: Vec::new(),
+            dissolving: false,
+            steps: 0,
+            invariants: Vec::new(),
+            violation: None,
+            last_fired: Vec::new(),
+            cycles: None,
+            stats: None,
+            rng,
+            resolver: Box::new(RandomResolver),
+            tags,
+            mode: EvolveMode::default(),
+            time: 0.0,
+            agenda: Agenda::new()
+        }
+    }
+
+    /// 是否不区分可并行的规则，全部按冲突规则依次执行
+    pub fn no_parallel(&self) -> bool {
+        self.no_parallel
+    }
+
+    /// 开启或关闭增量检查（默认开启），见 [`BasicRuleStore::check_on_changes_with`]
+    pub fn set_incremental_check(&mut self, on: bool) {
+        self.incremental = on;
+        self.rules.invalidate_checks();
+    }
+
+    /// 确认对象的变化，不经过增量检查时同时丢弃检查的缓存
+    fn dismiss_changes(&mut self, checked: bool) {
+        self.objs.dismiss();
+        if !checked {
+            self.rules.invalidate_checks();
+        }
+    }
+
+    /// 注册不变量，每一步应用影响后检查
+    pub fn add_invariant(&mut self, inv: Invariant<OT, U>) {
+        self.invariants.push(inv);
+    }
+
+    pub fn invariants(&self) -> &[Invariant<OT, U>] {
+        &self.invariants
+    }
+
+    /// 被违反的不变量的报告，膜因此停止后不会再演化
+    pub fn violation(&self) -> Option<&InvariantViolation<RT>> {
+        self.violation.as_ref()
+    }
+
+    /// 已应用规则的步数
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    /// 上一步中执行了的规则
+    pub fn last_fired(&self) -> &[RT] {
+        &self.last_fired
+    }
+
+    /// 开启或关闭环检测，开启后每一步记录格局的指纹与快照（见 [`snapshot`]），格局重复时膜停止运行
+    pub fn detect_cycles(&mut self, on: bool) {
+        self.cycles = on.then(CycleDetector::new);
+    }
+
+    /// 检测到的环
+    pub fn cycle(&self) -> Option<&CycleReport<RT>> {
+        self.cycles.as_ref().and_then(|d| d.report())
+    }
+
+    /// 设置随机数种子，膜中所有的随机选择（随机选取 tagged 对象、冲突规则的执行顺序）都由它决定  
+    /// 相同的种子、初始格局和规则会得到相同的运行过程，不设置时使用系统熵
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+        self.tags.set_uuid_seed(derive_seed(seed, u64::MAX));
+    }
+
+    /// 膜的 tag 生成器，规则的影响通过 [`RequestedObj::next_tag`] 使用
+    pub fn tag_gen(&self) -> &TagGen {
+        &self.tags
+    }
+
+    pub fn tag_gen_mut(&mut self) -> &mut TagGen {
+        &mut self.tags
+    }
+
+    /// 开启后新对象的 tag 与已有对象相同时拒绝新对象，而不是替换已有对象，见 [`BasicObjStore::set_collision_check`]
+    pub fn check_tag_collisions(&mut self, on: bool) {
+        self.objs.set_collision_check(on);
+    }
+
+    /// 被拒绝的对象的 tag
+    pub fn tag_collisions(&self) -> &[OT] {
+        self.objs.collisions()
+    }
+
+    /// 设置演化方式，见 [`crate::mems::stochastic`]
+    pub fn set_mode(&mut self, mode: EvolveMode) {
+        self.mode = mode;
+    }
+
+    pub fn mode(&self) -> EvolveMode {
+        self.mode
+    }
+
+    /// 延迟出现的对象
+    pub fn agenda(&self) -> &Agenda<OT, U> {
+        &self.agenda
+    }
+
+    /// 取出日程，用于膜溶解时转移到其他膜
+    pub fn take_agenda(&mut self) -> Agenda<OT, U> {
+        std::mem::take(&mut self.agenda)
+    }
+
+    /// 计划在模拟时间 `at` 出现 `item`，`at` 不晚于当前时间时在下一步结束时出现
+    pub fn schedule(&mut self, at: f64, item: Scheduled<OT, U>) {
+        self.agenda.schedule(at, item);
+    }
+
+    /// 模拟时间推进到 `t`（不会后退），并加入到期的对象
+    pub fn advance_to(&mut self, t: f64) {
+        self.time = self.time.max(t);
+        for item in self.agenda.due(self.time) {
+            match item {
+                Scheduled::Untagged(ty, a) => if let Err(e) = self.objs.increase(&ty, a) { e.log(); },
+                Scheduled::Obj(o) => self.objs.put(o),
+            }
+        }
+    }
+
+    /// 没有规则可以执行时跳到日程中下一个对象出现的时间，日程为空时返回 [`EmuStatus::Pause`]
+    fn idle(&mut self) -> EmuStatus {
+        self.last_fired.clear();
+        match self.agenda.next_time() {
+            Some(t) => {
+                self.advance_to(t);
+                EmuStatus::Continue
+            },
+            None => EmuStatus::Pause,
+        }
+    }
+
+    fn drain_scheduled(&mut self, out: &mut EPOut<OT, U>) {
+        for (delay, item) in out.to_schedule.drain(..) {
+            self.agenda.schedule(self.time + delay, item);
+        }
+    }
+
+    /// 设置冲突解决策略，默认为 [`RandomResolver`]
+    pub fn set_resolver<R: ConflictResolver<RT> + 'static>(&mut self, resolver: R) {
+        self.resolver = Box::new(resolver);
+    }
+
+    /// 开启或关闭规则执行统计，开启时会清空已有的统计  
+    /// 统计需要在每一步找出不能执行的规则的原因，会增加检查的开销  
+    /// 随机模式下倾向函数大于零的规则视为条件满足，执行的规则计入 `fired_parallel`
+    pub fn collect_stats(&mut self, on: bool) {
+        self.stats = on.then(AHashMap::new);
+    }
+
+    /// 规则 `t` 的执行统计
+    pub fn rule_stats(&self, t: &RT) -> Option<&RuleStats<OT, U>> {
+        self.stats.as_ref().and_then(|s| s.get(t))
+    }
+
+    /// 所有规则的执行统计
+    pub fn all_rule_stats(&self) -> impl Iterator<Item = (&RT, &RuleStats<OT, U>)> {
+        self.stats.iter().flatten()
+    }
+
+    fn record_check(&mut self, applicable: &[usize], kept: &[usize]) {
+        let Some(stats) = self.stats.as_mut() else { return; };
+        let (applicable, kept) = (applicable.iter().collect::<AHashSet<_>>(), kept.iter().collect::<AHashSet<_>>());
+        for i in 0..self.rules.conditions_count() {
+            let Some(t) = self.rules.tag_at(i) else { continue; };
+            let st = stats.entry(t).or_default();
+            st.checked += 1;
+            if applicable.contains(&i) {
+                st.applicable += 1;
+                if !kept.contains(&i) {
+                    st.last_failure = Some(FailReason::LowerPriority);
+                }
+            } else if let Some(c) = self.rules.condition_at(i) {
+                st.last_failure = diagnose(c, &self.objs).or_else(|| {
+                    self.rules.probability_at(i).is_some_and(|p| p < 1.0).then_some(FailReason::NotDrawn)
+                });
+            }
+        }
+    }
+
+    fn record_fired(&mut self, parallel: &[RT], conflict: &[RT], fired_conflict: &[RT]) {
+        let Some(stats) = self.stats.as_mut() else { return; };
+        for t in parallel {
+            stats.entry(t.clone()).or_default().fired_parallel += 1;
+        }
+        for t in conflict {
+            let st = stats.entry(t.clone()).or_default();
+            if fired_conflict.contains(t) {
+                st.fired_conflict += 1;
+            } else {
+                st.lost_conflict += 1;
+                st.last_failure = Some(FailReason::LostConflict);
+            }
         }
     }
 
+    pub fn objs(&self) -> &BasicObjStore<OT, U> {
+        &self.objs
+    }
+
+    /// 通过存储的接口做出的修改会被记录，用于增量检查
+    pub fn objs_mut(&mut self) -> &mut BasicObjStore<OT, U> {
+        &mut self.objs
+    }
+
+    pub fn rules(&self) -> &BasicRuleStore<RT, OT, U> {
+        &self.rules
+    }
+
+    /// 声明规则 `higher` 优先于规则 `lower`，见 [`BasicRuleStore::add_priority`]
+    pub fn add_priority(&mut self, higher: RT, lower: RT) {
+        self.rules.add_priority(higher, lower);
+    }
+
+    /// 取走本膜的全部对象，用于溶解
+    pub fn take_objs(&mut self) -> BasicObjStore<OT, U> {
+        std::mem::take(&mut self.objs)
+    }
+
+    /// 取走上一步中规则发送到其他膜的对象
+    pub fn take_outbox(&mut self) -> Vec<(MemTarget, TypeId, U)> {
+        std::mem::take(&mut self.outbox)
+    }
+
+    /// 上一步中是否有规则溶解了本膜
+    pub fn is_dissolving(&self) -> bool {
+        self.dissolving
+    }
+
     pub fn init(&mut self, mut tagged: Vec<PObj<OT, U>>, mut untagged: Vec<(TypeId, U)>, mut rules: Vec<PBasicRule<RT, OT, U>>) {
         while let Some(o) = tagged.pop() {
-            self.objs.add_or_update(o.obj_tag().clone(), o);
+            self.objs.put(o);
         }
         while let Some(r) = rules.pop() {
             self.rules.add_or_update(r.obj_tag().clone(), r);
         }
         while let Some((ty, amount)) = untagged.pop() {
-            self.objs.increase(&ty, amount);
+            if let Err(e) = self.objs.increase(&ty, amount) { e.log(); }
         }
         self.ready = true;
     }
 
+    /// 执行影响中的操作，结果写入 `out`  
+    /// 调试构建中检查创建对象的操作只产生 `sigs` 中声明的类型
     pub fn effect_proc(
-        es: &Vec<OperationEffect<OT, U>>, mut req: RequestedObj<'_, OT, U>, stop_mux: &Arc<Mutex<bool>>,
-        out: &mut EPOut<OT, U>) {
-        for e in es {
+        es: &[OperationEffect<OT, U>], sigs: &[EffectSignature], mut req: RequestedObj<'_, OT, U>,
+        stop_mux: &Arc<Mutex<bool>>, out: &mut EPOut<OT, U>) {
+        for (i, e) in es.iter().enumerate() {
+            let (from, scheduled_from) = (out.to_add.len(), out.to_schedule.len());
             match e {
                 OperationEffect::CreateObj(f) => {
                     out.to_add.push(f(&mut req));
@@ -119,12 +397,368 @@ U: Scalar
                 OperationEffect::RemoveObjUntagged(_) => {
 
                 }
+                OperationEffect::SendObjUntagged((t, u, target)) => {
+                    out.to_send.push((target.clone(), t.tid, *u));
+                },
+                OperationEffect::ScheduleObj((f, delay)) => {
+                    out.to_schedule.push((*delay, Scheduled::Obj(f(&mut req))));
+                },
+                OperationEffect::ScheduleUntagged((t, u, delay)) => {
+                    out.to_schedule.push((*delay, Scheduled::Untagged(t.tid, *u)));
+                },
+                OperationEffect::DissolveMem => {
+                    out.dissolve = true;
+                },
                 OperationEffect::Stop => {
                     *stop_mux.lock().unwrap() = true;
                 },
                 _ => {}
             }
+            if cfg!(debug_assertions) {
+                let scheduled = out.to_schedule[scheduled_from..].iter().filter_map(|(_, s)| match s {
+                    Scheduled::Obj(o) => Some(o),
+                    Scheduled::Untagged(..) => None,
+                });
+                for o in out.to_add[from..].iter().chain(scheduled) {
+                    let ty = o.obj_type();
+                    if sigs.get(i).is_none_or(|s| !s.declares_product(&ty.tid)) {
+                        panic!("operation {} created obj {:?} of undeclared type {}", i, o.obj_tag(), ty.name);
+                    }
+                }
+            }
+        }
+        out.ids = req.ids;
+    }
+
+    /// Gillespie 直接法的一步：按倾向函数抽取一条规则执行，模拟时间按指数分布推进
+    fn evolve_gillespie(&mut self) -> EmuStatus {
+        let props = (0..self.rules.conditions_count())
+            .map(|i| match (self.rules.rate_at(i), self.rules.condition_at(i)) {
+                (Some(k), Some(c)) => propensity(c, &self.objs, k),
+                _ => 0.0,
+            })
+            .collect::<Vec<_>>();
+        if self.stats.is_some() {
+            let applicable = (0..props.len()).filter(|i| props[*i] > 0.0).collect::<Vec<_>>();
+            self.record_check(&applicable, &applicable);
+        }
+        let Some((rule_index, tau)) = sample_next(&props, &mut self.rng) else {
+            return self.idle();
+        };
+        // 日程中的对象先于下一次反应出现时先加入对象，由于指数分布无记忆，之后重新抽取
+        if self.agenda.next_time().is_some_and(|t| t <= self.time + tau) {
+            return self.idle();
+        }
+        self.begin_step();
+        self.time += tau;
+        let stop = Arc::new(Mutex::new(false));
+        let info = ExecutableInfo { rule_index, rand_tags: None, requested_tag: None, skip_take: false };
+        let fired = self.execute_dynamic(VecDeque::from([info]), &stop);
+        self.record_fired(&fired, &[], &[]);
+        self.finish_step(fired, &stop)
+    }
+
+    /// tau-leaping 的一步：每条规则执行 Poisson(a·tau) 次，出现负数量时 tau 减半重试  
+    /// 有规则不能按化学计量批量执行或 tau 太小时退回直接法
+    fn evolve_tau_leap(&mut self, cfg: TauLeap) -> EmuStatus {
+        let mut rated = Vec::new();
+        for i in 0..self.rules.conditions_count() {
+            let (Some(k), Some(c), Some(e)) = (self.rules.rate_at(i), self.rules.condition_at(i), self.rules.effect_at(i)) else {
+                continue;
+            };
+            match stoichiometry(c, e) {
+                Some(st) => rated.push((i, k, st)),
+                None => return self.evolve_gillespie(),
+            }
+        }
+        let props = rated.par_iter()
+            .map(|(i, k, _)| self.rules.condition_at(*i).map_or(0.0, |c| propensity(c, &self.objs, *k)))
+            .collect::<Vec<_>>();
+        let a0 = props.iter().sum::<f64>();
+        let applicable = if self.stats.is_some() {
+            rated.iter().zip(props.iter()).filter(|(_, a)| **a > 0.0).map(|((i, _, _), _)| *i).collect()
+        } else {
+            Vec::new()
+        };
+        if a0 <= 0.0 {
+            self.record_check(&applicable, &applicable);
+            return self.idle();
+        }
+        let stoich = rated.iter().map(|(_, _, st)| st).collect::<Vec<_>>();
+        let amount = |ty: &TypeId| self.objs.get_u(ty).map_or(0.0, |a| a.cast::<f64>());
+        let mut tau = select_tau(&props, &stoich, amount, cfg.epsilon);
+        if !tau.is_finite() || tau < cfg.ssa_threshold / a0 {
+            return self.evolve_gillespie();
+        }
+        if let Some(t) = self.agenda.next_time() {
+            tau = tau.min(t - self.time).max(0.0);
+        }
+        let mut leap = None;
+        for _ in 0..=cfg.max_retries {
+            let seed = self.rng.gen::<u64>();
+            let counts = props.par_iter()
+                .enumerate()
+                .map(|(j, a)| poisson(a * tau, &mut StdRng::seed_from_u64(derive_seed(seed, j as u64))))
+                .collect::<Vec<_>>();
+            let mut delta: AHashMap<TypeId, f64> = AHashMap::new();
+            for (n, st) in counts.iter().zip(stoich.iter()).filter(|(n, _)| **n > 0) {
+                for (ty, v) in st.change.iter() {
+                    *delta.entry(*ty).or_insert(0.0) += *n as f64 * v;
+                }
+            }
+            if delta.iter().all(|(ty, d)| amount(ty) + d >= 0.0) {
+                leap = Some((counts, delta));
+                break;
+            }
+            tau /= 2.0;
+        }
+        let Some((counts, delta)) = leap else {
+            return self.evolve_gillespie();
+        };
+        self.record_check(&applicable, &applicable);
+
+        self.begin_step();
+        for (ty, d) in delta {
+            let res = if d > 0.0 {
+                self.objs.increase(&ty, d.cast::<U>())
+            } else if d < 0.0 {
+                self.objs.decrease(&ty, (-d).cast::<U>())
+            } else {
+                continue;
+            };
+            if let Err(e) = res { e.log(); }
+        }
+        let mut fired = Vec::new();
+        for ((i, _, st), n) in rated.iter().zip(counts).filter(|(_, n)| *n > 0) {
+            let times = (n as f64).cast::<U>();
+            for (target, ty, a) in st.send.iter() {
+                self.outbox.push((target.clone(), *ty, *a * times));
+            }
+            self.dissolving |= st.dissolve;
+            fired.extend(self.rules.tag_at(*i));
+        }
+        self.record_fired(&fired, &[], &[]);
+        self.time += tau;
+        self.advance_to(self.time);
+        let stop = Arc::new(Mutex::new(false));
+        self.finish_step(fired, &stop)
+    }
+
+    /// 极大并行的一步（P 系统语义），有规则不能按化学计量批量执行时返回 [`None`]，由调用者按默认方式执行本步
+    fn evolve_multiset(&mut self) -> Option<EmuStatus> {
+        let mut stoich = Vec::new();
+        for i in 0..self.rules.conditions_count() {
+            let (Some(c), Some(e)) = (self.rules.condition_at(i), self.rules.effect_at(i)) else {
+                continue;
+            };
+            stoich.push((i, stoichiometry(c, e)?));
+        }
+        self.dismiss_changes(false);
+        let reqs = (0..self.rules.conditions_count())
+            .map(|i| self.rules.condition_at(i).map_or(Vec::new(), |c| {
+                c.untagged().iter().flatten().map(|u| (u.ty.tid, u.amount.cast::<f64>())).collect::<Vec<_>>()
+            }))
+            .collect::<Vec<_>>();
+        let amount = |ty: &TypeId| self.objs.get_u(ty).map_or(0.0, |a| a.cast::<f64>());
+        // 步开始时可以执行的规则，按概率抽取后再按优先级筛选
+        let mut applicable = VecDeque::<ExecutableInfo<OT>>::new();
+        for (i, _) in stoich.iter() {
+            if reqs[*i].iter().all(|(ty, a)| amount(ty) >= *a) && self.rules.draw_at(*i, &mut self.rng) {
+                applicable.push_back(ExecutableInfo { rule_index: *i, rand_tags: None, requested_tag: None, skip_take: false });
+            }
+        }
+        let found = if self.stats.is_some() { applicable.iter().map(|e| e.rule_index).collect() } else { Vec::new() };
+        let mut executable = ExecutableRules { parallel_executable: Some(applicable), conflict_executable: None };
+        executable.retain_top_priority(|i| self.rules.priority_at(i).unwrap_or(0));
+        executable.retain_unsuppressed(|i, j| self.rules.outranks(i, j));
+        let kept = executable.parallel_executable.into_iter().flatten().map(|e| e.rule_index).collect::<Vec<_>>();
+        let chosen = stoich.into_iter().filter(|(i, _)| kept.contains(i)).collect::<Vec<_>>();
+        let counts = maximal_multiset(
+            &chosen.iter().map(|(i, _)| reqs[*i].clone()).collect::<Vec<_>>(),
+            &chosen.iter().map(|(_, st)| st.dissolve).collect::<Vec<_>>(),
+            amount,
+            &mut self.rng
+        );
+        self.record_check(&found, &kept);
+        if counts.iter().all(|n| *n == 0) {
+            return Some(self.idle());
+        }
+
+        self.begin_step();
+        let mut delta: AHashMap<TypeId, f64> = AHashMap::new();
+        let mut fired = Vec::new();
+        for ((i, st), n) in chosen.iter().zip(counts).filter(|(_, n)| *n > 0) {
+            for (ty, v) in st.change.iter() {
+                *delta.entry(*ty).or_insert(0.0) += n as f64 * v;
+            }
+            let times = (n as f64).cast::<U>();
+            for (target, ty, a) in st.send.iter() {
+                self.outbox.push((target.clone(), *ty, *a * times));
+            }
+            self.dissolving |= st.dissolve;
+            fired.extend(self.rules.tag_at(*i));
+        }
+        self.record_fired(&fired, &[], &[]);
+        // 先减少再增加，需求已经按步开始时的数量保留，减少不会失败
+        let mut delta = delta.into_iter().filter(|(_, d)| *d != 0.0).collect::<Vec<_>>();
+        delta.sort_by(|a, b| a.1.total_cmp(&b.1));
+        for (ty, d) in delta {
+            let res = if d > 0.0 {
+                self.objs.increase(&ty, d.cast::<U>())
+            } else {
+                self.objs.decrease(&ty, (-d).cast::<U>())
+            };
+            if let Err(e) = res { e.log(); }
+        }
+        let dt = fired.iter()
+            .filter_map(|t| self.rules.pos_of(t).and_then(|i| self.rules.duration_at(i)))
+            .fold(0.0, f64::max);
+        self.advance_to(self.time + dt);
+        let stop = Arc::new(Mutex::new(false));
+        Some(self.finish_step(fired, &stop))
+    }
+
+    /// 一步开始执行规则前的准备
+    fn begin_step(&mut self) {
+        for inv in self.invariants.iter_mut() {
+            inv.prepare(&self.objs);
+        }
+        if let Some(d) = self.cycles.as_mut() {
+            d.start(|| (fingerprint(&self.objs), snapshot(&self.objs)));
+        }
+    }
+
+    /// 一步执行完成后的记录与检查，`fired` 为本步执行了的规则
+    fn finish_step(&mut self, fired: Vec<RT>, stop: &Arc<Mutex<bool>>) -> EmuStatus {
+        let step = self.steps;
+        self.steps += 1;
+        self.last_fired = fired;
+        if let Some(inv) = self.invariants.iter().find(|inv| !inv.check(&self.objs)) {
+            let v = InvariantViolation { step, time: self.time, invariant: inv.name().to_string(), fired: self.last_fired.clone() };
+            log!(
+                target: log_target::Mem::Exceptions.into(),
+                Level::Error,
+                "Mem {:?} : {}.",
+                self.tag, v
+            );
+            self.violation = Some(v);
+            return EmuStatus::EmuError;
+        }
+        if let Some(d) = self.cycles.as_mut() {
+            if let Some(c) = d.record(fingerprint(&self.objs), snapshot(&self.objs), self.last_fired.clone()) {
+                log!(
+                    target: log_target::Mem::Info.into(),
+                    Level::Warn,
+                    "Mem {:?} : configuration of step {} repeats at step {} (cycle length {}), rules {:?}.",
+                    self.tag, c.first_seen, c.step, c.length, c.rules
+                );
+                return EmuStatus::Stopped;
+            }
+        }
+
+        if stop.is_poisoned()
+        || *stop.lock().unwrap() {
+            return EmuStatus::Stopped;
         }
+        EmuStatus::Continue
+    }
+
+    /// 依次尝试执行 `rules` 中的规则，每条规则执行前重新检查条件，返回执行了的规则
+    fn execute_dynamic(&mut self, rules: VecDeque<ExecutableInfo<OT>>, stop: &Arc<Mutex<bool>>) -> Vec<RT> {
+        let mut fired = Vec::new();
+        let mut proc_out = EPOut::new();
+        proc_out.ids = self.tags.lane(0, 1);
+        self.rules.dynamic_execute_with(
+            &mut self.objs, Some(rules), &mut self.rng,
+            |os, rule_tag, e, mut req| {
+                fired.extend(rule_tag.clone());
+                if let Some((es, sigs)) = e.and_then(|e| e.effects().as_ref().map(|es| (es, e.signatures()))) {
+                    let (mut refr_set, mut refr_rand) = (None, None);
+                    let (mut tag_set, mut tag_rand) = (None, None);
+                    let (mut take_set, mut take_rand) = (None, None);
+                    //todo: 收集对象
+                    for s in req.0.iter() {
+                        if s.method == UseBy::Take {
+                            if let Some(o) = os.remove(&s.tag) {
+                                take_set.get_or_insert(Vec::new()).push(o);
+                            } else {
+                                log!(
+                                    target: log_target::Mem::Exceptions.into(), 
+                                    Level::Error, 
+                                    "In mem {:?} : Trying to get *set* obj by *take* {:?} for rule {:?} but failed.",
+                                    self.tag, s.tag, rule_tag
+                                );
+                            }
+                        }
+                    }
+                    for r in req.1.iter() {
+                        if r.method == UseBy::Take {
+                            let v = os.remove_batch_skip(&r.tag);
+                            if v.len() != r.tag.len() {
+                                log!(
+                                    target: log_target::Mem::Exceptions.into(), 
+                                    Level::Error, 
+                                    "In mem {:?} : Trying to get *rand* obj {:?} by *take* for rule {:?} but missing objs ( got {} but should be {} ).",
+                                    self.tag, r.tag, rule_tag, v.len(),  r.tag.len()
+                                );
+                            }
+                            take_rand.get_or_insert(Vec::new()).push(v);
+                        }
+                    }
+                    while let Some(s) = req.0.pop_front() {
+                        match s.method {
+                            UseBy::Tag => {
+                                tag_set.get_or_insert(Vec::new()).push(s.tag);
+                            },
+                            UseBy::Ref => {
+                                if let Some(ro) = os.get(&s.tag) {
+                                    refr_set.get_or_insert(Vec::new()).push(ro);
+                                } else {
+                                    log!(
+                                        target: log_target::Mem::Exceptions.into(), 
+                                        Level::Error, 
+                                        "In mem {:?} : Trying to get *set* obj by *ref* {:?} for rule {:?} but failed.",
+                                        self.tag, s.tag, rule_tag
+                                    );
+                                }
+                            },
+                            _ => {}
+                        };
+                    }
+                    while let Some(r) = req.1.pop_front() {
+                        match r.method {
+                            UseBy::Tag => {
+                                tag_rand.get_or_insert(Vec::new()).push(r.tag);
+                            },
+                            UseBy::Ref => {
+                                let ro = os.get_batch_skip(&r.tag);
+                                if ro.len() != r.tag.len() {
+                                    log!(
+                                        target: log_target::Mem::Exceptions.into(), 
+                                        Level::Error, 
+                                        "In mem {:?} : Trying to get *rand* obj {:?} by *ref* for rule {:?} but missing objs ( got {} but should be {} ).",
+                                        self.tag, r.tag,  rule_tag, ro.len(),  r.tag.len()
+                                    );
+                                }
+                                refr_rand.get_or_insert(Vec::new()).push(ro);
+                            },
+                            _ => {}
+                        };
+                    }
+                    let take = RequestTyped::new_opt(take_set, take_rand);
+                    let refr = RequestTyped::new_opt(refr_set, refr_rand);
+                    let tag = RequestTyped::new_opt(tag_set, tag_rand);
+                    let r = RequestedObj::new(refr, take, tag).with_ids(proc_out.ids.clone());
+                    Self::effect_proc(es, sigs, r, stop, &mut proc_out);
+                    Self::apply_influences(&mut proc_out, os);
+                }
+            }
+        );
+        self.tags.advance(std::slice::from_ref(&proc_out.ids));
+        self.outbox.append(&mut proc_out.to_send);
+        self.drain_scheduled(&mut proc_out);
+        self.dissolving |= proc_out.dissolve;
+        fired
     }
 
     pub fn apply_influences(ep_out: &mut EPOut<OT, U>, os: &mut BasicObjStore<OT, U>) {
@@ -132,13 +766,13 @@ U: Scalar
             os.remove(&t);
         }
         while let Some(o) = ep_out.to_add.pop() {
-            os.add_or_update(o.obj_tag().clone(), o);
+            os.put(o);
         }
         while let Some((ty, a)) = ep_out.to_inc.pop() {
-            os.increase(&ty, a);
+            if let Err(e) = os.increase(&ty, a) { e.log(); }
         }
         while let Some((ty, a)) = ep_out.to_dec.pop() {
-            os.decrease(&ty, a);
+            if let Err(e) = os.decrease(&ty, a) { e.log(); }
         }
     }
 }
@@ -154,8 +788,34 @@ U: Scalar
     fn ready(&self) -> bool {
         self.ready
     }
+
+    fn time(&self) -> f64 {
+        self.time
+    }
     
     fn evolve(&mut self) -> EmuStatus {
+        if self.violation.is_some() {
+            return EmuStatus::EmuError;
+        }
+        if self.cycle().is_some() {
+            return EmuStatus::Stopped;
+        }
+        match self.mode {
+            EvolveMode::Gillespie => {
+                self.dismiss_changes(false);
+                return self.evolve_gillespie();
+            },
+            EvolveMode::TauLeap(cfg) => {
+                self.dismiss_changes(false);
+                return self.evolve_tau_leap(cfg);
+            },
+            EvolveMode::MaximalMultiset => {
+                if let Some(status) = self.evolve_multiset() {
+                    return status;
+                }
+            },
+            EvolveMode::MaximallyParallel => {}
+        }
         let stop = Arc::new(Mutex::new(false));
     
         let time_loop = Instant::now();
@@ -166,16 +826,34 @@ U: Scalar
             "Mem {:?} : Checking {} rules with {} objects.",
             self.tag, self.rules.len(), self.objs.len()
         );
-        let executable = if self.no_parallel {
-            self.rules.check_on_simple(&self.objs)
+        let mut executable = if self.incremental {
+            self.rules.check_on_changes_with(&self.objs, self.objs.changes(), self.no_parallel, &mut self.rng)
+        } else if self.no_parallel {
+            self.rules.check_on_simple_with(&self.objs, &mut self.rng)
         } else {
-            self.rules.check_on(&self.objs)
+            self.rules.check_on_with(&self.objs, &mut self.rng)
         }; // todo: 可选检查方式 -ok
+        self.dismiss_changes(self.incremental);
+        let indexes = |e: &ExecutableRules<OT>| e.parallel_executable.iter().flatten()
+            .chain(e.conflict_executable.iter().flatten())
+            .map(|i| i.rule_index)
+            .collect::<Vec<_>>();
+        let applicable = if self.stats.is_some() { indexes(&executable) } else { Vec::new() };
+        executable.retain_top_priority(|i| self.rules.priority_at(i).unwrap_or(0));
+        if self.rules.has_priorities() {
+            executable.retain_unsuppressed(|i, j| self.rules.outranks(i, j));
+        }
+        if self.stats.is_some() {
+            self.record_check(&applicable, &indexes(&executable));
+        }
 
-        if executable.conflict_executable.is_none()
-        && executable.parallel_executable.is_none() { //膜内规则无法执行，故只能依靠外部改变更改膜内对象或规则，因此为了节省计算资源暂停该膜
-            return EmuStatus::Pause;
+        if executable.is_empty() { //膜内规则无法执行，故只能依靠外部改变更改膜内对象或规则，因此为了节省计算资源暂停该膜
+            return self.idle();
         }
+        self.begin_step();
+        let mut parallel_fired = Vec::new();
+        let mut conflict_tried = Vec::new();
+        let mut conflict_fired = Vec::new();
         log!(
             target: log_target::Mem::Performance.into(), 
             Level::Info, 
@@ -252,12 +930,15 @@ U: Scalar
                 updates.push(((take, opt_tp, e), EPOut::new()));
             }//while let
 
+            let n = updates.len();
+            updates.iter_mut().enumerate().for_each(|(k, (_, epo))| epo.ids = self.tags.lane(k, n));
+
             //并行执行
             updates.par_iter_mut()
             .filter_map(|(a, b)| {
-                self.rules.effect_at(a.2.rule_index).and_then(|eff| eff.effects().as_ref()).map(|es| (a, b, es))
+                self.rules.effect_at(a.2.rule_index).and_then(|eff| eff.effects().as_ref().map(|es| (a, b, es, eff.signatures())))
             })
-            .for_each(|((take, tp, e), proc_out, es)| {
+            .for_each(|((take, tp, e), proc_out, es, sigs)| {
                 let (mut refr_set, mut refr_rand) = (None, None);
                 if let Some(tps) = tp {
                     tps.iter()
@@ -295,106 +976,42 @@ U: Scalar
                 }
                 //todo: 收集对象 -ok
                 let refr = RequestTyped::new_opt(refr_set, refr_rand);
-                let r = RequestedObj::new(refr, take.take(), e.requested_tag.take());
-                Self::effect_proc(es, r, &stop, proc_out);
+                let r = RequestedObj::new(refr, take.take(), e.requested_tag.take()).with_ids(proc_out.ids.clone());
+                Self::effect_proc(es, sigs, r, &stop, proc_out);
             });
 
             // 应用更改
-            updates.iter_mut().for_each(|(_, epo)| {
+            self.tags.advance(&updates.iter().map(|(_, epo)| epo.ids.clone()).collect::<Vec<_>>());
+            updates.iter_mut().for_each(|((_, _, e), epo)| {
+                parallel_fired.extend(self.rules.tag_at(e.rule_index));
                 Self::apply_influences( epo, &mut self.objs);
+                self.outbox.append(&mut epo.to_send);
+                self.dissolving |= epo.dissolve;
+                for (delay, item) in epo.to_schedule.drain(..) {
+                    self.agenda.schedule(self.time + delay, item);
+                }
             });
         }
 
         //这些规则单独可以应用，但是同时应用可能会冲突
-        if let Some(mut ce) = executable.conflict_executable { // todo: 动态应用 -ok 
-            let mut rng = thread_rng();
-            ce.make_contiguous().shuffle(&mut rng);
-            let mut proc_out = EPOut::new();
-            self.rules.dynamic_execute(
-                &mut self.objs, Some(ce),
-                |os, rule_tag, e, mut req| {
-                    if let Some(es) = e.and_then(|e| e.effects().as_ref()) {
-                        let (mut refr_set, mut refr_rand) = (None, None);
-                        let (mut tag_set, mut tag_rand) = (None, None);
-                        let (mut take_set, mut take_rand) = (None, None);
-                        //todo: 收集对象
-                        for s in req.0.iter() {
-                            if s.method == UseBy::Take {
-                                if let Some(o) = os.remove(&s.tag) {
-                                    take_set.get_or_insert(Vec::new()).push(o);
-                                } else {
-                                    log!(
-                                        target: log_target::Mem::Exceptions.into(), 
-                                        Level::Error, 
-                                        "In mem {:?} : Trying to get *set* obj by *take* {:?} for rule {:?} but failed.",
-                                        self.tag, s.tag, rule_tag
-                                    );
-                                }
-                            }
-                        }
-                        for r in req.1.iter() {
-                            if r.method == UseBy::Take {
-                                let v = os.remove_batch_skip(&r.tag);
-                                if v.len() != r.tag.len() {
-                                    log!(
-                                        target: log_target::Mem::Exceptions.into(), 
-                                        Level::Error, 
-                                        "In mem {:?} : Trying to get *rand* obj {:?} by *take* for rule {:?} but missing objs ( got {} but should be {} ).",
-                                        self.tag, r.tag, rule_tag, v.len(),  r.tag.len()
-                                    );
-                                }
-                                take_rand.get_or_insert(Vec::new()).push(v);
-                            }
-                        }
-                        while let Some(s) = req.0.pop_front() {
-                            match s.method {
-                                UseBy::Tag => {
-                                    tag_set.get_or_insert(Vec::new()).push(s.tag);
-                                },
-                                UseBy::Ref => {
-                                    if let Some(ro) = os.get(&s.tag) {
-                                        refr_set.get_or_insert(Vec::new()).push(ro);
-                                    } else {
-                                        log!(
-                                            target: log_target::Mem::Exceptions.into(), 
-                                            Level::Error, 
-                                            "In mem {:?} : Trying to get *set* obj by *ref* {:?} for rule {:?} but failed.",
-                                            self.tag, s.tag, rule_tag
-                                        );
-                                    }
-                                },
-                                _ => {}
-                            };
-                        }
-                        while let Some(r) = req.1.pop_front() {
-                            match r.method {
-                                UseBy::Tag => {
-                                    tag_rand.get_or_insert(Vec::new()).push(r.tag);
-                                },
-                                UseBy::Ref => {
-                                    let ro = os.get_batch_skip(&r.tag);
-                                    if ro.len() != r.tag.len() {
-                                        log!(
-                                            target: log_target::Mem::Exceptions.into(), 
-                                            Level::Error, 
-                                            "In mem {:?} : Trying to get *rand* obj {:?} by *ref* for rule {:?} but missing objs ( got {} but should be {} ).",
-                                            self.tag, r.tag,  rule_tag, ro.len(),  r.tag.len()
-                                        );
-                                    }
-                                    refr_rand.get_or_insert(Vec::new()).push(ro);
-                                },
-                                _ => {}
-                            };
-                        }
-                        let take = RequestTyped::new_opt(take_set, take_rand);
-                        let refr = RequestTyped::new_opt(refr_set, refr_rand);
-                        let tag = RequestTyped::new_opt(tag_set, tag_rand);
-                        let r = RequestedObj::new(refr, take, tag);
-                        Self::effect_proc(es, r, &stop, &mut proc_out);
-                        Self::apply_influences(&mut proc_out, os);
-                    }
-                }
-            );
+        if let Some(ce) = executable.conflict_executable { // todo: 动态应用 -ok 
+            let (mut infos, cands): (Vec<_>, Vec<_>) = ce.into_iter()
+                .filter_map(|e| {
+                    let rule_index = e.rule_index;
+                    let tag = self.rules.tag_at(rule_index)?;
+                    let priority = self.rules.priority_at(rule_index).unwrap_or(0);
+                    Some((Some(e), Candidate { rule_index, tag, priority }))
+                })
+                .unzip();
+            let ce = self.resolver.order(&cands, &mut self.rng)
+                .into_iter()
+                .filter_map(|i| infos.get_mut(i).and_then(Option::take))
+                .collect::<VecDeque<_>>();
+            if self.stats.is_some() {
+                conflict_tried.extend(ce.iter().filter_map(|e| self.rules.tag_at(e.rule_index)));
+            }
+            conflict_fired = self.execute_dynamic(ce, &stop);
+            conflict_fired.iter().for_each(|t| self.resolver.fired(t));
         }
         log!(
             target: log_target::Mem::Performance.into(), 
@@ -409,11 +1026,13 @@ U: Scalar
             self.tag, time_loop.elapsed().as_micros()
         );
 
-        if stop.is_poisoned()
-        || *stop.lock().unwrap() {
-            return EmuStatus::Stopped;
-        }
-        EmuStatus::Continue
+        self.record_fired(&parallel_fired, &conflict_tried, &conflict_fired);
+        parallel_fired.append(&mut conflict_fired);
+        let dt = parallel_fired.iter()
+            .filter_map(|t| self.rules.pos_of(t).and_then(|i| self.rules.duration_at(i)))
+            .fold(0.0, f64::max);
+        self.advance_to(self.time + dt);
+        self.finish_step(parallel_fired, &stop)
     }
   
 }
\ No newline at end of file
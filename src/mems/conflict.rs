@@ -0,0 +1,153 @@
+// Copyright 2024 Junshuang Hu
+//! 冲突规则的解决策略
+//!
+//! 单独可以执行、但同时执行可能冲突的规则会按 [`ConflictResolver::order`] 给出的顺序依次尝试，
+//! 先尝试的规则先取得对象，之后需要的对象不足的规则不会执行
+
+use std::fmt::Debug;
+use std::hash::Hash;
+
+use ahash::AHashMap;
+use rand::seq::SliceRandom;
+use rand::{Rng, RngCore};
+
+/// 参与冲突的规则
+#[derive(Debug, Clone)]
+pub struct Candidate<RT> {
+    pub rule_index: usize,
+    pub tag: RT,
+    pub priority: i32
+}
+
+/// 冲突解决策略，每个膜可以使用不同的策略
+pub trait ConflictResolver<RT>: Debug + Send + Sync {
+    /// 返回 `candidates` 中下标的尝试顺序，不在其中的规则本步不会执行
+    fn order(&mut self, candidates: &[Candidate<RT>], rng: &mut dyn RngCore) -> Vec<usize>;
+
+    /// 冲突中规则 `tag` 成功执行后调用
+    fn fired(&mut self, _tag: &RT) {}
+}
+
+fn shuffled<RT>(candidates: &[Candidate<RT>], rng: &mut dyn RngCore) -> Vec<usize> {
+    let mut order = (0..candidates.len()).collect::<Vec<_>>();
+    order.shuffle(rng);
+    order
+}
+
+/// 随机顺序，默认策略
+#[derive(Debug, Default, Clone)]
+pub struct RandomResolver;
+
+impl<RT> ConflictResolver<RT> for RandomResolver {
+    fn order(&mut self, candidates: &[Candidate<RT>], rng: &mut dyn RngCore) -> Vec<usize> {
+        shuffled(candidates, rng)
+    }
+}
+
+/// 按给定的等级从高到低尝试，未给出等级的规则使用规则自身的优先级，等级相同时随机
+#[derive(Debug, Clone)]
+pub struct PriorityResolver<RT: Hash + Eq> {
+    ranks: AHashMap<RT, i32>
+}
+
+impl<RT: Hash + Eq> Default for PriorityResolver<RT> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<RT: Hash + Eq> PriorityResolver<RT> {
+    pub fn new() -> Self {
+        Self { ranks: AHashMap::new() }
+    }
+
+    pub fn rank(mut self, tag: RT, rank: i32) -> Self {
+        self.ranks.insert(tag, rank);
+        self
+    }
+}
+
+impl<RT> ConflictResolver<RT> for PriorityResolver<RT>
+where RT: Hash + Eq + Debug + Send + Sync {
+    fn order(&mut self, candidates: &[Candidate<RT>], rng: &mut dyn RngCore) -> Vec<usize> {
+        let mut order = shuffled(candidates, rng);
+        order.sort_by_key(|i| {
+            let c = &candidates[*i];
+            std::cmp::Reverse(self.ranks.get(&c.tag).copied().unwrap_or(c.priority))
+        });
+        order
+    }
+}
+
+/// 轮转：最久没有在冲突中执行的规则先尝试，从未执行过的规则最先，相同时随机
+#[derive(Debug, Clone)]
+pub struct RoundRobinResolver<RT: Hash + Eq> {
+    round: usize,
+    last: AHashMap<RT, usize>
+}
+
+impl<RT: Hash + Eq> Default for RoundRobinResolver<RT> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<RT: Hash + Eq> RoundRobinResolver<RT> {
+    pub fn new() -> Self {
+        Self { round: 0, last: AHashMap::new() }
+    }
+}
+
+impl<RT> ConflictResolver<RT> for RoundRobinResolver<RT>
+where RT: Clone + Hash + Eq + Debug + Send + Sync {
+    fn order(&mut self, candidates: &[Candidate<RT>], rng: &mut dyn RngCore) -> Vec<usize> {
+        self.round += 1;
+        let mut order = shuffled(candidates, rng);
+        order.sort_by_key(|i| self.last.get(&candidates[*i].tag).copied().unwrap_or(0));
+        order
+    }
+
+    fn fired(&mut self, tag: &RT) {
+        self.last.insert(tag.clone(), self.round);
+    }
+}
+
+/// 按权重随机排列，权重越大越可能先尝试，未给出权重的规则权重为 `1`，权重不大于 `0` 的规则最后尝试
+#[derive(Debug, Clone)]
+pub struct WeightedResolver<RT: Hash + Eq> {
+    weights: AHashMap<RT, f64>
+}
+
+impl<RT: Hash + Eq> Default for WeightedResolver<RT> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<RT: Hash + Eq> WeightedResolver<RT> {
+    pub fn new() -> Self {
+        Self { weights: AHashMap::new() }
+    }
+
+    pub fn weight(mut self, tag: RT, w: f64) -> Self {
+        self.weights.insert(tag, w);
+        self
+    }
+}
+
+impl<RT> ConflictResolver<RT> for WeightedResolver<RT>
+where RT: Hash + Eq + Debug + Send + Sync {
+    fn order(&mut self, candidates: &[Candidate<RT>], rng: &mut dyn RngCore) -> Vec<usize> {
+        // 不放回的加权抽样：以 u^(1/w) 为键从大到小排列
+        let mut keyed = candidates.iter()
+            .enumerate()
+            .map(|(i, c)| {
+                let w = self.weights.get(&c.tag).copied().unwrap_or(1.0);
+                let u: f64 = rng.gen_range(f64::EPSILON..1.0);
+                (i, if w > 0.0 { u.powf(1.0 / w) } else { 0.0 })
+            })
+            .collect::<Vec<_>>();
+        keyed.sort_by(|a, b| b.1.total_cmp(&a.1));
+        keyed.into_iter().map(|(i, _)| i).collect()
+    }
+}
@@ -0,0 +1,173 @@
+// Copyright 2024 Junshuang Hu
+//! 环检测
+//!
+//! 格局由 untagged 对象的数量与 tagged 对象的 tag 集合组成，tagged 对象内部的状态不属于格局，
+//! 因此只有内部状态不同的两个格局视为同一个格局
+use std::any::TypeId;
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::hash::Hash;
+
+use ahash::{AHashMap, AHashSet, RandomState};
+use krnl::scalar::Scalar;
+
+use crate::core::{IObjStat, IUntaggedStore};
+use crate::objs::BasicObjStore;
+
+/// 固定种子，保证同一个格局在不同的膜和不同的步中得到相同的指纹
+fn hasher() -> RandomState {
+    RandomState::with_seeds(0x6d65_6d65, 0x6379_636c, 0x6669_6e67, 0x7072_696e)
+}
+
+/// 格局的指纹：由 untagged 对象的数量和 tagged 对象的 tag 集合计算，与储存的顺序无关
+/// 不同的格局可能有相同的指纹，需要再比较 [`Snapshot`]
+pub fn fingerprint<OT, U>(os: &BasicObjStore<OT, U>) -> u64
+where OT: Clone + Hash + Eq, U: Scalar {
+    let s = hasher();
+    let untagged = (0..os.type_count())
+        .filter_map(|i| os.tid_at(i))
+        .filter_map(|t| os.get_u(t).map(|a| (t, a)))
+        .filter(|(_, a)| *a != U::zero())
+        .fold(0u64, |acc, (t, a)| acc.wrapping_add(s.hash_one((t, a.cast::<f64>().to_bits()))));
+    let tagged = os.objs()
+        .fold(0u64, |acc, o| acc.wrapping_add(s.hash_one(o.obj_tag())));
+    s.hash_one((untagged, tagged))
+}
+
+/// 格局的快照，指纹相同时比较快照，确认格局确实重复
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot<OT: Hash + Eq, U> {
+    /// 按类型排序的非零 untagged 数量
+    untagged: Vec<(TypeId, U)>,
+    tagged: AHashSet<OT>
+}
+
+pub fn snapshot<OT, U>(os: &BasicObjStore<OT, U>) -> Snapshot<OT, U>
+where OT: Clone + Hash + Eq, U: Scalar {
+    let mut untagged = (0..os.type_count())
+        .filter_map(|i| os.tid_at(i))
+        .filter_map(|t| os.get_u(t).map(|a| (*t, a)))
+        .filter(|(_, a)| *a != U::zero())
+        .collect::<Vec<_>>();
+    untagged.sort_by_key(|(t, _)| *t);
+    Snapshot { untagged, tagged: os.objs().map(|o| o.obj_tag().clone()).collect() }
+}
+
+/// 组合多个指纹，与顺序有关
+pub fn combine(parts: impl Iterator<Item = u64>) -> u64 {
+    let s = hasher();
+    parts.fold(0u64, |acc, p| s.hash_one((acc, p)))
+}
+
+/// 发现的环
+#[derive(Debug, Clone)]
+pub struct CycleReport<RT> {
+    /// 重复的格局第一次出现的步数，`0` 为初始格局
+    pub first_seen: usize,
+    /// 再次出现的步数
+    pub step: usize,
+    /// 环的长度（步数）
+    pub length: usize,
+    /// 环中执行过的规则（不重复）
+    pub rules: Vec<RT>
+}
+
+/// 环检测器：记录每一步后格局的指纹，指纹相同且快照 `S` 相等时给出 [`CycleReport`]  
+/// 每一步只保存指纹，完整的快照只保存最近 [`CycleDetector::window`] 步，以及指纹重复时的候选快照：
+/// 长度不超过窗口的环在格局第一次重复时发现，更长的环在第二次重复时由候选快照确认
+#[derive(Debug)]
+pub struct CycleDetector<RT, S> {
+    seen: AHashMap<u64, Vec<usize>>,
+    recent: VecDeque<(usize, S)>,
+    candidates: AHashMap<u64, Vec<(S, usize)>>,
+    window: usize,
+    fired: Vec<Vec<RT>>,
+    report: Option<CycleReport<RT>>
+}
+
+impl<RT, S> Default for CycleDetector<RT, S>
+where RT: Clone + Hash + Eq, S: Clone + PartialEq {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<RT, S> CycleDetector<RT, S>
+where RT: Clone + Hash + Eq, S: Clone + PartialEq {
+    pub fn new() -> Self {
+        Self {
+            seen: AHashMap::new(),
+            recent: VecDeque::new(),
+            candidates: AHashMap::new(),
+            window: 64,
+            fired: Vec::new(),
+            report: None
+        }
+    }
+
+    /// 保存完整快照的最近步数，默认为 `64`，至少为 `1`
+    pub fn window(mut self, n: usize) -> Self {
+        self.window = n.max(1);
+        self
+    }
+
+    /// 记录初始格局，只在没有任何记录时生效
+    pub fn start<F: FnOnce() -> (u64, S)>(&mut self, conf: F) {
+        if self.seen.is_empty() {
+            let (fp, snap) = conf();
+            self.remember(fp, snap, 0);
+        }
+    }
+
+    /// 记录一步后的格局与该步执行的规则，格局重复时返回环
+    pub fn record(&mut self, fp: u64, snap: S, fired: Vec<RT>) -> Option<&CycleReport<RT>> {
+        self.fired.push(fired);
+        let step = self.fired.len();
+        let earlier = self.seen.get(&fp).map_or(&[][..], |v| v.as_slice());
+        let in_window = self.recent.iter()
+            .find(|(i, s)| earlier.contains(i) && *s == snap)
+            .map(|(i, _)| *i);
+        let confirmed = in_window.or_else(|| {
+            self.candidates.get(&fp).and_then(|c| c.iter().find(|(s, _)| *s == snap)).map(|(_, i)| *i)
+        });
+        if let Some(first_seen) = confirmed {
+            let mut done = AHashSet::new();
+            let rules = self.fired[first_seen..].iter()
+                .flatten()
+                .filter(|r| done.insert((*r).clone()))
+                .cloned()
+                .collect();
+            self.report = Some(CycleReport { first_seen, step, length: step - first_seen, rules });
+        } else if earlier.iter().any(|i| self.recent.front().is_none_or(|(first, _)| i < first)) {
+            // 指纹与窗口外的格局相同，保存快照以便之后确认
+            self.candidates.entry(fp).or_default().push((snap.clone(), step));
+        }
+        self.remember(fp, snap, step);
+        self.report.as_ref()
+    }
+
+    fn remember(&mut self, fp: u64, snap: S, step: usize) {
+        self.seen.entry(fp).or_default().push(step);
+        self.recent.push_back((step, snap));
+        if self.recent.len() > self.window {
+            self.recent.pop_front();
+        }
+    }
+
+    pub fn report(&self) -> Option<&CycleReport<RT>> {
+        self.report.as_ref()
+    }
+
+    pub fn reset(&mut self) {
+        self.seen.clear();
+        self.recent.clear();
+        self.candidates.clear();
+        self.fired.clear();
+        self.report = None;
+    }
+
+    /// 已记录的步数
+    pub fn steps(&self) -> usize {
+        self.fired.len()
+    }
+}
@@ -0,0 +1,657 @@
+// Copyright 2024 Junshuang Hu
+//! 小规模 untagged 系统的状态空间穷举
+//!
+//! 膜的演化中的不确定性来自冲突规则的随机执行顺序，[`Explorer`] 不抽样而是枚举每一步所有可能的结果，
+//! 得到可达格局图 [`ConfigGraph`]，相同的格局只保存一次
+//!
+//! 每一步的语义与 [`BasicMem`] 和 [`MemHierarchy`] 一致，按膜的 [`EvolveMode`] 展开：
+//! - 只有优先级最高、且没有优先于它的规则（[`crate::rules::BasicRuleStore::add_priority`]）可执行的规则参与
+//! - [`EvolveMode::MaximallyParallel`]：每条规则最多执行一次，需要的对象无竞争的规则全部执行，
+//!   其余规则按任意顺序依次检查并执行（枚举所有顺序）
+//! - [`EvolveMode::MaximalMultiset`]：枚举规则执行次数的所有极大多重集，溶解膜的规则最多执行一次；
+//!   与 [`BasicMem`] 一样，有规则不能按化学计量批量执行（如停止）时按默认方式展开
+//! - 膜层级中所有膜同步演化，然后投递发送的对象，最后溶解膜
+//!
+//! 只支持规则的条件只有 untagged 对象、影响只增减或发送 untagged 对象、溶解膜或停止，且没有概率的系统，
+//! [`EvolveMode::Gillespie`] 与 [`EvolveMode::TauLeap`] 的膜不能穷举
+
+use std::any::TypeId;
+use std::fmt::Debug;
+use std::hash::Hash;
+
+use ahash::{AHashMap, AHashSet};
+use krnl::scalar::Scalar;
+
+use crate::core::{ICondition, IObjStat, IRuleEffect, IRuleStat, IUntaggedStore, MemTarget, ObjType, OperationEffect};
+use crate::errors::MemError;
+use crate::mems::basic::BasicMem;
+use crate::mems::hierarchy::MemHierarchy;
+use crate::mems::stochastic::EvolveMode;
+use crate::rules::BasicRuleStore;
+
+/// 按 [`TypeId`] 排序、不含零数量的多重集
+pub type Multiset<U> = Vec<(TypeId, U)>;
+
+fn ms_get<U: Scalar>(m: &Multiset<U>, ty: &TypeId) -> U {
+    m.binary_search_by(|(t, _)| t.cmp(ty)).map_or(U::zero(), |i| m[i].1)
+}
+
+fn ms_add<U: Scalar>(m: &mut Multiset<U>, ty: TypeId, a: U) {
+    if a == U::zero() {
+        return;
+    }
+    match m.binary_search_by(|(t, _)| t.cmp(&ty)) {
+        Ok(i) => m[i].1 += a,
+        Err(i) => m.insert(i, (ty, a)),
+    }
+}
+
+fn ms_sub<U: Scalar>(m: &mut Multiset<U>, ty: TypeId, a: U) {
+    if let Ok(i) = m.binary_search_by(|(t, _)| t.cmp(&ty)) {
+        if m[i].1 <= a {
+            m.remove(i);
+        } else {
+            m[i].1 -= a;
+        }
+    }
+}
+
+/// 系统的一个格局
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Config<U> {
+    mems: Vec<Option<Multiset<U>>>,
+    parents: Vec<Option<usize>>,
+    children: Vec<Vec<usize>>,
+    environment: Multiset<U>,
+    stopped: bool
+}
+
+impl<U: Scalar> Config<U> {
+    /// 膜内的对象，溶解的膜为 [`None`]，膜的下标与 [`MemHierarchy`] 中一致
+    pub fn mem(&self, pos: usize) -> Option<&Multiset<U>> {
+        self.mems.get(pos).and_then(|m| m.as_ref())
+    }
+
+    pub fn amount_in(&self, pos: usize, ty: &ObjType) -> U {
+        self.mem(pos).map_or(U::zero(), |m| ms_get(m, &ty.tid))
+    }
+
+    pub fn environment(&self) -> &Multiset<U> {
+        &self.environment
+    }
+
+    pub fn environment_amount(&self, ty: &ObjType) -> U {
+        ms_get(&self.environment, &ty.tid)
+    }
+
+    /// 所有未溶解的膜中 `ty` 的数量之和
+    pub fn total(&self, ty: &ObjType) -> U {
+        self.mems.iter().flatten().fold(U::zero(), |acc, m| acc + ms_get(m, &ty.tid))
+    }
+
+    pub fn parent(&self, pos: usize) -> Option<usize> {
+        self.parents.get(pos).copied().flatten()
+    }
+
+    pub fn is_dissolved(&self, pos: usize) -> bool {
+        self.mems.get(pos).is_none_or(|m| m.is_none())
+    }
+
+    /// 是否由执行了停止操作的一步到达
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+}
+
+/// 一步中执行的规则，`(膜的下标, 规则的 tag)`
+pub type Fired<RT> = Vec<(usize, RT)>;
+
+/// 格局之间的一步
+#[derive(Debug, Clone)]
+pub struct Transition<RT> {
+    pub to: usize,
+    pub fired: Fired<RT>
+}
+
+/// 可达格局图，初始格局的下标为 `0`
+#[derive(Debug)]
+pub struct ConfigGraph<RT, U> {
+    configs: Vec<Config<U>>,
+    index: AHashMap<Config<U>, usize>,
+    edges: Vec<Vec<Transition<RT>>>,
+    expanded: usize,
+    complete: bool
+}
+
+impl<RT, U> ConfigGraph<RT, U>
+where U: Scalar + Hash + Eq {
+    pub fn len(&self) -> usize {
+        self.configs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.configs.is_empty()
+    }
+
+    pub fn config(&self, i: usize) -> Option<&Config<U>> {
+        self.configs.get(i)
+    }
+
+    pub fn configs(&self) -> &[Config<U>] {
+        &self.configs
+    }
+
+    pub fn index_of(&self, c: &Config<U>) -> Option<usize> {
+        self.index.get(c).copied()
+    }
+
+    pub fn successors(&self, i: usize) -> &[Transition<RT>] {
+        self.edges.get(i).map_or(&[], |e| e.as_slice())
+    }
+
+    /// 是否探索了所有可达格局，达到格局数上限时为 `false`
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    /// 停机格局：没有规则可以执行，或者执行了停止操作
+    pub fn halting(&self) -> Vec<usize> {
+        (0..self.configs.len()).filter(|i| self.is_halting(*i)).collect()
+    }
+
+    /// 未展开的格局（达到格局数上限时）不算停机格局
+    pub fn is_halting(&self, i: usize) -> bool {
+        self.configs[i].stopped || i < self.expanded && self.edges[i].is_empty()
+    }
+
+    /// 所有停机格局的输出（去重），`out` 从格局中读取输出，如某个膜或环境中某类对象的数量
+    pub fn halting_outputs<V, F>(&self, out: F) -> Vec<V>
+    where V: Hash + Eq + Clone, F: Fn(&Config<U>) -> V {
+        let mut seen = AHashSet::new();
+        self.halting().into_iter()
+            .map(|i| out(&self.configs[i]))
+            .filter(|v| seen.insert(v.clone()))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Op<U> {
+    Inc(TypeId, U),
+    Dec(TypeId, U),
+    Send(MemTarget, TypeId, U),
+    Dissolve,
+    Stop
+}
+
+#[derive(Debug)]
+struct RuleOps<RT, U> {
+    tag: RT,
+    needs: Vec<(TypeId, U)>,
+    priority: i32,
+    ops: Vec<Op<U>>
+}
+
+impl<RT, U: Scalar> RuleOps<RT, U> {
+    /// 极大多重集中最多执行一次：溶解膜或不需要对象的规则
+    fn once(&self) -> bool {
+        self.ops.iter().any(|op| matches!(op, Op::Dissolve)) || self.needs.iter().all(|(_, a)| *a <= U::zero())
+    }
+}
+
+#[derive(Debug)]
+struct MemRules<RT, U> {
+    label: usize,
+    no_parallel: bool,
+    /// 按极大多重集展开
+    multiset: bool,
+    /// `(i, j)`：第 `i` 条规则优先于第 `j` 条规则
+    outranks: Vec<(usize, usize)>,
+    req: AHashMap<TypeId, U>,
+    rules: Vec<RuleOps<RT, U>>
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Outcome<U> {
+    objs: Multiset<U>,
+    sends: Vec<(MemTarget, TypeId, U)>,
+    dissolve: bool,
+    stop: bool
+}
+
+impl<U: Scalar> Outcome<U> {
+    fn apply<RT>(&mut self, r: &RuleOps<RT, U>) {
+        for op in r.ops.iter() {
+            match op {
+                Op::Inc(t, a) => ms_add(&mut self.objs, *t, *a),
+                Op::Dec(t, a) => ms_sub(&mut self.objs, *t, *a),
+                Op::Send(target, t, a) => self.sends.push((target.clone(), *t, *a)),
+                Op::Dissolve => self.dissolve = true,
+                Op::Stop => self.stop = true,
+            }
+        }
+    }
+
+    /// 发送的对象排序后作为去重的键
+    fn into_key(mut self) -> Self {
+        self.sends.sort_by(|a, b| (&a.0, a.1).cmp(&(&b.0, b.1)).then(a.2.partial_cmp(&b.2).unwrap_or(std::cmp::Ordering::Equal)));
+        self
+    }
+}
+
+fn satisfied<RT, U: Scalar>(m: &Multiset<U>, r: &RuleOps<RT, U>) -> bool {
+    r.needs.iter().all(|(t, a)| ms_get(m, t) >= *a)
+}
+
+fn mem_rules<T, OT, RT, U>(mem: &BasicMem<T, OT, RT, U>, label: usize) -> Result<MemRules<RT, U>, MemError<RT>>
+where
+T: Clone + Hash + Eq + Debug + 'static,
+RT: Clone + Hash + Eq + Send + Sync + Debug + 'static,
+OT: Clone + Hash + Eq + Send + Sync + Debug + 'static,
+U: Scalar {
+    let multiset = match mem.mode() {
+        EvolveMode::MaximallyParallel => false,
+        EvolveMode::MaximalMultiset => true,
+        mode => return Err(MemError {
+            info: format!("mem evolves in mode {mode:?}, only maximally parallel and maximal multiset mems can be explored"),
+            data: None
+        }),
+    };
+    let rules: &BasicRuleStore<RT, OT, U> = mem.rules();
+    // 与 stochastic::stoichiometry 一致，只有增减、发送 untagged 对象和溶解膜的规则可以批量执行
+    let mut batchable = true;
+    let mut res = Vec::new();
+    for (i, r) in rules.rules().enumerate() {
+        let tag = r.obj_tag().clone();
+        let unsupported = |what: &str| MemError {
+            info: format!("rule {tag:?} {what}, only untagged systems can be explored"),
+            data: Some(tag.clone())
+        };
+        let c = rules.condition_at(i).ok_or_else(|| unsupported("has no condition"))?;
+        if c.tagged().as_ref().is_some_and(|t| !t.is_empty()) {
+            return Err(unsupported("requires tagged objects"));
+        }
+        if rules.probability_at(i).is_some_and(|p| p < 1.0) {
+            return Err(unsupported("fires with a probability"));
+        }
+        let needs = c.untagged().iter().flatten().map(|u| (u.ty.tid, u.amount)).collect();
+        let mut ops = Vec::new();
+        for op in rules.effect_at(i).and_then(|e| e.effects().as_ref()).into_iter().flatten() {
+            ops.push(match op {
+                OperationEffect::IncreaseObjUntagged((t, a)) => Op::Inc(t.tid, *a),
+                OperationEffect::DecreaseObjUntagged((t, a)) => Op::Dec(t.tid, *a),
+                OperationEffect::SendObjUntagged((t, a, target)) => Op::Send(target.clone(), t.tid, *a),
+                OperationEffect::DissolveMem => Op::Dissolve,
+                OperationEffect::Stop => {
+                    batchable = false;
+                    Op::Stop
+                },
+                OperationEffect::RemoveObjUntagged(_) | OperationEffect::Pause => {
+                    batchable = false;
+                    continue
+                },
+                OperationEffect::ScheduleObj(_) | OperationEffect::ScheduleUntagged(_) => return Err(unsupported("schedules objects")),
+                _ => return Err(unsupported("creates or removes tagged objects")),
+            });
+        }
+        res.push(RuleOps { tag, needs, priority: rules.priority_at(i).unwrap_or(0), ops });
+    }
+    let n = res.len();
+    let outranks = (0..n)
+        .flat_map(|i| (0..n).map(move |j| (i, j)))
+        .filter(|(i, j)| rules.has_priorities() && rules.outranks(*i, *j))
+        .collect();
+    Ok(MemRules {
+        label,
+        no_parallel: mem.no_parallel(),
+        multiset: multiset && batchable,
+        outranks,
+        req: rules.req_of_types().clone(),
+        rules: res
+    })
+}
+
+/// 一个膜一步所有可能的结果，没有规则可以执行时返回空
+fn mem_outcomes<RT: Clone, U>(mr: &MemRules<RT, U>, m: &Multiset<U>) -> Vec<(Outcome<U>, Vec<RT>)>
+where U: Scalar + Hash + Eq {
+    let applicable = (0..mr.rules.len()).filter(|i| satisfied(m, &mr.rules[*i])).collect::<Vec<_>>();
+    let Some(top) = applicable.iter().map(|i| mr.rules[*i].priority).max() else {
+        return Vec::new();
+    };
+    let top_rules = applicable.iter().copied().filter(|i| mr.rules[*i].priority == top).collect::<Vec<_>>();
+    let kept = top_rules.iter()
+        .copied()
+        .filter(|j| !mr.outranks.iter().any(|(i, l)| l == j && top_rules.contains(i)))
+        .collect::<Vec<_>>();
+    if mr.multiset {
+        return multiset_outcomes(mr, m, &kept);
+    }
+
+    // 与 IRuleStat::check_on 相同的划分方式
+    let mut conflict_tys = AHashSet::new();
+    if !mr.no_parallel {
+        let mut released: AHashMap<TypeId, U> = AHashMap::new();
+        for (_, r) in mr.rules.iter().enumerate().filter(|(i, _)| !applicable.contains(i)) {
+            for (t, a) in r.needs.iter() {
+                *released.entry(*t).or_insert(U::zero()) += *a;
+            }
+        }
+        for (t, a) in m.iter() {
+            if mr.req.get(t).is_some_and(|req| *req > *a + released.get(t).copied().unwrap_or(U::zero())) {
+                conflict_tys.insert(*t);
+            }
+        }
+    }
+    let (parallel, conflict): (Vec<usize>, Vec<usize>) = kept.into_iter()
+        .partition(|i| !mr.no_parallel && mr.rules[*i].needs.iter().all(|(t, _)| !conflict_tys.contains(t)));
+
+    let mut base = Outcome { objs: m.clone(), sends: Vec::new(), dissolve: false, stop: false };
+    for i in parallel.iter() {
+        base.apply(&mr.rules[*i]);
+    }
+    let fired = parallel.iter().map(|i| mr.rules[*i].tag.clone()).collect::<Vec<_>>();
+
+    let mut res: AHashMap<Outcome<U>, Vec<RT>> = AHashMap::new();
+    let mut visited = AHashSet::new();
+    let mut stack = vec![(base, conflict, fired)];
+    while let Some((out, remaining, fired)) = stack.pop() {
+        if remaining.is_empty() {
+            res.entry(out.into_key()).or_insert(fired);
+            continue;
+        }
+        if !visited.insert((out.clone(), remaining.clone())) {
+            continue;
+        }
+        for (k, i) in remaining.iter().enumerate() {
+            let mut rest = remaining.clone();
+            rest.remove(k);
+            let r = &mr.rules[*i];
+            if satisfied(&out.objs, r) {
+                let mut next = out.clone();
+                next.apply(r);
+                let mut f = fired.clone();
+                f.push(r.tag.clone());
+                stack.push((next, rest, f));
+            } else {
+                stack.push((out.clone(), rest, fired.clone()));
+            }
+        }
+    }
+    res.into_iter().collect()
+}
+
+/// 极大并行语义下一个膜一步所有可能的结果，`rules` 为步开始时筛选后的规则
+fn multiset_outcomes<RT: Clone, U>(mr: &MemRules<RT, U>, m: &Multiset<U>, rules: &[usize]) -> Vec<(Outcome<U>, Vec<RT>)>
+where U: Scalar + Hash + Eq {
+    let mut all = Vec::new();
+    maximal_multisets(mr, rules, 0, m, &mut vec![0; rules.len()], &mut all);
+
+    let mut res: AHashMap<Outcome<U>, Vec<RT>> = AHashMap::new();
+    for counts in all {
+        let mut out = Outcome { objs: m.clone(), sends: Vec::new(), dissolve: false, stop: false };
+        // 与 BasicMem 一样先减少再增加
+        for dec in [true, false] {
+            for (i, n) in rules.iter().zip(counts.iter()).filter(|(_, n)| **n > 0) {
+                let times = (*n as f64).cast::<U>();
+                for op in mr.rules[*i].ops.iter() {
+                    match op {
+                        Op::Dec(t, a) if dec => ms_sub(&mut out.objs, *t, *a * times),
+                        Op::Inc(t, a) if !dec => ms_add(&mut out.objs, *t, *a * times),
+                        Op::Send(target, t, a) if !dec => out.sends.push((target.clone(), *t, *a * times)),
+                        Op::Dissolve if !dec => out.dissolve = true,
+                        _ => {}
+                    }
+                }
+            }
+        }
+        let fired = rules.iter().zip(counts.iter())
+            .filter(|(_, n)| **n > 0)
+            .map(|(i, _)| mr.rules[*i].tag.clone())
+            .collect();
+        res.entry(out.into_key()).or_insert(fired);
+    }
+    res.into_iter().collect()
+}
+
+/// 枚举 `rules[k..]` 的执行次数，`avail` 为前面的规则保留需求后剩余的对象，
+/// 得到的多重集是极大的：没有规则可以再执行一次
+fn maximal_multisets<RT, U: Scalar>(mr: &MemRules<RT, U>, rules: &[usize], k: usize, avail: &Multiset<U>, counts: &mut [u64], res: &mut Vec<Vec<u64>>) {
+    if k == rules.len() {
+        let maximal = rules.iter().zip(counts.iter()).all(|(i, n)| {
+            let r = &mr.rules[*i];
+            r.once() && *n > 0 || !satisfied(avail, r)
+        });
+        if maximal {
+            res.push(counts.to_vec());
+        }
+        return;
+    }
+    let r = &mr.rules[rules[k]];
+    let max = if !satisfied(avail, r) {
+        0
+    } else if r.once() {
+        1
+    } else {
+        r.needs.iter()
+            .filter(|(_, a)| *a > U::zero())
+            .map(|(t, a)| (ms_get(avail, t).cast::<f64>() / a.cast::<f64>()).floor() as u64)
+            .min()
+            .unwrap_or(1)
+    };
+    for n in 0..=max {
+        let mut rest = avail.clone();
+        let times = (n as f64).cast::<U>();
+        for (t, a) in r.needs.iter() {
+            ms_sub(&mut rest, *t, *a * times);
+        }
+        counts[k] = n;
+        maximal_multisets(mr, rules, k + 1, &rest, counts, res);
+    }
+    counts[k] = 0;
+}
+
+/// 状态空间穷举器
+#[derive(Debug, Clone)]
+pub struct Explorer {
+    max_configs: usize
+}
+
+impl Default for Explorer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Explorer {
+    pub fn new() -> Self {
+        Self { max_configs: 100_000 }
+    }
+
+    /// 最多保存的格局数，达到后停止探索，[`ConfigGraph::is_complete`] 为 `false`
+    pub fn max_configs(mut self, n: usize) -> Self {
+        self.max_configs = n;
+        self
+    }
+
+    /// 穷举单个膜，发送到外部的对象视为进入环境，发送到子膜的对象留在膜内
+    pub fn explore_mem<T, OT, RT, U>(&self, mem: &BasicMem<T, OT, RT, U>) -> Result<ConfigGraph<RT, U>, MemError<RT>>
+    where
+    T: Clone + Hash + Eq + Debug + 'static,
+    OT: Clone + Hash + Eq + Send + Sync + Debug + 'static,
+    RT: Clone + Hash + Eq + Send + Sync + Debug + 'static,
+    U: Scalar + Hash + Eq {
+        let rules = vec![mem_rules(mem, 0)?];
+        let init = Config {
+            mems: vec![Some(multiset_of(mem))],
+            parents: vec![None],
+            children: vec![Vec::new()],
+            environment: Vec::new(),
+            stopped: false
+        };
+        Ok(self.explore(&rules, init))
+    }
+
+    /// 穷举膜层级，格局中膜的下标与 `h` 中一致
+    pub fn explore_hierarchy<T, OT, RT, U>(&self, h: &MemHierarchy<T, OT, RT, U>) -> Result<ConfigGraph<RT, U>, MemError<RT>>
+    where
+    T: Clone + Hash + Eq + Debug + 'static,
+    OT: Clone + Hash + Eq + Send + Sync + Debug + 'static,
+    RT: Clone + Hash + Eq + Send + Sync + Debug + 'static,
+    U: Scalar + Hash + Eq {
+        let mut rules = Vec::new();
+        let mut init = Config { mems: Vec::new(), parents: Vec::new(), children: Vec::new(), environment: Vec::new(), stopped: false };
+        for n in h.nodes() {
+            rules.push(mem_rules(&n.mem, n.label())?);
+            init.mems.push((!n.is_dissolved()).then(|| multiset_of(&n.mem)));
+            init.parents.push(n.parent());
+            init.children.push(n.children().to_vec());
+        }
+        let env = h.environment();
+        init.environment = (0..env.type_count())
+            .filter_map(|i| env.tid_at(i).copied())
+            .filter_map(|t| env.get_u(&t).map(|a| (t, a)))
+            .filter(|(_, a)| *a > U::zero())
+            .collect();
+        init.environment.sort_by_key(|(t, _)| *t);
+        Ok(self.explore(&rules, init))
+    }
+
+    fn explore<RT: Clone, U>(&self, rules: &[MemRules<RT, U>], init: Config<U>) -> ConfigGraph<RT, U>
+    where U: Scalar + Hash + Eq {
+        let mut g = ConfigGraph { configs: Vec::new(), index: AHashMap::new(), edges: Vec::new(), expanded: 0, complete: true };
+        g.index.insert(init.clone(), 0);
+        g.configs.push(init);
+        g.edges.push(Vec::new());
+
+        let mut next = 0;
+        while next < g.configs.len() {
+            let cur = next;
+            next += 1;
+            if g.configs[cur].stopped {
+                g.expanded = next;
+                continue;
+            }
+            for (c, fired) in step(rules, &g.configs[cur]) {
+                let to = match g.index.get(&c) {
+                    Some(to) => *to,
+                    None => {
+                        if g.configs.len() >= self.max_configs {
+                            g.complete = false;
+                            continue;
+                        }
+                        let to = g.configs.len();
+                        g.index.insert(c.clone(), to);
+                        g.configs.push(c);
+                        g.edges.push(Vec::new());
+                        to
+                    }
+                };
+                g.edges[cur].push(Transition { to, fired });
+            }
+            if !g.complete {
+                break;
+            }
+            g.expanded = next;
+        }
+        g
+    }
+}
+
+fn multiset_of<T, OT, RT, U>(mem: &BasicMem<T, OT, RT, U>) -> Multiset<U>
+where
+T: Clone + Hash + Eq + Debug + 'static,
+OT: Clone + Hash + Eq + Send + Sync + Debug + 'static,
+RT: Clone + Hash + Eq + Send + Sync + Debug + 'static,
+U: Scalar {
+    let os = mem.objs();
+    let mut m = (0..os.type_count())
+        .filter_map(|i| os.tid_at(i).copied())
+        .filter_map(|t| os.get_u(&t).map(|a| (t, a)))
+        .filter(|(_, a)| *a > U::zero())
+        .collect::<Vec<_>>();
+    m.sort_by_key(|(t, _)| *t);
+    m
+}
+
+/// 系统一步所有可能的后继格局
+fn step<RT: Clone, U>(rules: &[MemRules<RT, U>], c: &Config<U>) -> Vec<(Config<U>, Fired<RT>)>
+where U: Scalar + Hash + Eq {
+    let alive = (0..c.mems.len()).filter(|i| c.mems[*i].is_some()).collect::<Vec<_>>();
+    let per_mem = alive.iter()
+        .map(|i| (*i, mem_outcomes(&rules[*i], c.mems[*i].as_ref().unwrap())))
+        .filter(|(_, o)| !o.is_empty())
+        .collect::<Vec<_>>();
+    if per_mem.is_empty() {
+        return Vec::new();
+    }
+
+    let mut res: AHashMap<Config<U>, Fired<RT>> = AHashMap::new();
+    let mut choice = vec![0; per_mem.len()];
+    loop {
+        let mut next = c.clone();
+        let mut fired = Vec::new();
+        let mut sends = Vec::new();
+        let mut dissolving = Vec::new();
+        for (k, (i, outs)) in per_mem.iter().enumerate() {
+            let (o, f) = &outs[choice[k]];
+            next.mems[*i] = Some(o.objs.clone());
+            fired.extend(f.iter().map(|t| (*i, t.clone())));
+            sends.extend(o.sends.iter().map(|s| (*i, s.clone())));
+            if o.dissolve {
+                dissolving.push(*i);
+            }
+            next.stopped |= o.stop;
+        }
+        for (from, (target, t, a)) in sends {
+            deliver(rules, &mut next, from, target, t, a);
+        }
+        for i in dissolving {
+            dissolve(&mut next, i);
+        }
+        res.entry(next).or_insert(fired);
+
+        // 下一个组合
+        let mut k = 0;
+        while k < choice.len() {
+            choice[k] += 1;
+            if choice[k] < per_mem[k].1.len() {
+                break;
+            }
+            choice[k] = 0;
+            k += 1;
+        }
+        if k == choice.len() {
+            break;
+        }
+    }
+    res.into_iter().collect()
+}
+
+fn deliver<RT, U: Scalar>(rules: &[MemRules<RT, U>], c: &mut Config<U>, from: usize, target: MemTarget, t: TypeId, a: U) {
+    let to = match target {
+        MemTarget::Here => Some(from),
+        MemTarget::Out => c.parents[from],
+        MemTarget::In(label) => c.children[from].iter().copied().find(|ch| rules[*ch].label == label),
+    };
+    match (to, target) {
+        (Some(to), _) => ms_add(c.mems[to].as_mut().unwrap(), t, a),
+        (None, MemTarget::Out) => ms_add(&mut c.environment, t, a),
+        (None, _) => ms_add(c.mems[from].as_mut().unwrap(), t, a),
+    }
+}
+
+fn dissolve<U: Scalar>(c: &mut Config<U>, pos: usize) {
+    let Some(parent) = c.parents[pos] else { return; };
+    let objs = c.mems[pos].take().unwrap_or_default();
+    let target = c.mems[parent].as_mut().unwrap();
+    for (t, a) in objs {
+        ms_add(target, t, a);
+    }
+    let children = std::mem::take(&mut c.children[pos]);
+    for ch in children.iter() {
+        c.parents[*ch] = Some(parent);
+    }
+    c.children[parent].retain(|ch| *ch != pos);
+    c.children[parent].extend(children);
+    c.parents[pos] = None;
+}
@@ -0,0 +1,323 @@
+// Copyright 2024 Junshuang Hu
+use crate::lib_info::log_target;
+use crate as meme;
+use crate::core::*;
+use crate::helpers::derive_seed;
+use crate::meme_derive::*;
+use crate::mems::basic::BasicMem;
+use crate::mems::cycle::{combine, fingerprint, snapshot, CycleDetector, CycleReport, Snapshot};
+use crate::objs::BasicObjStore;
+
+use std::fmt::Debug;
+use std::hash::Hash;
+use log::{log, Level};
+
+use krnl::scalar::Scalar;
+
+#[derive(Debug)]
+pub struct MemNode<T, OT = T, RT = T, U = u32>
+where
+T: Clone + Hash + Eq + Debug + 'static,
+OT: Clone + Hash + Eq + Send + Sync + Debug + 'static,
+RT: Clone + Hash + Eq + Send + Sync + Debug + 'static,
+U: Scalar {
+    pub mem: BasicMem<T, OT, RT, U>,
+    label: usize,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    dissolved: bool
+}
+
+impl<T, OT, RT, U> MemNode<T, OT, RT, U>
+where
+T: Clone + Hash + Eq + Debug + 'static,
+OT: Clone + Hash + Eq + Send + Sync + Debug + 'static,
+RT: Clone + Hash + Eq + Send + Sync + Debug + 'static,
+U: Scalar {
+    pub fn label(&self) -> usize {
+        self.label
+    }
+
+    pub fn parent(&self) -> Option<usize> {
+        self.parent
+    }
+
+    pub fn children(&self) -> &[usize] {
+        &self.children
+    }
+
+    pub fn is_dissolved(&self) -> bool {
+        self.dissolved
+    }
+}
+
+/// 系统的格局：每个膜的父膜与快照（已溶解的膜为 `None`），以及环境的快照
+type Configuration<OT, U> = (Vec<Option<(Option<usize>, Snapshot<OT, U>)>>, Snapshot<OT, U>);
+
+/// 膜层级（膜管理器）
+/// 以树的形式组织多个 [`BasicMem`]，所有膜同步演化：每一步先让每个未溶解的膜演化一次，
+/// 然后投递膜之间发送的对象，最后处理溶解的膜（对象与子膜并入父膜）
+/// 膜用下标（加入的顺序）标识，根膜的下标为 `0`，溶解后的下标不会被复用
+/// 从根膜发送到外部的对象进入环境 [`MemHierarchy::environment`]
+/// 每步结束时所有膜的模拟时间对齐到其中最晚的时间
+#[derive(IObj, Debug)]
+#[obj_type(TypeGroup::Membrane)]
+pub struct MemHierarchy<T, OT = T, RT = T, U = u32>
+where
+T: Clone + Hash + Eq + Debug + 'static,
+OT: Clone + Hash + Eq + Send + Sync + Debug + 'static,
+RT: Clone + Hash + Eq + Send + Sync + Debug + 'static,
+U: Scalar {
+    #[tag]
+    tag: T,
+
+    nodes: Vec<MemNode<T, OT, RT, U>>,
+    environment: BasicObjStore<OT, U>,
+    steps: usize,
+    time: f64,
+    cycles: Option<CycleDetector<(usize, RT), Configuration<OT, U>>>
+}
+
+impl<T, OT, RT, U> MemHierarchy<T, OT, RT, U>
+where
+T: Clone + Hash + Eq + Debug + 'static,
+OT: Clone + Hash + Eq + Send + Sync + Debug + 'static,
+RT: Clone + Hash + Eq + Send + Sync + Debug + 'static,
+U: Scalar {
+    pub fn new(tag: T, root: BasicMem<T, OT, RT, U>, root_label: usize) -> Self {
+        Self {
+            tag,
+            nodes: vec![MemNode { mem: root, label: root_label, parent: None, children: Vec::new(), dissolved: false }],
+            environment: BasicObjStore::new(),
+            steps: 0,
+            time: 0.0,
+            cycles: None
+        }
+    }
+
+    /// 在 `parent` 膜内加入子膜，返回子膜的下标
+    /// 如果 `parent` 不存在或已溶解则返回 `None`
+    pub fn add_child(&mut self, parent: usize, mem: BasicMem<T, OT, RT, U>, label: usize) -> Option<usize> {
+        if self.nodes.get(parent).is_none_or(|n| n.dissolved) {
+            return None;
+        }
+        let pos = self.nodes.len();
+        self.nodes.push(MemNode { mem, label, parent: Some(parent), children: Vec::new(), dissolved: false });
+        self.nodes[parent].children.push(pos);
+        Some(pos)
+    }
+
+    pub fn node(&self, pos: usize) -> Option<&MemNode<T, OT, RT, U>> {
+        self.nodes.get(pos)
+    }
+
+    pub fn mem(&self, pos: usize) -> Option<&BasicMem<T, OT, RT, U>> {
+        self.nodes.get(pos).map(|n| &n.mem)
+    }
+
+    pub fn mem_mut(&mut self, pos: usize) -> Option<&mut BasicMem<T, OT, RT, U>> {
+        self.nodes.get_mut(pos).map(|n| &mut n.mem)
+    }
+
+    /// 所有膜（包括已溶解的）
+    pub fn nodes(&self) -> impl Iterator<Item = &MemNode<T, OT, RT, U>> {
+        self.nodes.iter()
+    }
+
+    /// 未溶解的膜的下标
+    pub fn alive(&self) -> impl Iterator<Item = usize> + '_ {
+        self.nodes.iter().enumerate().filter(|(_, n)| !n.dissolved).map(|(i, _)| i)
+    }
+
+    /// 未溶解的、标签为 `label` 的膜的下标
+    pub fn labelled(&self, label: usize) -> impl Iterator<Item = usize> + '_ {
+        self.alive().filter(move |i| self.nodes[*i].label == label)
+    }
+
+    pub fn environment(&self) -> &BasicObjStore<OT, U> {
+        &self.environment
+    }
+
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    /// 设置随机数种子，每个膜使用由 `seed` 和膜的下标派生的种子  
+    /// 之后加入的膜需要单独设置
+    pub fn set_seed(&mut self, seed: u64) {
+        self.nodes.iter_mut()
+            .enumerate()
+            .for_each(|(i, n)| n.mem.set_seed(derive_seed(seed, i as u64)));
+    }
+
+    /// 第 `i` 个膜的 tag 生成器从 `start + i * width` 开始，每个膜生成的 tag 不超过 `width` 个时各膜的 tag 互不重复
+    pub fn partition_tags(&mut self, start: u64, width: u64) {
+        self.nodes.iter_mut()
+            .enumerate()
+            .for_each(|(i, n)| n.mem.tag_gen_mut().reseed(start + i as u64 * width));
+    }
+
+    /// 重置所有膜的 tag 生成器
+    pub fn reset_tags(&mut self) {
+        self.nodes.iter_mut().for_each(|n| n.mem.tag_gen_mut().reset());
+    }
+
+    /// 开启或关闭环检测，格局（所有膜和环境中的对象以及膜结构）重复时系统停止运行
+    pub fn detect_cycles(&mut self, on: bool) {
+        self.cycles = on.then(CycleDetector::new);
+    }
+
+    /// 检测到的环，规则以 `(膜的下标, 规则的 tag)` 标识
+    pub fn cycle(&self) -> Option<&CycleReport<(usize, RT)>> {
+        self.cycles.as_ref().and_then(|d| d.report())
+    }
+
+    fn configuration(&self) -> (u64, Configuration<OT, U>) {
+        let fp = combine(
+            self.nodes.iter()
+                .map(|n| if n.dissolved { 0 } else { combine([n.parent.unwrap_or(usize::MAX) as u64, fingerprint(n.mem.objs())].into_iter()) })
+                .chain(std::iter::once(fingerprint(&self.environment)))
+        );
+        let mems = self.nodes.iter()
+            .map(|n| (!n.dissolved).then(|| (n.parent, snapshot(n.mem.objs()))))
+            .collect();
+        (fp, (mems, snapshot(&self.environment)))
+    }
+
+    fn deliver(&mut self, from: usize, target: MemTarget, ty: std::any::TypeId, amount: U) {
+        let to = match target {
+            MemTarget::Here => Some(from),
+            MemTarget::Out => self.nodes[from].parent,
+            MemTarget::In(label) => self.nodes[from].children.iter()
+                .copied()
+                .find(|c| self.nodes[*c].label == label),
+        };
+        let res = match (to, &target) {
+            (Some(to), _) => self.nodes[to].mem.objs_mut().increase(&ty, amount),
+            (None, MemTarget::Out) => self.environment.increase(&ty, amount),
+            (None, _) => {
+                log!(
+                    target: log_target::Mem::Exceptions.into(),
+                    Level::Error,
+                    "Hierarchy {:?} : mem {} has no target {:?}, objects stay in place.",
+                    self.tag, from, target
+                );
+                self.nodes[from].mem.objs_mut().increase(&ty, amount)
+            }
+        };
+        if let Err(e) = res { e.log(); }
+    }
+
+    /// 溶解 `pos` 膜，对象与子膜并入父膜，根膜不能被溶解
+    fn dissolve(&mut self, pos: usize) {
+        let Some(parent) = self.nodes[pos].parent else {
+            log!(
+                target: log_target::Mem::Exceptions.into(),
+                Level::Error,
+                "Hierarchy {:?} : trying to dissolve the root mem.",
+                self.tag
+            );
+            return;
+        };
+        let agenda = self.nodes[pos].mem.take_agenda();
+        for (at, item) in agenda.into_iter() {
+            self.nodes[parent].mem.schedule(at, item);
+        }
+        let mut objs = self.nodes[pos].mem.take_objs();
+        let untagged = (0..objs.type_count())
+            .filter_map(|i| objs.tid_at(i).copied())
+            .filter_map(|ty| objs.get_u(&ty).map(|a| (ty, a)))
+            .collect::<Vec<_>>();
+        let tags = objs.objs().map(|o| o.obj_tag().clone()).collect::<Vec<_>>();
+        let target = self.nodes[parent].mem.objs_mut();
+        for (ty, a) in untagged {
+            if a > U::zero() {
+                if let Err(e) = target.increase(&ty, a) { e.log(); }
+            }
+        }
+        for o in objs.remove_batch_skip(&tags) {
+            target.put(o);
+        }
+
+        let children = std::mem::take(&mut self.nodes[pos].children);
+        for c in children.iter() {
+            self.nodes[*c].parent = Some(parent);
+        }
+        let siblings = &mut self.nodes[parent].children;
+        siblings.retain(|c| *c != pos);
+        siblings.extend(children);
+        self.nodes[pos].dissolved = true;
+    }
+}
+
+impl<T, OT, RT, U> IMem for MemHierarchy<T, OT, RT, U>
+where
+T: Clone + Hash + Eq + Send + Sync + Debug + 'static,
+OT: Clone + Hash + Eq + Send + Sync + Debug + 'static,
+RT: Clone + Hash + Eq + Send + Sync + Debug + 'static,
+U: Scalar {
+    fn ready(&self) -> bool {
+        self.nodes.iter().filter(|n| !n.dissolved).all(|n| n.mem.ready())
+    }
+
+    fn time(&self) -> f64 {
+        self.time
+    }
+
+    /// 所有膜都无法执行规则且没有对象需要投递时返回 [`EmuStatus::Pause`]，即系统停机
+    fn evolve(&mut self) -> EmuStatus {
+        if self.cycle().is_some() {
+            return EmuStatus::Stopped;
+        }
+        let conf = self.cycles.as_ref().is_some_and(|d| d.steps() == 0).then(|| self.configuration());
+        if let (Some(conf), Some(d)) = (conf, self.cycles.as_mut()) {
+            d.start(|| conf);
+        }
+        let alive = self.alive().collect::<Vec<_>>();
+        let mut status = EmuStatus::Pause;
+        for i in alive.iter() {
+            match self.nodes[*i].mem.evolve() {
+                EmuStatus::Continue => {
+                    if status == EmuStatus::Pause { status = EmuStatus::Continue; }
+                },
+                EmuStatus::Pause => {},
+                s => { status = s; }
+            }
+        }
+
+        let mut dissolving = Vec::new();
+        let fired = alive.iter()
+            .flat_map(|i| self.nodes[*i].mem.last_fired().iter().map(|r| (*i, r.clone())))
+            .collect::<Vec<_>>();
+        for i in alive {
+            for (target, ty, a) in self.nodes[i].mem.take_outbox() {
+                self.deliver(i, target, ty, a);
+            }
+            if self.nodes[i].mem.is_dissolving() {
+                dissolving.push(i);
+            }
+        }
+        for i in dissolving {
+            self.dissolve(i);
+        }
+        self.steps += 1;
+        let alive = self.alive().collect::<Vec<_>>();
+        self.time = alive.iter().map(|i| self.nodes[*i].mem.time()).fold(self.time, f64::max);
+        for i in alive {
+            self.nodes[i].mem.advance_to(self.time);
+        }
+        let conf = (status == EmuStatus::Continue && self.cycles.is_some()).then(|| self.configuration());
+        if let (Some((fp, snap)), Some(d)) = (conf, self.cycles.as_mut()) {
+            if let Some(c) = d.record(fp, snap, fired) {
+                log!(
+                    target: log_target::Mem::Info.into(),
+                    Level::Warn,
+                    "Hierarchy {:?} : configuration of step {} repeats at step {} (cycle length {}), rules {:?}.",
+                    self.tag, c.first_seen, c.step, c.length, c.rules
+                );
+                return EmuStatus::Stopped;
+            }
+        }
+        status
+    }
+}
@@ -0,0 +1,119 @@
+// Copyright 2024 Junshuang Hu
+use std::fmt::{Debug, Display};
+use std::hash::Hash;
+
+use krnl::scalar::Scalar;
+
+use crate::core::{IObjStat, ITaggedStore, ObjType};
+use crate::objs::BasicObjStore;
+
+pub type InvariantFn<OT, U> = Box<dyn Fn(&BasicObjStore<OT, U>) -> bool + Send + Sync>;
+
+pub enum InvariantKind<OT, U>
+where OT: Clone + Hash + Eq, U: Scalar {
+    /// 这些类型的对象数量之和（包括 tagged 对象）保持不变，以第一次演化前的数量为准
+    ConstantSum(Vec<ObjType>),
+    /// 指定 tag 的对象总是存在
+    TagPresent(OT),
+    /// 任意条件
+    Holds(InvariantFn<OT, U>)
+}
+
+impl<OT, U> Debug for InvariantKind<OT, U>
+where OT: Clone + Hash + Eq + Debug, U: Scalar {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ConstantSum(tys) => f.debug_tuple("ConstantSum").field(&tys.iter().map(|t| t.name).collect::<Vec<_>>()).finish(),
+            Self::TagPresent(t) => f.debug_tuple("TagPresent").field(t).finish(),
+            Self::Holds(_) => f.write_str("Holds(..)"),
+        }
+    }
+}
+
+/// 膜的不变量，膜在每一步应用影响后检查，被违反时停止运行并返回 [`crate::core::EmuStatus::EmuError`]
+#[derive(Debug)]
+pub struct Invariant<OT, U>
+where OT: Clone + Hash + Eq, U: Scalar {
+    name: String,
+    kind: InvariantKind<OT, U>,
+    baseline: Option<U>
+}
+
+impl<OT, U> Invariant<OT, U>
+where OT: Clone + Hash + Eq, U: Scalar {
+    pub fn new(name: &str, kind: InvariantKind<OT, U>) -> Self {
+        Self { name: name.to_string(), kind, baseline: None }
+    }
+
+    /// # 例子
+    /// ```
+    /// use meme::mems::invariant::Invariant;
+    /// use meme::core::ObjType;
+    /// use meme_derive::IObj;
+    ///
+    /// #[derive(IObj, Debug)]
+    /// struct A { #[tag] tag: u32 }
+    /// #[derive(IObj, Debug)]
+    /// struct B { #[tag] tag: u32 }
+    ///
+    /// let inv = Invariant::<u32, u32>::constant_sum("a + b", &[ObjType::default_group::<A>(), ObjType::default_group::<B>()]);
+    /// assert_eq!(inv.name(), "a + b");
+    /// ```
+    pub fn constant_sum(name: &str, tys: &[ObjType]) -> Self {
+        Self::new(name, InvariantKind::ConstantSum(tys.to_vec()))
+    }
+
+    pub fn tag_present(name: &str, tag: OT) -> Self {
+        Self::new(name, InvariantKind::TagPresent(tag))
+    }
+
+    pub fn holds<F>(name: &str, f: F) -> Self
+    where F: Fn(&BasicObjStore<OT, U>) -> bool + Send + Sync + 'static {
+        Self::new(name, InvariantKind::Holds(Box::new(f)))
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn kind(&self) -> &InvariantKind<OT, U> {
+        &self.kind
+    }
+
+    fn sum(tys: &[ObjType], os: &BasicObjStore<OT, U>) -> U {
+        tys.iter().filter_map(|t| os.amount_of(t)).fold(U::zero(), |a, b| a + b)
+    }
+
+    /// 在演化前记录需要的初始值，只在第一次调用时生效
+    pub fn prepare(&mut self, os: &BasicObjStore<OT, U>) {
+        if let (InvariantKind::ConstantSum(tys), None) = (&self.kind, &self.baseline) {
+            self.baseline = Some(Self::sum(tys, os));
+        }
+    }
+
+    pub fn check(&self, os: &BasicObjStore<OT, U>) -> bool {
+        match &self.kind {
+            InvariantKind::ConstantSum(tys) => self.baseline.is_none_or(|b| Self::sum(tys, os) == b),
+            InvariantKind::TagPresent(t) => os.contains(t),
+            InvariantKind::Holds(f) => f(os),
+        }
+    }
+}
+
+/// 不变量被违反的报告
+#[derive(Debug, Clone)]
+pub struct InvariantViolation<RT> {
+    /// 违反发生的步数，从 `0` 开始
+    pub step: usize,
+    /// 违反发生时的模拟时间
+    pub time: f64,
+    pub invariant: String,
+    /// 该步中执行了的规则
+    pub fired: Vec<RT>
+}
+
+impl<RT: Debug> Display for InvariantViolation<RT> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invariant \"{}\" violated at step {} (time {}) after rules {:?} fired", self.invariant, self.step, self.time, self.fired)
+    }
+}
@@ -0,0 +1,91 @@
+// Copyright 2024 Junshuang Hu
+use std::any::TypeId;
+use std::hash::Hash;
+
+use ahash::AHashMap;
+use krnl::scalar::Scalar;
+
+use crate::core::{ICondition, IObjStat, ITaggedStore, ObjType, TaggedPresenceInfo};
+use crate::objs::BasicObjStore;
+
+/// 规则在某一步没有执行的原因，只记录第一个不满足的需求
+#[derive(Debug, Clone, PartialEq)]
+pub enum FailReason<OT, U> {
+    /// 需要的指定 tag 的对象不存在
+    MissingTag(OT),
+    /// 某类对象的数量不足
+    InsufficientAmount { ty: ObjType, needed: U, present: U },
+    /// 可供随机选择的某类 tagged 对象不足
+    TooFewCandidates { ty: ObjType, needed: usize, found: usize },
+    /// 可以执行，但有优先级更高的规则可以执行
+    LowerPriority,
+    /// 可以执行，但在冲突中需要的对象被先执行的规则用掉了
+    LostConflict,
+    /// 条件满足，但按规则的概率本步没有抽中
+    NotDrawn
+}
+
+/// 单条规则的执行统计
+#[derive(Debug, Clone)]
+pub struct RuleStats<OT, U> {
+    /// 被检查的次数
+    pub checked: usize,
+    /// 检查时条件满足的次数
+    pub applicable: usize,
+    /// 无冲突并行执行的步数，[`crate::mems::stochastic::EvolveMode::MaximalMultiset`] 下一步中执行多次也只计一次
+    pub fired_parallel: usize,
+    /// 作为冲突规则依次执行的次数
+    pub fired_conflict: usize,
+    /// 作为冲突规则却没能执行的次数
+    pub lost_conflict: usize,
+    /// 最近一次没有执行的原因
+    pub last_failure: Option<FailReason<OT, U>>
+}
+
+impl<OT, U> Default for RuleStats<OT, U> {
+    fn default() -> Self {
+        Self { checked: 0, applicable: 0, fired_parallel: 0, fired_conflict: 0, lost_conflict: 0, last_failure: None }
+    }
+}
+
+impl<OT, U> RuleStats<OT, U> {
+    pub fn fired(&self) -> usize {
+        self.fired_parallel + self.fired_conflict
+    }
+}
+
+/// 找出条件中第一个不满足的需求，顺序与 [`crate::core::IRuleStat::check_on`] 的检查顺序相同
+pub fn diagnose<OT, U, C>(c: &C, os: &BasicObjStore<OT, U>) -> Option<FailReason<OT, U>>
+where OT: Clone + Hash + Eq, U: Scalar, C: ICondition<OT, U> {
+    for u in c.untagged().iter().flatten() {
+        let present = os.amount_of_u(&u.ty).unwrap_or(U::zero());
+        if present < u.amount {
+            return Some(FailReason::InsufficientAmount { ty: u.ty.clone(), needed: u.amount, present });
+        }
+    }
+    let mut used: AHashMap<TypeId, usize> = AHashMap::new();
+    for p in c.tagged().iter().flatten() {
+        match &p.info {
+            TaggedPresenceInfo::OfTag(t) => {
+                match os.get(t) {
+                    Some(o) => *used.entry(o.obj_type().tid).or_insert(0) += 1,
+                    None => return Some(FailReason::MissingTag(t.clone())),
+                }
+            },
+            TaggedPresenceInfo::RandTags((ty, n)) => {
+                let tags = os.tags_of(&ty.tid).unwrap_or(&[]);
+                let total = match &p.select {
+                    Some(sel) => os.get_batch_skip(tags).into_iter().filter(|o| sel.accepts(&***o)).count(),
+                    None => tags.len(),
+                };
+                let taken = used.entry(ty.tid).or_insert(0);
+                let found = total.saturating_sub(*taken);
+                if found < *n {
+                    return Some(FailReason::TooFewCandidates { ty: ty.clone(), needed: *n, found });
+                }
+                *taken += n;
+            }
+        }
+    }
+    None
+}
@@ -0,0 +1,230 @@
+// Copyright 2024 Junshuang Hu
+//! 随机模拟模式
+//!
+//! 规则带有动力学速率常数（[`crate::core::IRule::rate`]），untagged 对象的数量视为分子数，
+//! 按质量作用定律计算每条规则的倾向函数
+//! - [`EvolveMode::Gillespie`]：Gillespie 直接法，每步只执行一条规则，并按指数分布推进模拟时间
+//! - [`EvolveMode::TauLeap`]：tau-leaping 近似方法，每步推进时间 tau，每条规则执行泊松分布的次数，
+//!   只适用于只作用于 untagged 对象的规则，其他情况下退回直接法
+//! - [`EvolveMode::MaximalMultiset`]：P 系统的极大并行语义，规则不需要速率，
+//!   每步不确定地选择规则，每条规则按剩余对象允许的次数执行，直到没有规则可以再执行
+
+use std::any::TypeId;
+use std::f64::consts::PI;
+use std::hash::Hash;
+
+use ahash::AHashMap;
+use krnl::scalar::Scalar;
+use rand::Rng;
+use rand::seq::SliceRandom;
+
+use crate::core::{ICondition, IObjStat, IRuleEffect, ITaggedStore, MemTarget, OperationEffect, TaggedPresenceInfo};
+use crate::objs::BasicObjStore;
+
+/// 膜的演化方式
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum EvolveMode {
+    /// 每步以极大并行的方式执行规则，默认方式
+    #[default]
+    MaximallyParallel,
+    /// Gillespie 直接法
+    Gillespie,
+    /// tau-leaping
+    TauLeap(TauLeap),
+    /// P 系统的极大并行语义，只适用于只作用于 untagged 对象的规则，其他情况下按默认方式执行一步  
+    /// 溶解膜的规则每步最多执行一次，优先级（包括 [`crate::rules::BasicRuleStore::add_priority`] 声明的优先关系）
+    /// 按步开始时的格局筛选规则
+    MaximalMultiset
+}
+
+/// tau-leaping 的参数
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TauLeap {
+    /// 一步中倾向函数允许的相对变化，越小越精确
+    pub epsilon: f64,
+    /// tau 小于 `ssa_threshold / a0` 时改用直接法执行一步，`a0` 为倾向函数之和
+    pub ssa_threshold: f64,
+    /// 出现负数量时 tau 减半重试的最多次数，之后改用直接法
+    pub max_retries: usize
+}
+
+impl Default for TauLeap {
+    fn default() -> Self {
+        Self { epsilon: 0.03, ssa_threshold: 10.0, max_retries: 10 }
+    }
+}
+
+/// 组合数 C(n, k)
+pub fn binomial(n: f64, k: f64) -> f64 {
+    if k > n {
+        return 0.0;
+    }
+    let mut r = 1.0;
+    let mut i = 0.0;
+    while i < k {
+        r *= (n - i) / (i + 1.0);
+        i += 1.0;
+    }
+    r
+}
+
+/// 质量作用定律下的倾向函数 `k·∏C(n_i, a_i)`
+/// 指定 tag 的需求不存在时为 `0`，随机选择的需求按可选对象的组合数计算，不考虑不同需求之间的重叠  
+/// 随机选择的需求通过按类型的索引（[`ITaggedStore::tags_of`]）计数，只访问该类型的对象
+pub fn propensity<OT, U, C>(c: &C, os: &BasicObjStore<OT, U>, k: f64) -> f64
+where OT: Clone + Hash + Eq, U: Scalar, C: ICondition<OT, U> {
+    let mut a = k;
+    for u in c.untagged().iter().flatten() {
+        let n = os.amount_of_u(&u.ty).map_or(0.0, |n| n.cast::<f64>());
+        a *= binomial(n, u.amount.cast::<f64>());
+    }
+    for p in c.tagged().iter().flatten() {
+        match &p.info {
+            TaggedPresenceInfo::OfTag(t) => {
+                if !os.contains(t) {
+                    return 0.0;
+                }
+            },
+            TaggedPresenceInfo::RandTags((ty, n)) => {
+                let tags = os.tags_of(&ty.tid).unwrap_or(&[]);
+                let found = match &p.select {
+                    Some(sel) => os.get_batch_skip(tags).into_iter().filter(|o| sel.accepts(&***o)).count(),
+                    None => tags.len(),
+                };
+                a *= binomial(found as f64, *n as f64);
+            }
+        }
+        if a == 0.0 {
+            return 0.0;
+        }
+    }
+    a
+}
+
+/// 按倾向函数抽取下一条规则和时间增量，所有倾向函数都为 `0` 时返回 [`None`]
+pub fn sample_next<R: Rng + ?Sized>(props: &[f64], rng: &mut R) -> Option<(usize, f64)> {
+    let a0 = props.iter().sum::<f64>();
+    if a0 <= 0.0 || !a0.is_finite() {
+        return None;
+    }
+    let tau = -(1.0 - rng.gen::<f64>()).ln() / a0;
+    let r = rng.gen::<f64>() * a0;
+    let mut acc = 0.0;
+    let mut last = None;
+    for (i, p) in props.iter().enumerate().filter(|(_, p)| **p > 0.0) {
+        acc += p;
+        last = Some(i);
+        if r < acc {
+            break;
+        }
+    }
+    last.map(|i| (i, tau))
+}
+
+/// 规则执行一次时本膜中各类型数量的净变化和发送到其他膜的对象，以及是否溶解本膜
+#[derive(Debug, Clone)]
+pub struct Stoichiometry<U> {
+    pub change: Vec<(TypeId, f64)>,
+    pub send: Vec<(MemTarget, TypeId, U)>,
+    pub dissolve: bool
+}
+
+/// 只作用于 untagged 对象的规则的化学计量，规则有 tagged 需求或其他操作时返回 [`None`]
+pub fn stoichiometry<OT, U, C, E>(c: &C, e: &E) -> Option<Stoichiometry<U>>
+where OT: Clone + Hash + Eq + Send + Sync, U: Scalar, C: ICondition<OT, U>, E: IRuleEffect<Effect = OperationEffect<OT, U>> {
+    if c.tagged().as_ref().is_some_and(|t| !t.is_empty()) {
+        return None;
+    }
+    let mut change: AHashMap<TypeId, f64> = AHashMap::new();
+    let mut send = Vec::new();
+    let mut dissolve = false;
+    for op in e.effects().iter().flatten() {
+        match op {
+            OperationEffect::IncreaseObjUntagged((ty, a)) => *change.entry(ty.tid).or_insert(0.0) += a.cast::<f64>(),
+            OperationEffect::DecreaseObjUntagged((ty, a)) => *change.entry(ty.tid).or_insert(0.0) -= a.cast::<f64>(),
+            OperationEffect::SendObjUntagged((ty, a, target)) => send.push((target.clone(), ty.tid, *a)),
+            OperationEffect::DissolveMem => dissolve = true,
+            _ => return None,
+        }
+    }
+    let mut change = change.into_iter().filter(|(_, d)| *d != 0.0).collect::<Vec<_>>();
+    change.sort_by_key(|(t, _)| *t);
+    Some(Stoichiometry { change, send, dissolve })
+}
+
+/// 按 Gillespie (2001) 的方法选择 tau：每类对象数量的期望变化和方差都不超过 `max(ε·x, 1)` 的相应量级
+pub fn select_tau<U, F>(props: &[f64], stoich: &[&Stoichiometry<U>], amount: F, epsilon: f64) -> f64
+where F: Fn(&TypeId) -> f64 {
+    let mut moments: AHashMap<TypeId, (f64, f64)> = AHashMap::new();
+    for (a, st) in props.iter().zip(stoich.iter()) {
+        for (ty, v) in st.change.iter() {
+            let m = moments.entry(*ty).or_insert((0.0, 0.0));
+            m.0 += v * a;
+            m.1 += v * v * a;
+        }
+    }
+    moments.into_iter()
+        .map(|(ty, (mu, sigma2))| {
+            let bound = (epsilon * amount(&ty)).max(1.0);
+            let t1 = if mu != 0.0 { bound / mu.abs() } else { f64::INFINITY };
+            let t2 = if sigma2 != 0.0 { bound * bound / sigma2 } else { f64::INFINITY };
+            t1.min(t2)
+        })
+        .fold(f64::INFINITY, f64::min)
+}
+
+/// 泊松分布的随机数，均值不小于 30 时使用正态近似
+pub fn poisson<R: Rng + ?Sized>(lambda: f64, rng: &mut R) -> u64 {
+    if lambda <= 0.0 || !lambda.is_finite() {
+        return 0;
+    }
+    if lambda < 30.0 {
+        let l = (-lambda).exp();
+        let mut k = 0;
+        let mut p = rng.gen::<f64>();
+        while p > l {
+            k += 1;
+            p *= rng.gen::<f64>();
+        }
+        k
+    } else {
+        let (u1, u2) = (1.0 - rng.gen::<f64>(), rng.gen::<f64>());
+        let z = (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos();
+        (lambda + z * lambda.sqrt() + 0.5).floor().max(0.0) as u64
+    }
+}
+
+/// 不确定地选择规则的极大多重集：反复随机选择一条仍可执行的规则，执行随机的次数（不超过剩余对象允许的次数），
+/// 直到没有规则可以执行，返回每条规则执行的次数  
+/// `reqs[j]` 为第 `j` 条规则执行一次需要的 untagged 对象，`once[j]` 为真或没有需求的规则最多执行一次
+pub fn maximal_multiset<F, R>(reqs: &[Vec<(TypeId, f64)>], once: &[bool], amount: F, rng: &mut R) -> Vec<u64>
+where F: Fn(&TypeId) -> f64, R: Rng + ?Sized {
+    let mut avail: AHashMap<TypeId, f64> = AHashMap::new();
+    for (ty, _) in reqs.iter().flatten() {
+        avail.entry(*ty).or_insert_with(|| amount(ty));
+    }
+    let mut counts = vec![0u64; reqs.len()];
+    let single = |j: usize| once.get(j).copied().unwrap_or(false) || reqs[j].iter().all(|(_, a)| *a <= 0.0);
+    loop {
+        let open = reqs.iter()
+            .enumerate()
+            .filter(|(j, _)| !(single(*j) && counts[*j] > 0))
+            .map(|(j, req)| {
+                let k = req.iter()
+                    .filter(|(_, a)| *a > 0.0)
+                    .map(|(ty, a)| (avail[ty] / a).floor())
+                    .fold(f64::INFINITY, f64::min);
+                (j, k)
+            })
+            .filter(|(_, k)| *k >= 1.0)
+            .collect::<Vec<_>>();
+        let Some(&(j, k)) = open.choose(rng) else {
+            return counts;
+        };
+        let n = if single(j) { 1 } else { rng.gen_range(1..=k as u64) };
+        for (ty, a) in reqs[j].iter() {
+            *avail.get_mut(ty).unwrap() -= a * n as f64;
+        }
+        counts[j] += n;
+    }
+}
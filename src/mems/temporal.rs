@@ -0,0 +1,189 @@
+// Copyright 2024 Junshuang Hu
+//! 可达格局图上的简单时序性质检查
+//!
+//! - [`ConfigGraph::check_halting_exists`]：存在满足条件的停机格局，如"某个停机格局的输出为 n"
+//! - [`ConfigGraph::check_always`]：所有可达格局都满足条件，如"X 的数量从不超过 k"
+//! - [`ConfigGraph::check_eventually_always`]：每条计算最终一直满足条件，如"最终一直 Y > 0"
+//!
+//! 停机格局视为一直停留在该格局；格局图不完整时找不到反例的结果为未知
+
+use std::collections::VecDeque;
+use std::hash::Hash;
+
+use krnl::scalar::Scalar;
+
+use crate::mems::explore::{Config, ConfigGraph, Fired};
+
+/// 一条计算路径，`configs` 比 `steps` 多一个元素，`steps[i]` 为从 `configs[i]` 到 `configs[i + 1]` 执行的规则
+/// `cycle_start` 不为 [`None`] 时路径最后一个格局与 `configs[cycle_start]` 相同，之后无限重复这个环
+#[derive(Debug, Clone)]
+pub struct Trace<RT> {
+    pub configs: Vec<usize>,
+    pub steps: Vec<Fired<RT>>,
+    pub cycle_start: Option<usize>
+}
+
+/// 检查结果，`holds` 为 [`None`] 表示格局图不完整而无法判断
+/// `trace` 对存在性性质为例子，对全称性质为反例
+#[derive(Debug, Clone)]
+pub struct Verdict<RT> {
+    pub holds: Option<bool>,
+    pub trace: Option<Trace<RT>>
+}
+
+impl<RT> Verdict<RT> {
+    fn undecided(complete: bool, holds: bool) -> Self {
+        Self { holds: complete.then_some(holds), trace: None }
+    }
+}
+
+impl<RT, U> ConfigGraph<RT, U>
+where RT: Clone, U: Scalar + Hash + Eq {
+    /// 从 `from` 到 `to` 的最短路径
+    pub fn path(&self, from: usize, to: usize) -> Option<Trace<RT>> {
+        let mut prev: Vec<Option<(usize, usize)>> = vec![None; self.len()];
+        let mut queue = VecDeque::from([from]);
+        let mut seen = vec![false; self.len()];
+        seen[from] = true;
+        while let Some(c) = queue.pop_front() {
+            if c == to {
+                let mut configs = vec![to];
+                let mut steps = Vec::new();
+                let mut cur = to;
+                while let Some((p, e)) = prev[cur] {
+                    steps.push(self.successors(p)[e].fired.clone());
+                    configs.push(p);
+                    cur = p;
+                }
+                configs.reverse();
+                steps.reverse();
+                return Some(Trace { configs, steps, cycle_start: None });
+            }
+            for (e, t) in self.successors(c).iter().enumerate() {
+                if !seen[t.to] {
+                    seen[t.to] = true;
+                    prev[t.to] = Some((c, e));
+                    queue.push_back(t.to);
+                }
+            }
+        }
+        None
+    }
+
+    /// 从初始格局到 `to` 的最短路径
+    pub fn path_to(&self, to: usize) -> Option<Trace<RT>> {
+        self.path(0, to)
+    }
+
+    /// 是否存在满足 `pred` 的停机格局，成立时给出到达该格局的路径
+    pub fn check_halting_exists<F>(&self, pred: F) -> Verdict<RT>
+    where F: Fn(&Config<U>) -> bool {
+        match self.halting().into_iter().find(|i| pred(&self.configs()[*i])) {
+            Some(i) => Verdict { holds: Some(true), trace: self.path_to(i) },
+            None => Verdict::undecided(self.is_complete(), false),
+        }
+    }
+
+    /// 是否所有可达格局都满足 `pred`，不成立时给出到达反例格局的最短路径
+    pub fn check_always<F>(&self, pred: F) -> Verdict<RT>
+    where F: Fn(&Config<U>) -> bool {
+        match (0..self.len()).find(|i| !pred(&self.configs()[*i])) {
+            Some(i) => Verdict { holds: Some(false), trace: self.path_to(i) },
+            None => Verdict::undecided(self.is_complete(), true),
+        }
+    }
+
+    /// 是否每条计算最终都一直满足 `pred`
+    /// 不成立时存在一条无限次经过不满足 `pred` 的格局的计算，反例为到达该格局的路径加上回到该格局的环
+    pub fn check_eventually_always<F>(&self, pred: F) -> Verdict<RT>
+    where F: Fn(&Config<U>) -> bool {
+        let scc = self.components();
+        let mut size = vec![0usize; self.len()];
+        for c in scc.iter() {
+            size[*c] += 1;
+        }
+        for i in (0..self.len()).filter(|i| !pred(&self.configs()[*i])) {
+            if self.is_halting(i) {
+                let mut trace = self.path_to(i);
+                if let Some(t) = trace.as_mut() {
+                    t.cycle_start = Some(t.configs.len() - 1);
+                }
+                return Verdict { holds: Some(false), trace };
+            }
+            let on_cycle = size[scc[i]] > 1 || self.successors(i).iter().any(|t| t.to == i);
+            if !on_cycle {
+                continue;
+            }
+            let trace = self.path_to(i).map(|mut prefix| {
+                let start = prefix.configs.len() - 1;
+                let (to, fired) = self.successors(i).iter()
+                    .find(|t| scc[t.to] == scc[i])
+                    .map(|t| (t.to, t.fired.clone()))
+                    .unwrap();
+                prefix.configs.push(to);
+                prefix.steps.push(fired);
+                if to != i {
+                    let back = self.path(to, i).unwrap();
+                    prefix.configs.extend(back.configs.into_iter().skip(1));
+                    prefix.steps.extend(back.steps);
+                }
+                prefix.cycle_start = Some(start);
+                prefix
+            });
+            return Verdict { holds: Some(false), trace };
+        }
+        Verdict::undecided(self.is_complete(), true)
+    }
+
+    /// 强连通分量，返回每个格局所在分量的编号
+    fn components(&self) -> Vec<usize> {
+        let n = self.len();
+        // 第一遍：按完成顺序排列
+        let mut order = Vec::with_capacity(n);
+        let mut visited = vec![false; n];
+        for s in 0..n {
+            if visited[s] {
+                continue;
+            }
+            visited[s] = true;
+            let mut stack = vec![(s, 0)];
+            while let Some((v, k)) = stack.pop() {
+                if let Some(t) = self.successors(v).get(k) {
+                    stack.push((v, k + 1));
+                    if !visited[t.to] {
+                        visited[t.to] = true;
+                        stack.push((t.to, 0));
+                    }
+                } else {
+                    order.push(v);
+                }
+            }
+        }
+        // 第二遍：在反向图上按完成顺序的逆序遍历
+        let mut rev = vec![Vec::new(); n];
+        for v in 0..n {
+            for t in self.successors(v) {
+                rev[t.to].push(v);
+            }
+        }
+        let mut comp = vec![usize::MAX; n];
+        let mut count = 0;
+        for s in order.into_iter().rev() {
+            if comp[s] != usize::MAX {
+                continue;
+            }
+            comp[s] = count;
+            let mut stack = vec![s];
+            while let Some(v) = stack.pop() {
+                for p in rev[v].iter() {
+                    if comp[*p] == usize::MAX {
+                        comp[*p] = count;
+                        stack.push(*p);
+                    }
+                }
+            }
+            count += 1;
+        }
+        comp
+    }
+}
@@ -1,31 +1,228 @@
 // Copyright 2024 Junshuang Hu
-use std::{any::TypeId, collections::hash_map::Values, hash::Hash};
+use std::{any::TypeId, collections::{hash_map::Values, HashMap}, hash::Hash};
 
-use ahash::AHashMap;
-use krnl::scalar::Scalar;
+use ahash::{AHashMap, RandomState};
+use krnl::scalar::{Scalar, ScalarType};
 
-use crate::core::{IObjStat, ITaggedStore, IUntaggedStore, IndexMap, PObj};
+use log::{log, Level};
+
+use crate::lib_info::log_target;
+use crate::core::{IObjStat, ITaggedStore, IUntaggedStore, IndexMap, ObjChanges, PObj};
+use crate::errors::AmountError;
 
 pub mod com;
-// todo: 分类储存obj
+pub mod symbol;
+pub mod uid;
+// todo: 分类储存obj -ok
+
+/// 同一类型的 tagged 对象的 tag，删除时与末尾交换，可以按位置随机访问
+#[derive(Debug)]
+struct TagIndex<T> {
+    tags: Vec<T>,
+    pos: AHashMap<T, usize>
+}
+
+impl<T> TagIndex<T>
+where T: Clone + Hash + Eq {
+    fn new() -> Self {
+        Self { tags: Vec::new(), pos: AHashMap::new() }
+    }
+
+    fn insert(&mut self, t: T) {
+        if !self.pos.contains_key(&t) {
+            self.pos.insert(t.clone(), self.tags.len());
+            self.tags.push(t);
+        }
+    }
+
+    fn remove(&mut self, t: &T) {
+        if let Some(p) = self.pos.remove(t) {
+            self.tags.swap_remove(p);
+            if let Some(moved) = self.tags.get(p) {
+                self.pos.insert(moved.clone(), p);
+            }
+        }
+    }
+}
+
+/// 一类对象的数量，tagged 为该类 tagged 对象的 [`crate::core::IObj::obj_amount`] 之和
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Amounts<U> {
+    pub tagged: U,
+    pub untagged: U
+}
+
+impl<U: Scalar> Amounts<U> {
+    /// tagged 与 untagged 数量之和，溢出时返回 `None`
+    pub fn total(&self) -> Option<U> {
+        checked_add(self.tagged, self.untagged)
+    }
+}
+
+/// 不溢出时返回 `a + b`  
+/// 整数转换为 `U` 对应的具体类型后使用其 `checked_add`，浮点数要求结果是有限值
+fn checked_add<U: Scalar>(a: U, b: U) -> Option<U> {
+    macro_rules! checked {
+        ($($st:ident => $t:ty),*) => {
+            match U::SCALAR_TYPE {
+                $(ScalarType::$st => a.cast::<$t>().checked_add(b.cast::<$t>()).map(|_| a + b),)*
+                _ => Some(a + b).filter(|s| s.cast::<f64>().is_finite())
+            }
+        };
+    }
+    checked!(U8 => u8, I8 => i8, U16 => u16, I16 => i16, U32 => u32, I32 => i32, U64 => u64, I64 => i64)
+}
+
+/// 加入对象失败时交回的对象与错误
+type Rejected<T, U> = (PObj<T, U>, AmountError<TypeId, U>);
 
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub struct BasicObjStore<T = u32, U = u32>
 where T: Clone + Hash + Eq, U: Scalar {
     instances: AHashMap<T, PObj<T, U>>,
-    amount: IndexMap<TypeId,(U, U)>,
-    modified: bool
+    by_type: AHashMap<TypeId, TagIndex<T>>,
+    amount: IndexMap<TypeId, Amounts<U>>,
+    changes: ObjChanges<T>,
+    check_collisions: bool,
+    collisions: Vec<T>
 }
 
 impl<T, U> BasicObjStore<T, U> 
 where T: Clone + Hash + Eq, U: Scalar {
+    /// tagged 对象使用固定种子的哈希，使遍历顺序只取决于操作的顺序，随机选择因此可以复现
     pub fn new() -> Self {
-        Self { instances: AHashMap::new(), amount: IndexMap::new(), modified: false }
+        let instances = HashMap::with_hasher(RandomState::with_seeds(0x6f62_6a73, 0x7461_6773, 0x6d65_6d65, 0x7365_6564));
+        Self { instances: AHashMap::from(instances), by_type: AHashMap::new(), amount: IndexMap::new(), changes: ObjChanges::new(), check_collisions: false, collisions: Vec::new() }
+    }
+
+    /// 开启后 [`BasicObjStore::put`] 使用 [`ITaggedStore::try_add`]，不再替换 tag 已存在的对象，
+    /// 冲突的 tag 记录在 [`BasicObjStore::collisions`] 中
+    pub fn set_collision_check(&mut self, on: bool) {
+        self.check_collisions = on;
+    }
+
+    /// 按对象自身的 tag 加入对象  
+    /// 开启冲突检查时使用 [`ITaggedStore::try_add`]，否则使用 [`ITaggedStore::add_or_update`]
+    pub fn put(&mut self, o: PObj<T, U>) {
+        let t = o.obj_tag().clone();
+        if !self.check_collisions {
+            self.add_or_update(t, o);
+        } else if let Err(o) = self.try_add(t.clone(), o) {
+            if self.instances.contains_key(&t) {
+                log!(
+                    target: log_target::Mem::Exceptions.into(),
+                    Level::Error,
+                    "Tag collision when adding obj {:?}, the new obj is rejected.",
+                    o
+                );
+                self.collisions.push(t);
+            }
+        }
+    }
+
+    pub fn collisions(&self) -> &[T] {
+        &self.collisions
+    }
+
+    pub fn clear_collisions(&mut self) {
+        self.collisions.clear();
     }
 
-    pub fn objs(&self) -> Values<T, PObj<T, U>> {
+    pub fn objs(&self) -> Values<'_, T, PObj<T, U>> {
         self.instances.values()
     }
+
+    /// 上次 [`IObjStat::dismiss`] 之后发生变化的类型与 tag
+    pub fn changes(&self) -> &ObjChanges<T> {
+        &self.changes
+    }
+
+    /// `ty` 类对象的 tagged 与 untagged 数量
+    pub fn amounts_of(&self, ty: &TypeId) -> Option<Amounts<U>> {
+        self.amount.get(ty).copied()
+    }
+
+    /// 按现有的 tagged 对象重新计算 tagged 数量  
+    /// 通过 [`ITaggedStore::get_mut`] 或 [`ITaggedStore::iter_mut`] 修改了对象的数量后需要调用
+    pub fn recount_tagged(&mut self) {
+        for a in self.amount.vals_mut() {
+            a.tagged = U::zero();
+        }
+        for o in self.instances.values() {
+            let tid = o.obj_type().tid;
+            if let Some(a) = self.amount.get_mut(&tid) {
+                a.tagged += o.obj_amount();
+            } else {
+                self.amount.insert(tid, Amounts { tagged: o.obj_amount(), untagged: U::zero() });
+            }
+        }
+        self.changes.record_all();
+    }
+
+    fn index(&mut self, ty: TypeId, t: T) {
+        self.by_type.entry(ty).or_insert_with(TagIndex::new).insert(t);
+    }
+
+    fn unindex(&mut self, ty: &TypeId, t: &T) {
+        if let Some(idx) = self.by_type.get_mut(ty) {
+            idx.remove(t);
+            if idx.tags.is_empty() {
+                self.by_type.remove(ty);
+            }
+        }
+    }
+
+    /// 移除对象后更新索引与 tagged 数量  
+    /// tagged 数量少于对象的数量时不修改数量并返回错误，通常是修改对象数量后没有调用 [`BasicObjStore::recount_tagged`]
+    fn forget(&mut self, t: &T, o: &PObj<T, U>) -> Result<(), AmountError<TypeId, U>> {
+        let tid = o.obj_type().tid;
+        self.changes.record_tag(t.clone(), tid);
+        self.unindex(&tid, t);
+        let Some(am) = self.amount.get_mut(&tid) else { return Ok(()); };
+        if am.tagged < o.obj_amount() {
+            return Err(AmountError::Underflow { ty: tid, present: am.tagged, requested: o.obj_amount() });
+        }
+        am.tagged -= o.obj_amount();
+        Ok(())
+    }
+
+    /// 加入对象前检查该类型的总数量，溢出时不修改存储并交回新对象与错误  
+    /// 成功时返回被替换的旧对象
+    fn insert_checked(&mut self, t: T, v: PObj<T, U>) -> Result<Option<PObj<T, U>>, Rejected<T, U>> {
+        let (tid, added) = (v.obj_type().tid, v.obj_amount());
+        let am = self.amount.get(&tid).copied().unwrap_or_default();
+        let replaced = self.instances.get(&t)
+            .filter(|o| o.obj_type().tid == tid)
+            .map_or(U::zero(), |o| o.obj_amount());
+        let present = match am.total() {
+            Some(total) if replaced <= total => total - replaced,
+            _ => return Err((v, AmountError::Overflow { ty: tid, present: am.tagged, added: am.untagged }))
+        };
+        if checked_add(present, added).is_none() {
+            return Err((v, AmountError::Overflow { ty: tid, present, added }));
+        }
+        self.changes.record_tag(t.clone(), tid);
+        let old = self.instances.insert(t.clone(), v);
+        if let Some(o) = old.as_ref() {
+            if let Err(e) = self.forget(&t, o) {
+                e.log();
+            }
+        }
+        self.index(tid, t);
+        if let Some(am) = self.amount.get_mut(&tid) {
+            am.tagged += added;
+        } else {
+            self.amount.insert(tid, Amounts { tagged: added, untagged: U::zero() });
+        }
+        Ok(old)
+    }
+}
+
+impl<T, U> Default for BasicObjStore<T, U>
+where T: Clone + Hash + Eq, U: Scalar {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<T, U> ITaggedStore<T, PObj<T, U>> for BasicObjStore<T, U>
@@ -40,42 +237,49 @@ where T: Clone + Hash + Eq, U: Scalar {
     }
 
     fn get_mut(&mut self, t: &T) -> Option<&mut PObj<T, U>> {
-        self.modified = true;
-        self.instances.get_mut(t)
+        let o = self.instances.get_mut(t)?;
+        self.changes.record_tag(t.clone(), o.obj_type().tid);
+        Some(o)
     }
 
     fn remove(&mut self, t: &T) -> Option<PObj<T, U>> {
         if let Some(tag_o) = self.instances.remove(t) {
-            self.modified = true;
-            if let Some(am) = self.amount.get_mut(&tag_o.obj_type().tid) {
-                am.0 -= tag_o.obj_amount();
+            if let Err(e) = self.forget(t, &tag_o) {
+                e.log();
             }
             return Some(tag_o);
         }
         None
     }
 
+    /// 该类型的总数量会溢出时不加入新对象，记录 [`AmountError::Overflow`] 并返回新对象
     fn add_or_update(&mut self, t: T, v: PObj<T, U>) -> Option<PObj<T, U>> {
-        self.modified = true;
-        if let Some(am) = self.amount.get_mut(&v.obj_type().tid) {
-            am.0 += v.obj_amount();
-        } else {
-            self.amount.insert(v.obj_type().tid, (v.obj_amount(), U::zero()));
-        }
-        if let Some(old) = self.instances.insert(t, v) {
-            if let Some(am) = self.amount.get_mut(&old.obj_type().tid) {
-                am.0 -= old.obj_amount();
+        match self.insert_checked(t, v) {
+            Ok(old) => old,
+            Err((v, e)) => {
+                e.log();
+                Some(v)
             }
-            return Some(old);
         }
-        None
     }
     
+    /// tag 已存在或该类型的总数量会溢出时拒绝新对象
+    fn try_add(&mut self, t: T, v: PObj<T, U>) -> Result<(), PObj<T, U>> {
+        if self.instances.contains_key(&t) {
+            return Err(v);
+        }
+        self.insert_checked(t, v).map(|_| ()).map_err(|(v, e)| {
+            e.log();
+            v
+        })
+    }
+
     fn iter<'a>(&'a self) -> impl Iterator<Item = &'a PObj<T, U>> where PObj<T, U>: 'a {
         self.instances.values()
     }
     
     fn iter_mut<'a>(&'a mut self) -> impl Iterator<Item = &'a mut PObj<T, U>> where PObj<T, U>: 'a {
+        self.changes.record_all();
         self.instances.values_mut()
     }
     
@@ -90,26 +294,13 @@ where T: Clone + Hash + Eq, U: Scalar {
 
     fn remove_batch(&mut self, ts: &[T]) -> Vec<Option<PObj<T, U>>> {
         ts.iter()
-        .map(|t| self.instances.remove(t))
-        .map(|o| {
-            if let Some(ref po) = o {
-                if let Some(am) = self.amount.get_mut(&po.obj_type().tid) {
-                    am.0 -= po.obj_amount();
-                }
-            }
-            o
-        })
+        .map(|t| self.remove(t))
         .collect()
     }
 
     fn remove_batch_skip(&mut self, ts: &[T]) -> Vec<PObj<T, U>> {
         ts.iter()
-        .filter_map(|t| self.instances.remove(t))
-        .inspect(|o| {
-            if let Some(am) = self.amount.get_mut(&o.obj_type().tid) {
-                am.0 -= o.obj_amount();
-            }
-        })
+        .filter_map(|t| self.remove(t))
         .collect()
     }
     
@@ -121,6 +312,9 @@ where T: Clone + Hash + Eq, U: Scalar {
         self.instances.is_empty()
     }
 
+    fn tags_of(&self, ty: &TypeId) -> Option<&[T]> {
+        Some(self.by_type.get(ty).map_or(&[], |idx| &idx.tags))
+    }
 }
 
 impl<T, U> IObjStat<U> for BasicObjStore<T, U>
@@ -138,34 +332,55 @@ where T: Clone + Hash + Eq, U: Scalar {
         self.amount.index_of(&ty.tid)
     }
 
-    fn amounts(&self) -> impl Iterator<Item = &U> {
-        self.amount.vals().map(|v| &v.0)
+    fn amounts(&self) -> impl Iterator<Item = U> {
+        self.amount.vals().filter_map(Amounts::total)
     }
+
     fn amount_of(&self, ty: &crate::core::ObjType) -> Option<U> {
-        self.amount.get(&ty.tid).cloned().map(|v| v.0)
+        self.amount.get(&ty.tid).and_then(Amounts::total)
+    }
+
+    fn amount_of_many(&self, tys: &[crate::core::ObjType]) -> Vec<U> {
+        tys.iter().filter_map(|ty| self.amount.get(&ty.tid).and_then(Amounts::total)).collect()
     }
 
-    fn amount_of_many(&self, tys: &[crate::core::ObjType]) -> Vec<&U> {
-        tys.iter().filter_map(|ty| self.amount.get(&ty.tid).map(|v| &v.0)).collect()
+    fn amounts_t(&self) -> impl Iterator<Item = U> {
+        self.amount.vals().map(|v| v.tagged)
     }
 
-    fn amounts_u(&self) -> impl Iterator<Item = &U> {
-        self.amount.vals().map(|v| &v.1)
+    fn amount_of_t(&self, ty: &crate::core::ObjType) -> Option<U> {
+        self.amount.get(&ty.tid).map(|v| v.tagged)
+    }
+
+    fn amount_of_many_t(&self, tys: &[crate::core::ObjType]) -> Vec<U> {
+        tys.iter().filter_map(|ty| self.amount.get(&ty.tid).map(|v| v.tagged)).collect()
+    }
+
+    fn amounts_u(&self) -> impl Iterator<Item = U> {
+        self.amount.vals().map(|v| v.untagged)
     }
     
     fn amount_of_u(&self, ty: &crate::core::ObjType) -> Option<U> {
-        self.amount.get(&ty.tid).cloned().map(|v| v.1)
+        self.amount.get(&ty.tid).map(|v| v.untagged)
     }
     
-    fn amount_of_many_u(&self, tys: &[crate::core::ObjType]) -> Vec<&U> {
-        tys.iter().filter_map(|ty| self.amount.get(&ty.tid).map(|v| &v.1)).collect()
+    fn amount_of_many_u(&self, tys: &[crate::core::ObjType]) -> Vec<U> {
+        tys.iter().filter_map(|ty| self.amount.get(&ty.tid).map(|v| v.untagged)).collect()
+    }
+
+    fn modified(&self) -> bool {
+        !self.changes.is_empty()
+    }
+
+    fn dismiss(&mut self) {
+        self.changes.clear();
     }
 }
 
-impl<T, U> IUntaggedStore<TypeId, U> for  BasicObjStore<T, U> // todo: amount分开tagged 和untagged
+impl<T, U> IUntaggedStore<TypeId, U> for BasicObjStore<T, U> // todo: amount分开tagged 和untagged -ok
 where T: Clone + Hash + Eq, U: Scalar {
     fn contains_u(&self, ty: &TypeId) -> bool {
-        self.amount.containes(ty)
+        self.amount.get(ty).is_some_and(|a| a.untagged > U::zero())
     }
 
     fn len_u(&self) -> usize {
@@ -177,53 +392,55 @@ where T: Clone + Hash + Eq, U: Scalar {
     }
 
     fn iter_u<'a>(&'a self) -> impl Iterator<Item = &'a U> where U: 'a {
-        self.amount.vals().map(|v| &v.1)
+        self.amount.vals().map(|v| &v.untagged)
     }
 
     fn iter_mut_u<'a>(&'a mut self) -> impl Iterator<Item = &'a mut U> where U: 'a {
-        self.amount.vals_mut().map(|v| &mut v.1)
+        self.changes.record_all();
+        self.amount.vals_mut().map(|v| &mut v.untagged)
     }
 
     fn get_u(&self, ty: &TypeId) -> Option<U> {
-        self.amount.get(ty).cloned().map(|v| v.1)
+        self.amount.get(ty).map(|v| v.untagged)
     }
 
-    fn increase(&mut self, ty: &TypeId, amount: U) -> bool {
+    fn increase(&mut self, ty: &TypeId, amount: U) -> Result<U, AmountError<TypeId, U>> {
+        let present = self.amount.get(ty).copied().unwrap_or_default();
+        let total = present.total().ok_or(AmountError::Overflow { ty: *ty, present: present.tagged, added: present.untagged })?;
+        if checked_add(total, amount).is_none() {
+            return Err(AmountError::Overflow { ty: *ty, present: total, added: amount });
+        }
+        self.changes.record_type(*ty);
         if let Some(a) = self.amount.get_mut(ty) {
-            a.1 += amount;
-            a.0 += amount;
-            true
+            a.untagged += amount;
+            Ok(a.untagged)
         } else {
-            self.amount.insert(*ty, (amount, amount));
-            false
+            self.amount.insert(*ty, Amounts { tagged: U::zero(), untagged: amount });
+            Ok(amount)
         }
     }
 
-    fn decrease(&mut self, ty: &TypeId, amount: U) -> bool {
-        if let Some(a) = self.amount.get_mut(ty) {
-            a.1 -= amount;
-            a.0 -= amount;
-            true
-        } else {
-            false
+    fn decrease(&mut self, ty: &TypeId, amount: U) -> Result<U, AmountError<TypeId, U>> {
+        let Some(a) = self.amount.get_mut(ty) else {
+            return Err(AmountError::Missing(*ty));
+        };
+        if a.untagged < amount {
+            return Err(AmountError::Underflow { ty: *ty, present: a.untagged, requested: amount });
         }
+        a.untagged -= amount;
+        let left = a.untagged;
+        self.changes.record_type(*ty);
+        Ok(left)
     }
 
     fn remove_u(&mut self, ty: &TypeId)-> Option<U> {
-        if self.amount.containes(ty) {
-            let a = self.amount.get(ty).cloned().unwrap();
-            if a.0 != a.1 {
-                if let Some(u) = self.amount.get_mut(ty) {
-                    u.0 -= u.1;
-                    u.1 = U::zero();
-                }
-                None
-            } else {
-                self.amount.remove(ty).map(|u| u.0)
-            }
-        } else {
-            None
+        let a = self.amount.get_mut(ty)?;
+        let removed = a.untagged;
+        a.untagged = U::zero();
+        if !self.by_type.contains_key(ty) {
+            self.amount.remove(ty);
         }
-       
+        self.changes.record_type(*ty);
+        Some(removed)
     }
-}
\ No newline at end of file
+}
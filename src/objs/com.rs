@@ -4,10 +4,16 @@ use std::{fmt::Debug, hash::Hash, sync:: Mutex};
 use crossbeam_channel::{unbounded, Receiver, Sender};
 use krnl::scalar::Scalar;
 use meme_derive::IObj;
+use ahash::RandomState;
 use crate::{self as meme, core::{PObj, TypeGroup}, errors::MemError};
 
 pub type ObjChannel<T, U = u32, OT = T, OU = U> = Channel<T, U, PObj<OT, OU>>;
 
+/// 由一对通道的 tag 计算连接标识，使用固定种子，同样的 tag 在每次运行中得到相同的标识
+fn pair_link<T: Hash>(a: &T, b: &T) -> usize {
+    RandomState::with_seeds(0x6368_616e, 0x6e65_6c5f, 0x6c69_6e6b, 0x7061_6972).hash_one((a, b)) as usize
+}
+
 #[derive(IObj, Debug)]
 #[obj_type(TypeGroup::Com)]
 pub struct Channel<T, U = u32, DT = T> 
@@ -21,6 +27,7 @@ DT: Debug + 'static {
     #[amount]
     amount: U,
 
+    link: usize,
     r: Receiver<DT>,
     s: Sender<DT>,
 }
@@ -33,16 +40,19 @@ DT: Debug + 'static {
     pub fn new_pair(tag_a: T, tag_b: T) -> (Self, Self) {
         let (s1, r1) = unbounded();
         let (s2, r2) = unbounded();
+        let link = pair_link(&tag_a, &tag_b);
         (
             Self {
                 tag: tag_a,
                 amount: U::one(),
+                link,
                 r: r1,
                 s: s2,
             },
             Self {
                 tag: tag_b,
                 amount: U::one(),
+                link,
                 r: r2,
                 s: s1,
             }
@@ -51,22 +61,30 @@ DT: Debug + 'static {
 
     pub fn new_sr_pair(tag_s: T, tag_r: T) -> (SChannel<T, U, DT>, RChannel<T, U, DT>) {
         let (s, r) = unbounded();
+        let link = pair_link(&tag_s, &tag_r);
         (
             SChannel {
                 tag: tag_s,
                 amount: U::one(),
+                link,
                 s
             },
             RChannel {
                 tag: tag_r,
                 amount: U::one(),
+                link,
                 r
             }
         )
     }
 
     pub fn new_clone(&self, tag: T) -> Self {
-        Self { tag, amount: U::one(), r: self.r.clone(), s: self.s.clone() }
+        Self { tag, amount: U::one(), link: self.link, r: self.r.clone(), s: self.s.clone() }
+    }
+
+    /// 连接标识，同一对（及其克隆）通道的连接标识相同，由创建时两端的 tag 决定
+    pub fn link(&self) -> usize {
+        self.link
     }
 
     pub fn send(&self, d: DT) -> Result<(), MemError<DT>> {
@@ -97,6 +115,7 @@ DT: Debug + 'static {
     #[amount]
     amount: U,
 
+    link: usize,
     s: Sender<DT>,
 }
 
@@ -108,6 +127,10 @@ DT: Debug + 'static {
     pub fn send(&self, d: DT) -> Result<(),  MemError<DT>> {
         Ok(self.s.send(d)?)
     }
+
+    pub fn link(&self) -> usize {
+        self.link
+    }
 }
 
 #[derive(IObj, Debug)]
@@ -122,6 +145,7 @@ DT: Debug + 'static {
     #[amount]
     amount: U,
 
+    link: usize,
     r: Receiver<DT>,
 }
 
@@ -133,6 +157,10 @@ DT: Debug + 'static {
     pub fn receive(&self) -> Result<DT,  MemError<DT>>  {
         Ok(self.r.recv()?)
     }
+
+    pub fn link(&self) -> usize {
+        self.link
+    }
 }
 
 #[derive(Debug)]
@@ -0,0 +1,35 @@
+// Copyright 2024 Junshuang Hu
+use crate::{self as meme, core::{ObjType, DEFAULT_GROUP}};
+use meme_derive::IObj;
+use once_cell::sync::Lazy;
+
+/// 符号对象池的容量
+pub const SYMBOL_CAPACITY: usize = 256;
+
+/// 运行时才知道名字的 untagged 对象类型（例如从 P-Lingua 文件中导入的符号）
+/// 对象类型以 [`std::any::TypeId`] 区分，无法在运行时创建新类型，
+/// 因此预先实例化 [`SYMBOL_CAPACITY`] 个互不相同的类型供按需分配，它们只用于 untagged 计数，不会被构造
+/// 池是只读的，每个导入器各自从第一个类型开始分配，需要更多类型时见 [`crate::formats::pli::PliImporter::symbol_pool`]
+#[derive(IObj, Debug)]
+pub struct Symbol<const H: u8, const L: u8> {
+    #[tag]
+    tag: u32
+}
+
+macro_rules! symbol_table {
+    ($($h:literal)*) => {{
+        let mut v = Vec::with_capacity(SYMBOL_CAPACITY);
+        $( symbol_table!(@row v, $h, 0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15); )*
+        v
+    }};
+    (@row $v:ident, $h:literal, $($l:literal)*) => {
+        $( $v.push(ObjType::new::<Symbol<$h, $l>>(&DEFAULT_GROUP)); )*
+    };
+}
+
+static SYMBOLS: Lazy<Vec<ObjType>> = Lazy::new(|| symbol_table!(0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15));
+
+/// 第 `i` 个符号类型，超出容量返回 `None`
+pub fn symbol_type(i: usize) -> Option<ObjType> {
+    SYMBOLS.get(i).cloned()
+}
@@ -0,0 +1,67 @@
+// Copyright 2024 Junshuang Hu
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use rand::Rng;
+use uuid::{Builder, Uuid};
+
+/// 全局唯一的 tag，用于在独立启动的多个模拟进程之间交换对象而不需要重新分配 tag
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct UuidTag(Uuid);
+
+impl UuidTag {
+    /// 随机生成（v4）
+    pub fn new() -> Self {
+        Self(Uuid::new_v4())
+    }
+
+    /// 由给定的随机数生成器生成（v4），使用带种子的生成器时结果可以复现
+    pub fn from_rng<R: Rng + ?Sized>(rng: &mut R) -> Self {
+        Self(Builder::from_random_bytes(rng.gen()).into_uuid())
+    }
+
+    pub fn nil() -> Self {
+        Self(Uuid::nil())
+    }
+
+    pub fn as_uuid(&self) -> &Uuid {
+        &self.0
+    }
+
+    pub fn as_u128(&self) -> u128 {
+        self.0.as_u128()
+    }
+}
+
+/// 默认为 nil，随机生成使用 [`UuidTag::new`] 或 [`UuidTag::from_rng`]
+impl Default for UuidTag {
+    fn default() -> Self {
+        Self::nil()
+    }
+}
+
+impl From<Uuid> for UuidTag {
+    fn from(u: Uuid) -> Self {
+        Self(u)
+    }
+}
+
+impl From<UuidTag> for Uuid {
+    fn from(t: UuidTag) -> Self {
+        t.0
+    }
+}
+
+impl Display for UuidTag {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl FromStr for UuidTag {
+    type Err = uuid::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Uuid::parse_str(s).map(Self)
+    }
+}
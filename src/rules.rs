@@ -1,12 +1,16 @@
 // Copyright 2024 Junshuang Hu
 use std::{any::TypeId, hash::Hash};
 
-use ahash::AHashMap;
+use ahash::{AHashMap, AHashSet};
 use krnl::scalar::Scalar;
+use rand::Rng;
 
-use crate::core::{ICondition, IRuleEffect, IRuleStat, ITaggedStore, IndexMap, OperationEffect, PRule, TaggedPresences, UntaggedPresences};
+use crate::rules::conflict::ConflictGraph;
+use crate::core::{EffectSignature, ExecutableRules, ICondition, IObjStat, IRuleEffect, IRuleStat, ITaggedStore, IUntaggedStore, IndexMap, ObjChanges, OperationEffect, PObj, PRule, TaggedPresenceInfo, TaggedPresences, UntaggedPresences};
 
 pub mod com;
+pub mod conflict;
+pub mod multiset;
 
 // pub struct BasicEffect<T, U>
 // where T: Clone + Hash + Eq, U: Scalar {
@@ -18,12 +22,14 @@ pub mod com;
 pub struct BasicEffect<T = u32, U = u32>
 where T: Send + Sync, U: Send + Sync {
     effects: Option<Vec<OperationEffect<T, U>>>,
+    signatures: Vec<EffectSignature>
 }
 
 impl<T, U> BasicEffect<T, U>
 where T: Send + Sync, U: Send + Sync {
     pub fn new(ops: Option<Vec<OperationEffect<T, U>>>) -> Self {
-        Self { effects: ops }
+        let signatures = vec![EffectSignature::default(); ops.as_ref().map_or(0, |o| o.len())];
+        Self { effects: ops, signatures }
     }
 }
 
@@ -35,8 +41,12 @@ where T: Send + Sync + Clone, U: Send + Sync + Clone {
         &self.effects
     }
     
-    fn from_builder(effs: Option<Vec<Self::Effect>>) -> Self {
-        Self { effects: effs }
+    fn from_builder(effs: Option<Vec<Self::Effect>>, sigs: Vec<EffectSignature>) -> Self {
+        Self { effects: effs, signatures: sigs }
+    }
+
+    fn signatures(&self) -> &[EffectSignature] {
+        &self.signatures
     }
 }
 
@@ -44,14 +54,14 @@ where T: Send + Sync + Clone, U: Send + Sync + Clone {
 pub struct BasicCondition<T = u32, U = u32>
 where T: Clone + Hash + Eq, U: Scalar {
     untagged_cond: Option<UntaggedPresences<U>>,
-    tagged_cond: Option<TaggedPresences<T>>,
+    tagged_cond: Option<TaggedPresences<T, U>>,
     skip_take: bool
 }
 
 impl<T, U> ICondition<T, U> for BasicCondition<T, U>
 where T: Clone + Hash + Eq, U: Scalar {
         
-    fn from_builder(uts: Option<UntaggedPresences<U>>, tgs: Option<TaggedPresences<T>>, skip_take: bool) -> Self {
+    fn from_builder(uts: Option<UntaggedPresences<U>>, tgs: Option<TaggedPresences<T, U>>, skip_take: bool) -> Self {
         Self {
             untagged_cond: uts,
             tagged_cond: tgs,
@@ -63,7 +73,7 @@ where T: Clone + Hash + Eq, U: Scalar {
         &self.untagged_cond
     }
     
-    fn tagged(&self) -> &Option<TaggedPresences<T>> {
+    fn tagged(&self) -> &Option<TaggedPresences<T, U>> {
         &self.tagged_cond
     }
     
@@ -84,7 +94,14 @@ E: IRuleEffect,
 C: ICondition<OT, OU> {
     inner: IndexMap<T, PRule<T, OT, U, OU, E, C>>,
     stat: Vec<C>,
-    amount: AHashMap<TypeId, OU>
+    amount: AHashMap<TypeId, OU>,
+    conflicts: ConflictGraph<T, OT>,
+    /// 与 `stat` 按位置对应，上次增量检查时条件不满足的规则
+    unsat: Vec<bool>,
+    /// 与 `stat` 按位置对应，本次检查中跳过的规则，只在增量检查中非空
+    skip: Vec<bool>,
+    /// 规则之间的优先关系，键优先于值中的规则
+    outranks: AHashMap<T, AHashSet<T>>
 }
 
 impl<T, OT, U, OU, E, C> BasicRuleStore<T, OT, U, OU, E, C>
@@ -99,13 +116,85 @@ C: ICondition<OT, OU> {
         Self {
             inner: IndexMap::new(),
             stat: Vec::new(),
-            amount: AHashMap::new()
+            amount: AHashMap::new(),
+            conflicts: ConflictGraph::new(),
+            unsat: Vec::new(),
+            skip: Vec::new(),
+            outranks: AHashMap::new()
         }
     }
 
+    /// 声明规则 `higher` 优先于规则 `lower`（强优先）：`higher` 可执行时 `lower` 在该步中不会执行  
+    /// 关系不会自动传递，移除规则时同时移除与它有关的关系
+    pub fn add_priority(&mut self, higher: T, lower: T) {
+        self.outranks.entry(higher).or_default().insert(lower);
+    }
+
+    /// 第 `i` 条规则是否优先于第 `j` 条规则，见 [`BasicRuleStore::add_priority`]
+    pub fn outranks(&self, i: usize, j: usize) -> bool {
+        let (Some(hi), Some(lo)) = (self.inner.get_key(i), self.inner.get_key(j)) else { return false; };
+        self.outranks.get(hi).is_some_and(|s| s.contains(lo))
+    }
+
+    pub fn has_priorities(&self) -> bool {
+        !self.outranks.is_empty()
+    }
+
     pub fn rules(&self) -> impl Iterator<Item = &PRule<T, OT, U, OU, E, C>> {
         self.inner.vals()
     }
+
+    /// 规则冲突图，随规则的加入和移除增量更新
+    pub fn conflict_graph(&self) -> &ConflictGraph<T, OT> {
+        &self.conflicts
+    }
+
+    /// 增量检查：上次增量检查时条件不满足、且条件依赖的类型和 tag 都不在 `changes` 中的规则不再检查  
+    /// 条件只要求对象存在，对象增加才可能使不满足的条件变为满足，因此结果与完整检查相同，
+    /// 只是被跳过的规则不再消耗随机数  
+    /// `changes` 应包含上次增量检查之后的所有变化，`simple` 为真时使用 [`IRuleStat::check_on_simple_with`]
+    pub fn check_on_changes_with<OS, R>(&mut self, os: &OS, changes: &ObjChanges<OT>, simple: bool, rng: &mut R) -> ExecutableRules<OT>
+    where OS: ITaggedStore<OT, PObj<OT, OU>> + IUntaggedStore<TypeId, OU> + IObjStat<OU>, R: Rng + ?Sized {
+        self.skip = self.stat.iter()
+            .zip(self.unsat.iter())
+            .map(|(c, unsat)| *unsat && !affected_by(c, changes))
+            .collect();
+        let res = if simple { self.check_on_simple_with(os, rng) } else { self.check_on_with(os, rng) };
+        self.skip.clear();
+        let mut satisfied = vec![false; self.stat.len()];
+        for e in res.parallel_executable.iter().chain(res.conflict_executable.iter()).flatten() {
+            satisfied[e.rule_index] = true;
+        }
+        // 按概率没有抽中的规则不能确定条件是否满足，不缓存
+        self.unsat = satisfied.into_iter()
+            .enumerate()
+            .map(|(i, sat)| !sat && self.probability_at(i).is_some_and(|p| p >= 1.0))
+            .collect();
+        res
+    }
+
+    /// 丢弃增量检查的缓存，对象的变化没有交给 [`BasicRuleStore::check_on_changes_with`] 就被确认时需要调用
+    pub fn invalidate_checks(&mut self) {
+        self.unsat.iter_mut().for_each(|u| *u = false);
+    }
+
+    /// 上次增量检查时缓存为不满足的规则数，这些规则在依赖的对象变化之前不会再被检查
+    pub fn cached_unsatisfied(&self) -> usize {
+        self.unsat.iter().filter(|u| **u).count()
+    }
+}
+
+/// 条件是否依赖 `changes` 中的类型或 tag
+fn affected_by<OT, OU, C>(c: &C, changes: &ObjChanges<OT>) -> bool
+where OT: Eq + Hash + Clone, OU: Scalar, C: ICondition<OT, OU> {
+    if changes.is_all() {
+        return true;
+    }
+    c.untagged().iter().flatten().any(|u| changes.touches_type(&u.ty.tid)) ||
+    c.tagged().iter().flatten().any(|t| match &t.info {
+        TaggedPresenceInfo::OfTag(tg) => changes.touches_tag(tg),
+        TaggedPresenceInfo::RandTags((ty, _)) => changes.touches_type(&ty.tid),
+    })
 }
 
 impl<T, OT, U, OU, E, C> ITaggedStore<T, PRule<T, OT, U, OU, E, C>> for BasicRuleStore<T, OT, U, OU, E, C>
@@ -130,27 +219,29 @@ C: ICondition<OT, OU> {
     }
 
     fn remove(&mut self, t: &T) -> Option<PRule<T, OT, U, OU, E, C>> {
-        let old_ind = self.pos_of(t);
-        if let Some(old) = self.inner.remove(t) {
-            let old_c = self.stat.remove(old_ind.unwrap());
-            if let Some(o_req) = old_c.untagged() {
-                for o in o_req {
-                    let a = self.amount.get_mut(&o.ty.tid).unwrap();
-                    if *a > o.amount {
-                        *a -= o.amount;
-                    } else {
-                        self.amount.remove(&o.ty.tid);
-                    }
+        self.conflicts.remove(t);
+        self.outranks.remove(t);
+        self.outranks.values_mut().for_each(|s| { s.remove(t); });
+        self.outranks.retain(|_, s| !s.is_empty());
+        let (pos, old) = self.inner.remove_full(t)?;
+        let old_c = self.stat.swap_remove(pos);
+        self.unsat.swap_remove(pos);
+        if let Some(o_req) = old_c.untagged() {
+            for o in o_req {
+                let a = self.amount.get_mut(&o.ty.tid).unwrap();
+                if *a > o.amount {
+                    *a -= o.amount;
+                } else {
+                    self.amount.remove(&o.ty.tid);
                 }
             }
-            Some(old)
-        } else {
-            None
         }
+        Some(old)
     }
 
     fn add_or_update(&mut self, t: T, v: PRule<T, OT, U, OU, E, C>) -> Option<PRule<T, OT, U, OU, E, C>> {
         let cond = v.condition().clone();
+        self.conflicts.insert(t.clone(), &cond);
         if let Some(o_req) = cond.untagged() {
             for o in o_req {
                 if let Some(a) = self.amount.get_mut(&o.ty.tid) {
@@ -173,9 +264,11 @@ C: ICondition<OT, OU> {
                 }
             }
             self.stat[ind] = cond;
+            self.unsat[ind] = false;
             Some(old)
         } else {
             self.stat.push(cond);
+            self.unsat.push(false);
             None
         }
     }
@@ -197,17 +290,11 @@ C: ICondition<OT, OU> {
     }
     
     fn remove_batch(&mut self, ts: &[T]) -> Vec<Option<PRule<T, OT, U, OU, E, C>>> {
-        let mut res_kv=  self.inner.remove_batch(ts);
-        res_kv.iter_mut()
-        .map(|kv| kv.take().map(|(_,v)|v))
-        .collect::<Vec<_>>()
+        ts.iter().map(|t| self.remove(t)).collect()
     }
     
     fn remove_batch_skip(&mut self, ts: &[T]) ->  Vec<PRule<T, OT, U, OU, E, C>>  {
-        let mut res_kv=  self.inner.remove_batch(ts);
-        res_kv.iter_mut()
-        .filter_map(|kv| kv.take().map(|(_,v)|v))
-        .collect::<Vec<_>>()
+        ts.iter().filter_map(|t| self.remove(t)).collect()
     }
     
     fn len(&self) -> usize {
@@ -250,6 +337,22 @@ C: ICondition<OT, OU> {
     fn condition_at(&self, ind: usize) -> Option<&C> {
         self.inner.at(ind).map(|r| r.condition())
     }
+
+    fn priority_at(&self, ind: usize) -> Option<i32> {
+        self.inner.at(ind).map(|r| r.priority())
+    }
+
+    fn rate_at(&self, ind: usize) -> Option<f64> {
+        self.inner.at(ind).and_then(|r| r.rate())
+    }
+
+    fn duration_at(&self, ind: usize) -> Option<f64> {
+        self.inner.at(ind).map(|r| r.duration())
+    }
+
+    fn probability_at(&self, ind: usize) -> Option<f64> {
+        self.inner.at(ind).map(|r| r.probability())
+    }
     
     fn conditions<'a>(&'a self) -> impl Iterator<Item = &'a C> where C: 'a {
         self.stat.iter()
@@ -258,4 +361,8 @@ C: ICondition<OT, OU> {
     fn conditions_count(&self) -> usize {
         self.len()
     }
+
+    fn skip_at(&self, pos: usize) -> bool {
+        self.skip.get(pos).copied().unwrap_or(false)
+    }
 }
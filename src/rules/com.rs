@@ -78,6 +78,7 @@ OU: Scalar
                     })
                 }).collect::<Vec<_>>()
             })
+            .produces_any()
             .build(),
         }
     }
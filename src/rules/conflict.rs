@@ -0,0 +1,154 @@
+// Copyright 2024 Junshuang Hu
+use std::{any::TypeId, collections::VecDeque, hash::Hash};
+
+use ahash::{AHashMap, AHashSet};
+use krnl::scalar::Scalar;
+
+use crate::core::{ICondition, TaggedPresenceInfo};
+
+/// 规则竞争的资源
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ConflictOn<OT> {
+    /// 指定 tag 的对象
+    Tag(OT),
+    /// 某类 untagged 对象
+    Untagged(TypeId),
+    /// 从某类 tagged 对象中随机选择
+    RandTagged(TypeId)
+}
+
+/// 静态规则冲突图
+/// 两条规则的条件需要同一资源（[`ConflictOn`]）时相连，图只依赖规则的条件，不依赖膜内对象
+/// 指定 tag 的对象与随机选择的同类对象之间的竞争无法静态得知，不会相连
+#[derive(Debug)]
+pub struct ConflictGraph<T, OT>
+where T: Hash + Eq + Clone, OT: Hash + Eq + Clone {
+    users: AHashMap<ConflictOn<OT>, Vec<T>>,
+    res_of: AHashMap<T, Vec<ConflictOn<OT>>>
+}
+
+impl<T, OT> Default for ConflictGraph<T, OT>
+where T: Hash + Eq + Clone, OT: Hash + Eq + Clone {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, OT> ConflictGraph<T, OT>
+where T: Hash + Eq + Clone, OT: Hash + Eq + Clone {
+    pub fn new() -> Self {
+        Self { users: AHashMap::new(), res_of: AHashMap::new() }
+    }
+
+    /// 加入规则 `t`，如果已存在则先移除旧的条件
+    pub fn insert<U: Scalar, C: ICondition<OT, U>>(&mut self, t: T, c: &C) {
+        self.remove(&t);
+        let mut res = Vec::new();
+        for u in c.untagged().iter().flatten() {
+            res.push(ConflictOn::Untagged(u.ty.tid));
+        }
+        for p in c.tagged().iter().flatten() {
+            res.push(match &p.info {
+                TaggedPresenceInfo::OfTag(tg) => ConflictOn::Tag(tg.clone()),
+                TaggedPresenceInfo::RandTags((ty, _)) => ConflictOn::RandTagged(ty.tid),
+            });
+        }
+        let mut seen = AHashSet::new();
+        res.retain(|r| seen.insert(r.clone()));
+        for r in res.iter() {
+            self.users.entry(r.clone()).or_default().push(t.clone());
+        }
+        self.res_of.insert(t, res);
+    }
+
+    pub fn remove(&mut self, t: &T) {
+        if let Some(res) = self.res_of.remove(t) {
+            for r in res {
+                if let Some(us) = self.users.get_mut(&r) {
+                    us.retain(|u| u != t);
+                    if us.is_empty() {
+                        self.users.remove(&r);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.res_of.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.res_of.is_empty()
+    }
+
+    /// 规则 `t` 的条件需要的资源
+    pub fn resources_of(&self, t: &T) -> Option<&[ConflictOn<OT>]> {
+        self.res_of.get(t).map(|v| v.as_slice())
+    }
+
+    /// 条件需要资源 `r` 的规则
+    pub fn users_of(&self, r: &ConflictOn<OT>) -> &[T] {
+        self.users.get(r).map_or(&[], |v| v.as_slice())
+    }
+
+    /// 与规则 `t` 竞争的规则（不包括 `t`）
+    pub fn neighbors(&self, t: &T) -> AHashSet<T> {
+        self.res_of.get(t).into_iter()
+            .flatten()
+            .flat_map(|r| self.users_of(r).iter())
+            .filter(|u| *u != t)
+            .cloned()
+            .collect()
+    }
+
+    pub fn conflicts(&self, a: &T, b: &T) -> bool {
+        a != b && self.res_of.get(a).is_some_and(|res| {
+            res.iter().any(|r| self.users_of(r).contains(b))
+        })
+    }
+
+    /// 没有竞争者的规则，它们总是可以与其他规则并行执行
+    pub fn isolated(&self) -> impl Iterator<Item = &T> {
+        self.res_of.keys().filter(|t| self.neighbors(t).is_empty())
+    }
+
+    /// 所有的边，每条边只出现一次
+    pub fn edges(&self) -> Vec<(T, T)> {
+        let mut done: AHashSet<&T> = AHashSet::new();
+        let mut res = Vec::new();
+        for t in self.res_of.keys() {
+            for n in self.neighbors(t) {
+                if !done.contains(&n) {
+                    res.push((t.clone(), n));
+                }
+            }
+            done.insert(t);
+        }
+        res
+    }
+
+    /// 将规则划分为互不竞争的组（连通分量）
+    pub fn components(&self) -> Vec<Vec<T>> {
+        let mut visited: AHashSet<T> = AHashSet::new();
+        let mut res = Vec::new();
+        for t in self.res_of.keys() {
+            if visited.contains(t) {
+                continue;
+            }
+            visited.insert(t.clone());
+            let mut group = Vec::new();
+            let mut queue = VecDeque::from([t.clone()]);
+            while let Some(c) = queue.pop_front() {
+                for n in self.neighbors(&c) {
+                    if visited.insert(n.clone()) {
+                        queue.push_back(n);
+                    }
+                }
+                group.push(c);
+            }
+            res.push(group);
+        }
+        res
+    }
+}
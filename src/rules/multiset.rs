@@ -0,0 +1,79 @@
+// Copyright 2024 Junshuang Hu
+use std::{fmt::Debug, hash::Hash};
+
+use crate::{self as meme, core::{MemTarget, ObjType, TypeGroup}};
+use krnl::scalar::Scalar;
+use meme::{helpers, rules::{BasicCondition, BasicEffect}};
+use meme_derive::{IObj, IRule};
+
+/// 只作用于 untagged 对象的多重集重写规则 `u -> v`  
+/// 条件和影响完全由数据描述，用于在运行时构造规则（例如导入 P-Lingua 模型）
+/// 左侧的对象被消耗，右侧的对象在本膜中产生或发送到目标膜
+#[derive(IObj, IRule, Debug)]
+#[obj_type(TypeGroup::Rule)]
+pub struct MultisetRule<T, OT = T, U = u32>
+where 
+T: Clone + Hash + Eq + Debug + 'static,
+OT: Clone + Hash + Eq + Send + Sync + Debug + 'static,
+U: Scalar {
+    #[tag]
+    tag: T,
+    #[amount]
+    amount: U,
+    #[priority]
+    priority: i32,
+    #[rate]
+    rate: Option<f64>,
+    #[duration]
+    duration: f64,
+    #[probability]
+    probability: f64,
+    #[effect]
+    eff: BasicEffect<OT, U>,
+    #[condition]
+    cond: BasicCondition<OT, U>
+}
+
+impl<T, OT, U> MultisetRule<T, OT, U>
+where 
+T: Clone + Hash + Eq + Debug + 'static,
+OT: Clone + Hash + Eq + Send + Sync + Debug + 'static,
+U: Scalar {
+    /// `lhs` 中每种类型只应出现一次，`dissolve` 为真时规则执行后溶解所在的膜
+    pub fn new(tag: T, lhs: &[(ObjType, U)], rhs: &[(ObjType, U, MemTarget)], dissolve: bool, priority: i32) -> Self {
+        let cond = lhs.iter()
+            .fold(helpers::condition_builder(), |b, (ty, a)| b.some_untagged_of(ty.clone(), *a))
+            .build();
+        let mut eff = lhs.iter()
+            .fold(helpers::effect_builder(), |b, (ty, a)| b.decrease_untagged_of(ty.clone(), *a));
+        eff = rhs.iter().fold(eff, |b, (ty, a, target)| {
+            if *target == MemTarget::Here {
+                b.increase_untagged_of(ty.clone(), *a)
+            } else {
+                b.send_untagged_of(ty.clone(), *a, target.clone())
+            }
+        });
+        if dissolve {
+            eff = eff.dissolve_mem();
+        }
+        Self { tag, amount: U::one(), priority, rate: None, duration: 1.0, probability: 1.0, eff: eff.build(), cond }
+    }
+
+    /// 设置动力学速率常数
+    pub fn with_rate(mut self, k: f64) -> Self {
+        self.rate = Some(k);
+        self
+    }
+
+    /// 设置规则执行一次经过的模拟时间，默认为 `1`
+    pub fn with_duration(mut self, d: f64) -> Self {
+        self.duration = d;
+        self
+    }
+
+    /// 设置条件满足时执行的概率，默认为 `1`
+    pub fn with_probability(mut self, p: f64) -> Self {
+        self.probability = p;
+        self
+    }
+}
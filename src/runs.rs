@@ -0,0 +1,20 @@
+// Copyright 2024 Junshuang Hu
+pub mod sweep;
+pub mod ensemble;
+
+use crate::core::{EmuStatus, IMem};
+
+/// 运行系统直到停机、出错或达到 `max_steps` 步，返回产生新格局的步数与最后的状态  
+/// 产生新格局的步为返回 [`EmuStatus::Continue`] 或 [`EmuStatus::Stopped`] 的步，每个这样的步之后调用 `on_step`
+pub(crate) fn run_steps<M: IMem>(m: &mut M, max_steps: Option<usize>, mut on_step: impl FnMut(&M)) -> (usize, EmuStatus) {
+    let mut steps = 0;
+    let mut status = if m.ready() { EmuStatus::Continue } else { EmuStatus::EmuError };
+    while status == EmuStatus::Continue && max_steps.is_none_or(|n| steps < n) {
+        status = m.evolve();
+        if matches!(status, EmuStatus::Continue | EmuStatus::Stopped) {
+            steps += 1;
+            on_step(m);
+        }
+    }
+    (steps, status)
+}
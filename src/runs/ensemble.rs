@@ -0,0 +1,239 @@
+// Copyright 2024 Junshuang Hu
+//! 系综运行
+//!
+//! 用不同的种子构建并运行同一系统的 K 个副本，记录每一步后各观测量的值，
+//! 统计随时间变化的均值、方差与分位数，以及停机步数和输出的分布
+//! 副本在 rayon 上并行运行，每个副本的结果只依赖它的种子
+
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::hash::Hash;
+
+use krnl::scalar::Scalar;
+use rayon::prelude::*;
+
+use crate::core::{EmuStatus, IMem, IObjStat, ObjType};
+use crate::helpers::derive_seed;
+use crate::mems::basic::BasicMem;
+use crate::mems::hierarchy::MemHierarchy;
+use crate::runs::run_steps;
+
+type Factory<M> = Box<dyn Fn(u64) -> M + Send + Sync>;
+type ObserveFn<M> = Box<dyn Fn(&M) -> f64 + Send + Sync>;
+
+/// 可以按类型读取对象数量的系统
+pub trait AmountOf {
+    fn amount_of_type(&self, ty: &ObjType) -> f64;
+}
+
+impl<T, OT, RT, U> AmountOf for BasicMem<T, OT, RT, U>
+where
+T: Clone + Hash + Eq + Debug + 'static,
+OT: Clone + Hash + Eq + Send + Sync + Debug + 'static,
+RT: Clone + Hash + Eq + Send + Sync + Debug + 'static,
+U: Scalar {
+    fn amount_of_type(&self, ty: &ObjType) -> f64 {
+        self.objs().amount_of(ty).map_or(0.0, |a| a.cast::<f64>())
+    }
+}
+
+/// 所有未溶解的膜中的数量之和，不包括环境
+impl<T, OT, RT, U> AmountOf for MemHierarchy<T, OT, RT, U>
+where
+T: Clone + Hash + Eq + Debug + 'static,
+OT: Clone + Hash + Eq + Send + Sync + Debug + 'static,
+RT: Clone + Hash + Eq + Send + Sync + Debug + 'static,
+U: Scalar {
+    fn amount_of_type(&self, ty: &ObjType) -> f64 {
+        self.alive()
+            .filter_map(|i| self.mem(i))
+            .filter_map(|m| m.objs().amount_of(ty))
+            .fold(0.0, |acc, a| acc + a.cast::<f64>())
+    }
+}
+
+/// 一个副本的记录
+#[derive(Debug, Clone)]
+pub struct RunRecord {
+    pub seed: u64,
+    /// `series[t][c]` 为第 `t` 步后（`0` 为初始格局）第 `c` 个观测量的值
+    pub series: Vec<Vec<f64>>,
+    /// `times[t]` 为第 `t` 步后的模拟时间
+    pub times: Vec<f64>,
+    /// 停机时的步数，即最后一个格局的步数；达到步数上限仍未停止时为 [`None`]
+    pub halted_at: Option<usize>,
+    pub status: EmuStatus
+}
+
+impl RunRecord {
+    /// 第 `t` 步的值，停机后保持最后的值
+    pub fn value(&self, t: usize, col: usize) -> f64 {
+        self.series[t.min(self.series.len() - 1)][col]
+    }
+
+    /// 模拟时间 `time` 时的值，即最后一个不晚于 `time` 的格局的值
+    pub fn value_at_time(&self, time: f64, col: usize) -> f64 {
+        let t = self.times.partition_point(|x| *x <= time);
+        self.series[t.max(1) - 1][col]
+    }
+
+    pub fn last(&self, col: usize) -> f64 {
+        self.series[self.series.len() - 1][col]
+    }
+}
+
+/// 系综的结果
+#[derive(Debug, Clone)]
+pub struct EnsembleResult {
+    pub columns: Vec<String>,
+    pub runs: Vec<RunRecord>
+}
+
+impl EnsembleResult {
+    pub fn column_of(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|c| c == name)
+    }
+
+    /// 时间序列的长度（包括初始格局）
+    pub fn steps(&self) -> usize {
+        self.runs.iter().map(|r| r.series.len()).max().unwrap_or(0)
+    }
+
+    /// 第 `t` 步所有副本的值
+    pub fn values_at(&self, col: usize, t: usize) -> Vec<f64> {
+        self.runs.iter().map(|r| r.value(t, col)).collect()
+    }
+
+    fn over_time<F: Fn(&mut Vec<f64>) -> f64>(&self, col: usize, f: F) -> Vec<f64> {
+        (0..self.steps()).map(|t| f(&mut self.values_at(col, t))).collect()
+    }
+
+    pub fn mean(&self, col: usize) -> Vec<f64> {
+        self.over_time(col, |v| mean(v))
+    }
+
+    /// 样本方差（除以 K - 1）
+    pub fn variance(&self, col: usize) -> Vec<f64> {
+        self.over_time(col, |v| variance(v))
+    }
+
+    /// `q` 分位数，`q` 在 `[0, 1]` 内，使用线性插值
+    pub fn quantile(&self, col: usize, q: f64) -> Vec<f64> {
+        self.over_time(col, |v| quantile(v, q))
+    }
+
+    /// 停机步数的分布，未停机的副本不计入
+    pub fn halting_steps(&self) -> BTreeMap<usize, usize> {
+        self.runs.iter().filter_map(|r| r.halted_at).fold(BTreeMap::new(), |mut acc, s| {
+            *acc.entry(s).or_insert(0) += 1;
+            acc
+        })
+    }
+
+    /// 停机的副本最终输出的分布，按值排序
+    pub fn outputs(&self, col: usize) -> Vec<(f64, usize)> {
+        let mut v = self.runs.iter().filter(|r| r.halted_at.is_some()).map(|r| r.last(col)).collect::<Vec<_>>();
+        v.sort_by(f64::total_cmp);
+        v.into_iter().fold(Vec::new(), |mut acc: Vec<(f64, usize)>, x| {
+            match acc.last_mut() {
+                Some((y, n)) if *y == x => *n += 1,
+                _ => acc.push((x, 1)),
+            }
+            acc
+        })
+    }
+}
+
+pub fn mean(v: &[f64]) -> f64 {
+    if v.is_empty() { return f64::NAN; }
+    v.iter().sum::<f64>() / v.len() as f64
+}
+
+pub fn variance(v: &[f64]) -> f64 {
+    if v.len() < 2 { return 0.0; }
+    let m = mean(v);
+    v.iter().map(|x| (x - m) * (x - m)).sum::<f64>() / (v.len() - 1) as f64
+}
+
+pub fn quantile(v: &mut [f64], q: f64) -> f64 {
+    if v.is_empty() { return f64::NAN; }
+    v.sort_by(f64::total_cmp);
+    let pos = q.clamp(0.0, 1.0) * (v.len() - 1) as f64;
+    let (lo, hi) = (pos.floor() as usize, pos.ceil() as usize);
+    v[lo] + (v[hi] - v[lo]) * (pos - lo as f64)
+}
+
+/// 系综运行器
+pub struct Ensemble<M> {
+    factory: Factory<M>,
+    replicas: usize,
+    max_steps: usize,
+    seed: u64,
+    observables: Vec<(String, ObserveFn<M>)>
+}
+
+impl<M: IMem> Ensemble<M> {
+    /// `factory` 由种子构建一个副本
+    pub fn new<F>(factory: F) -> Self
+    where F: Fn(u64) -> M + Send + Sync + 'static {
+        Self { factory: Box::new(factory), replicas: 1, max_steps: 1000, seed: 0, observables: Vec::new() }
+    }
+
+    pub fn replicas(mut self, k: usize) -> Self {
+        self.replicas = k;
+        self
+    }
+
+    /// 每个副本最多运行的步数
+    pub fn max_steps(mut self, n: usize) -> Self {
+        self.max_steps = n;
+        self
+    }
+
+    /// 基础种子，副本的种子由它派生
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn observe<F>(mut self, name: &str, f: F) -> Self
+    where F: Fn(&M) -> f64 + Send + Sync + 'static {
+        self.observables.push((name.to_string(), Box::new(f)));
+        self
+    }
+
+    /// 记录 `ty` 类型对象的数量，列名为类型的 [`ObjType::short_name`]
+    pub fn track(self, ty: ObjType) -> Self
+    where M: AmountOf {
+        let name = ty.short_name().to_string();
+        self.observe(&name, move |m| m.amount_of_type(&ty))
+    }
+
+    /// 第 `k` 个副本的种子
+    pub fn seed_of(&self, k: usize) -> u64 {
+        derive_seed(self.seed, k as u64)
+    }
+
+    fn observe_all(&self, m: &M) -> Vec<f64> {
+        self.observables.iter().map(|(_, f)| f(m)).collect()
+    }
+
+    pub fn run_one(&self, seed: u64) -> RunRecord {
+        let mut m = (self.factory)(seed);
+        let mut series = vec![self.observe_all(&m)];
+        let mut times = vec![m.time()];
+        let (_, status) = run_steps(&mut m, Some(self.max_steps), |m| {
+            series.push(self.observe_all(m));
+            times.push(m.time());
+        });
+        let halted_at = matches!(status, EmuStatus::Pause | EmuStatus::Stopped).then_some(series.len() - 1);
+        RunRecord { seed, series, times, halted_at, status }
+    }
+
+    pub fn run(&self) -> EnsembleResult {
+        EnsembleResult {
+            columns: self.observables.iter().map(|(n, _)| n.clone()).collect(),
+            runs: (0..self.replicas).into_par_iter().map(|k| self.run_one(self.seed_of(k))).collect()
+        }
+    }
+}
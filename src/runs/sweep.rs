@@ -0,0 +1,185 @@
+// Copyright 2024 Junshuang Hu
+//! 参数扫描
+//!
+//! 由参数结构体构建系统，对每组参数运行系统并收集观测量，不同参数的运行在 rayon 上并行执行
+//!
+//! ```
+//! use meme::core::EmuStatus;
+//! use meme::mems::basic::BasicMem;
+//! use meme::runs::sweep::{Grid, Sweep};
+//!
+//! #[derive(Debug, Clone)]
+//! struct Params { n: u32, no_parallel: bool }
+//!
+//! let params = Grid::new(Params { n: 0, no_parallel: false })
+//!     .axis(&[1, 2, 3], |p, v| p.n = *v)
+//!     .axis(&[false, true], |p, v| p.no_parallel = *v)
+//!     .build();
+//! let table = Sweep::new(|p: &Params| {
+//!         let mut m = BasicMem::<u32, u32>::new(p.n, p.no_parallel);
+//!         m.init(Vec::new(), Vec::new(), Vec::new());
+//!         m
+//!     })
+//!     .max_steps(10)
+//!     .observe("steps", |m| m.steps() as f64)
+//!     .run(&params);
+//! assert_eq!(table.rows.len(), 6);
+//! assert!(table.rows.iter().all(|r| r.status == EmuStatus::Pause));
+//! ```
+
+use std::fmt::{Debug, Write};
+
+use rayon::prelude::*;
+
+use crate::core::{EmuStatus, IMem};
+use crate::runs::run_steps;
+
+type Setter<P> = Box<dyn Fn(&mut P) + Send + Sync>;
+type Factory<P, M> = Box<dyn Fn(&P) -> M + Send + Sync>;
+type ObserveFn<M> = Box<dyn Fn(&M) -> f64 + Send + Sync>;
+
+/// 参数网格，每个轴给出某个字段的若干取值，构建时取所有轴的笛卡尔积
+pub struct Grid<P> {
+    base: P,
+    axes: Vec<Vec<Setter<P>>>
+}
+
+impl<P: Clone> Grid<P> {
+    pub fn new(base: P) -> Self {
+        Self { base, axes: Vec::new() }
+    }
+
+    /// 添加一个轴，`set` 将取值写入参数
+    pub fn axis<V, F>(mut self, values: &[V], set: F) -> Self
+    where V: Clone + Send + Sync + 'static, F: Fn(&mut P, &V) + Clone + Send + Sync + 'static {
+        let setters = values.iter()
+            .cloned()
+            .map(|v| {
+                let set = set.clone();
+                Box::new(move |p: &mut P| set(p, &v)) as Setter<P>
+            })
+            .collect();
+        self.axes.push(setters);
+        self
+    }
+
+    /// 所有参数组合，最后添加的轴变化最快
+    pub fn build(&self) -> Vec<P> {
+        let mut res = vec![self.base.clone()];
+        for axis in self.axes.iter() {
+            res = res.iter()
+                .flat_map(|p| axis.iter().map(|set| {
+                    let mut p = p.clone();
+                    set(&mut p);
+                    p
+                }))
+                .collect();
+        }
+        res
+    }
+}
+
+/// 一组参数的运行结果
+#[derive(Debug, Clone)]
+pub struct SweepRow<P> {
+    pub params: P,
+    /// 产生新格局的步数，停机时与 [`crate::runs::ensemble::RunRecord::halted_at`] 相同
+    pub steps: usize,
+    /// 运行结束时的模拟时间
+    pub time: f64,
+    /// 运行结束时的状态，达到步数上限时为 [`EmuStatus::Continue`]
+    pub status: EmuStatus,
+    /// 与 [`SweepTable::columns`] 对应的观测量
+    pub values: Vec<f64>
+}
+
+/// 扫描结果，行的顺序与参数的顺序相同
+#[derive(Debug, Clone)]
+pub struct SweepTable<P> {
+    pub columns: Vec<String>,
+    pub rows: Vec<SweepRow<P>>
+}
+
+impl<P> SweepTable<P> {
+    pub fn column(&self, name: &str) -> Option<Vec<f64>> {
+        let i = self.columns.iter().position(|c| c == name)?;
+        Some(self.rows.iter().map(|r| r.values[i]).collect())
+    }
+
+    /// 导出为 CSV，参数列使用 `params` 给出的各列，含有 `,`、`"` 或换行的单元格会加上引号
+    pub fn to_csv<F>(&self, param_columns: &[&str], params: F) -> String
+    where F: Fn(&P) -> Vec<String> {
+        let mut out = param_columns.iter().map(|s| csv_cell(s))
+            .chain(["steps".to_string(), "time".to_string(), "status".to_string()])
+            .chain(self.columns.iter().map(|s| csv_cell(s)))
+            .collect::<Vec<_>>()
+            .join(",");
+        out.push('\n');
+        for r in self.rows.iter() {
+            let cells = params(&r.params).into_iter()
+                .chain([r.steps.to_string(), r.time.to_string(), format!("{:?}", r.status)])
+                .chain(r.values.iter().map(|v| v.to_string()))
+                .collect::<Vec<_>>();
+            let _ = writeln!(out, "{}", cells.iter().map(|c| csv_cell(c)).collect::<Vec<_>>().join(","));
+        }
+        out
+    }
+}
+
+/// 含有 `,`、`"` 或换行的单元格用引号括起，内部的引号重复一次
+fn csv_cell(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// 参数扫描
+pub struct Sweep<P, M> {
+    factory: Factory<P, M>,
+    max_steps: Option<usize>,
+    observables: Vec<(String, ObserveFn<M>)>
+}
+
+impl<P, M> Sweep<P, M>
+where P: Clone + Send + Sync, M: IMem {
+    pub fn new<F>(factory: F) -> Self
+    where F: Fn(&P) -> M + Send + Sync + 'static {
+        Self { factory: Box::new(factory), max_steps: None, observables: Vec::new() }
+    }
+
+    /// 每组参数最多运行的步数，不设置时运行到系统停止
+    pub fn max_steps(mut self, n: usize) -> Self {
+        self.max_steps = Some(n);
+        self
+    }
+
+    /// 运行结束后记录的观测量
+    pub fn observe<F>(mut self, name: &str, f: F) -> Self
+    where F: Fn(&M) -> f64 + Send + Sync + 'static {
+        self.observables.push((name.to_string(), Box::new(f)));
+        self
+    }
+
+    /// 运行一组参数
+    pub fn run_one(&self, p: &P) -> SweepRow<P> {
+        let mut m = (self.factory)(p);
+        let (steps, status) = run_steps(&mut m, self.max_steps, |_| {});
+        SweepRow {
+            params: p.clone(),
+            steps,
+            time: m.time(),
+            status,
+            values: self.observables.iter().map(|(_, f)| f(&m)).collect()
+        }
+    }
+
+    /// 并行地运行所有参数
+    pub fn run(&self, params: &[P]) -> SweepTable<P> {
+        SweepTable {
+            columns: self.observables.iter().map(|(n, _)| n.clone()).collect(),
+            rows: params.par_iter().map(|p| self.run_one(p)).collect()
+        }
+    }
+}
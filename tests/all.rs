@@ -3,6 +3,8 @@ mod misc;
 mod objs;
 mod rules;
 mod mems;
+mod formats;
+mod runs;
 
 #[test]
 fn all() {
@@ -0,0 +1,131 @@
+// Copyright 2024 Junshuang Hu
+use meme::core::{IMem, IObjStat, EmuStatus, ObjType};
+use meme::formats::{dot, pli::PliImporter};
+use meme::mems::basic::BasicMem;
+use meme::objs::com::ObjChannel;
+use meme::tagged;
+
+use crate::objs::{TestObjA, TestObjB};
+
+const MODEL: &str = "
+@model<transition>
+/* 膜 2 中的 a 变为 b 并向外送出 d，c 溶解膜 2 */
+def main()
+{
+    @mu = [[]'2]'1;
+    @ms(2) = a*2, c;
+    [a --> b, (d, out)]'2;
+    [c]'2 --> e;
+    [d -> (f, out)]'1; // 送到环境
+}
+";
+
+#[test]
+pub fn pli_import_test() {
+    let model = PliImporter::new().import::<u32>(MODEL).unwrap();
+    let ty = |n: &str| model.obj_type(n).unwrap().clone();
+    let (a, b, e, f) = (ty("a"), ty("b"), ty("e"), ty("f"));
+    let l2 = model.label_of("2").unwrap();
+    assert_eq!(model.system().labelled(l2).collect::<Vec<_>>(), vec![1]);
+    assert_eq!(model.system().mem(1).unwrap().objs().amount_of_u(&a), Some(2));
+
+    let mut sys = model.into_system();
+    assert_eq!(sys.start().ok(), Some(EmuStatus::Pause));
+    assert!(sys.node(1).unwrap().is_dissolved());
+    let skin = sys.mem(0).unwrap().objs();
+    assert_eq!(skin.amount_of_u(&a).unwrap_or(0), 0);
+    assert_eq!(skin.amount_of_u(&b), Some(2));
+    assert_eq!(skin.amount_of_u(&e), Some(1));
+    assert_eq!(sys.environment().amount_of_u(&f), Some(2));
+}
+
+#[test]
+pub fn pli_maximal_parallel_test() {
+    let src = "
+    def main() {
+        @mu = []'1;
+        @ms(1) = a*10;
+        [a --> b]'1;
+        [a --> c]'1;
+    }";
+    let model = PliImporter::new().import::<u32>(src).unwrap();
+    let (a, b, c) = (model.obj_type("a").unwrap().clone(), model.obj_type("b").unwrap().clone(),
+        model.obj_type("c").unwrap().clone());
+    let mut sys = model.into_system();
+    assert_eq!(sys.evolve(), EmuStatus::Continue);
+    let skin = sys.mem(0).unwrap().objs();
+    assert_eq!(skin.amount_of_u(&a), Some(0));
+    assert_eq!(skin.amount_of_u(&b).unwrap_or(0) + skin.amount_of_u(&c).unwrap_or(0), 10);
+}
+
+#[test]
+pub fn pli_priority_and_send_in_test() {
+    let src = "
+    def main() {
+        @mu = [[]'in_mem]'skin;
+        @ms(skin) = a*3;
+        [a --> b]'skin > [a --> c]'skin;
+        b[]'in_mem --> [x]'in_mem;
+    }";
+    let model = PliImporter::new().bind::<TestObjB>("x").import::<u32>(src).unwrap();
+    let (b, c) = (model.obj_type("b").unwrap().clone(), model.obj_type("c").unwrap().clone());
+    let x = model.obj_type("x").unwrap().clone();
+    assert!(x.tid == std::any::TypeId::of::<TestObjB>());
+
+    let mut sys = model.into_system();
+    sys.run();
+    let skin = sys.mem(0).unwrap().objs();
+    assert!(skin.amount_of_u(&c).is_none());
+    assert_eq!(skin.amount_of_u(&b), Some(0));
+    assert_eq!(sys.mem(1).unwrap().objs().amount_of_u(&x), Some(3));
+}
+
+#[test]
+pub fn pli_error_test() {
+    let res = PliImporter::new().import::<u32>("def main() {\n @mu = []'1;\n [a --> b]'2;\n}");
+    assert_eq!(res.unwrap_err().data, Some(3));
+    assert!(PliImporter::new().import::<u32>("def main() { @mu = []'1; [a]'1 --> b; }").is_err());
+    let res = PliImporter::new().import::<u32>("def main() {\n @mu = [[]'2]'1;\n [a --> b]'1 > [a --> c]'2;\n}");
+    assert_eq!(res.unwrap_err().data, Some(3));
+    let res = PliImporter::new().import::<u32>("def main() { @mu = []'1; [a --> b]'1 > [a --> c]'1; [a --> c]'1 > [a --> b]'1; }");
+    assert!(res.is_err());
+}
+
+#[test]
+pub fn pli_symbol_limit_test() {
+    let ms = |n: usize| (0..n).map(|i| format!("o{i}")).collect::<Vec<_>>().join(", ");
+    let src = |n: usize| format!("def main() {{\n @mu = []'1;\n @ms(1) = {};\n}}", ms(n));
+    assert!(PliImporter::new().import::<u32>(&src(256)).is_ok());
+    let res = PliImporter::new().import::<u32>(&src(257));
+    assert_eq!(res.unwrap_err().data, Some(3));
+    assert!(PliImporter::new().bind::<TestObjB>("o256").import::<u32>(&src(257)).is_ok());
+
+    // 自定义的类型池
+    let pool = vec![ObjType::default_group::<TestObjA>(), ObjType::default_group::<TestObjB>()];
+    let model = PliImporter::new().symbol_pool(pool.clone()).import::<u32>(&src(2)).unwrap();
+    assert_eq!(model.obj_type("o1"), Some(&pool[1]));
+    assert!(PliImporter::new().symbol_pool(pool).import::<u32>(&src(3)).is_err());
+}
+
+#[test]
+pub fn dot_export_test() {
+    let model = PliImporter::new().import::<u32>(MODEL).unwrap();
+    let tree = dot::mem_tree(model.system(), |l| model.label_name(l).unwrap().to_string());
+    assert!(tree.starts_with("digraph membranes {"));
+    assert!(tree.contains("m0 -> m1;"));
+    assert!(tree.contains("\\n'2\"]"));
+
+    let rules = dot::rule_graph(model.system().mem(1).unwrap().rules(), &model.type_names());
+    assert!(rules.contains("[shape=ellipse, label=\"a\"]"));
+    assert!(rules.contains("label=\"+1 (out)\""));
+
+    let (ca, cb) = ObjChannel::<i32>::new_pair(1, 2);
+    // 连接标识只由 tag 决定
+    assert_eq!(ca.link(), ObjChannel::<i32>::new_pair(1, 2).0.link());
+    assert_ne!(ca.link(), ObjChannel::<i32>::new_pair(1, 3).0.link());
+    let (mut ma, mut mb) = (BasicMem::<u32, i32>::new(0, false), BasicMem::<u32, i32>::new(1, false));
+    ma.init(vec![tagged!(ca)], Vec::new(), Vec::new());
+    mb.init(vec![tagged!(cb)], Vec::new(), Vec::new());
+    let chs = dot::channel_graph(&[&ma, &mb]);
+    assert!(chs.contains("m0 -> m1 [dir=none, style=dashed, label=\"1 ~ 2\"];"));
+}
@@ -0,0 +1,40 @@
+// Copyright 2024 Junshuang Hu
+use meme::formats::pli::PliImporter;
+use meme::mems::analysis::{analyze_hierarchy, analyze_mem};
+
+const MODEL: &str = "
+def main() {
+    @mu = [[]'2]'1;
+    @ms(1) = a;
+    @ms(2) = c;
+    [a --> b, (d, in 2)]'1;
+    [b --> e]'1;
+    [x --> a]'1;
+    [d --> g]'2;
+    [c]'2 --> h;
+    [h --> y]'1;
+    [y --> z]'2;
+}
+";
+
+#[test]
+pub fn dead_code_test() {
+    let model = PliImporter::new().import::<u32>(MODEL).unwrap();
+    let sys = model.system();
+
+    let res = analyze_hierarchy(sys);
+    let mut dead = res.dead_rules.clone();
+    dead.sort();
+    // x 不存在也不会被产生；溶解后膜 2 不再存在，z 的规则所需的 y 不会进入膜 2
+    assert_eq!(dead, vec![(0, 2), (1, 2)]);
+    let mut unconsumed = res.unconsumed.iter().map(|t| model.type_names().name_of(t)).collect::<Vec<_>>();
+    unconsumed.sort();
+    assert_eq!(unconsumed, vec!["e", "g", "y"]);
+    assert!(res.undeclared.is_empty());
+
+    // 单独分析膜 2 时，d 只能从外部送入
+    let inner = analyze_mem(sys.mem(1).unwrap());
+    let mut dead = inner.dead_rules.clone();
+    dead.sort();
+    assert_eq!(dead, vec![0, 2]);
+}
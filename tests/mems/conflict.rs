@@ -0,0 +1,49 @@
+// Copyright 2024 Junshuang Hu
+use std::any::TypeId;
+
+use meme::core::{EmuStatus, IMem, MemTarget, ObjType};
+use meme::mems::basic::BasicMem;
+use meme::mems::conflict::{ConflictResolver, PriorityResolver, RoundRobinResolver, WeightedResolver};
+use meme::rules::multiset::MultisetRule;
+use meme::tagged;
+
+use crate::objs::{TestObjA, TestObjB};
+
+/// 三条规则争夺唯一的 a，每两步中有一步只有一条能执行，另一步 3 把 b 换回 a
+fn winners<R: ConflictResolver<u32> + 'static>(resolver: R, steps: usize) -> Vec<u32> {
+    let (a, b) = (ObjType::default_group::<TestObjA>(), ObjType::default_group::<TestObjB>());
+    let mut m = BasicMem::<u32, u32>::new(0, false);
+    m.init(
+        Vec::new(),
+        vec![(TypeId::of::<TestObjA>(), 1)],
+        (0..3).map(|i| tagged!(MultisetRule::<u32>::new(i, &[(a.clone(), 1)], &[(b.clone(), 1, MemTarget::Here)], false, 0)) as _)
+            .chain([tagged!(MultisetRule::<u32>::new(3, &[(b.clone(), 1)], &[(a.clone(), 1, MemTarget::Here)], false, 0)) as _])
+            .collect()
+    );
+    m.set_seed(1);
+    m.set_resolver(resolver);
+    (0..steps).map(|_| {
+        assert_eq!(m.evolve(), EmuStatus::Continue);
+        assert_eq!(m.last_fired().len(), 1);
+        let w = m.last_fired()[0];
+        assert_eq!(m.evolve(), EmuStatus::Continue);
+        assert_eq!(m.last_fired(), &[3]);
+        w
+    }).collect()
+}
+
+#[test]
+pub fn conflict_resolver_test() {
+    assert!(winners(PriorityResolver::new().rank(2, 10), 10).iter().all(|w| *w == 2));
+
+    let rr = winners(RoundRobinResolver::new(), 9);
+    for round in rr.chunks(3) {
+        let mut r = round.to_vec();
+        r.sort();
+        assert_eq!(r, vec![0, 1, 2]);
+    }
+
+    let w = winners(WeightedResolver::new().weight(0, 0.0).weight(1, 100.0), 50);
+    assert!(!w.contains(&0));
+    assert!(w.iter().filter(|x| **x == 1).count() > 40);
+}
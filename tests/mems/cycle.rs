@@ -0,0 +1,85 @@
+// Copyright 2024 Junshuang Hu
+use std::any::TypeId;
+
+use meme::core::{EmuStatus, IMem, MemTarget, ObjType};
+use meme::formats::pli::PliImporter;
+use meme::mems::basic::BasicMem;
+use meme::mems::cycle::CycleDetector;
+use meme::rules::multiset::MultisetRule;
+use meme::tagged;
+
+use crate::objs::{TestObjA, TestObjB, TestObjC};
+
+#[test]
+pub fn mem_cycle_test() {
+    let (a, b, c) = (ObjType::default_group::<TestObjA>(), ObjType::default_group::<TestObjB>(), ObjType::default_group::<TestObjC>());
+    let mut m = BasicMem::<u32, u32>::new(0, false);
+    m.init(
+        Vec::new(),
+        vec![(TypeId::of::<TestObjA>(), 1), (TypeId::of::<TestObjC>(), 2)],
+        vec![
+            tagged!(MultisetRule::<u32>::new(0, &[(c.clone(), 1)], &[], false, 1)),
+            tagged!(MultisetRule::<u32>::new(1, &[(a.clone(), 1)], &[(b.clone(), 1, MemTarget::Here)], false, 0)),
+            tagged!(MultisetRule::<u32>::new(2, &[(b, 1)], &[(a, 1, MemTarget::Here)], false, 0)),
+        ]
+    );
+    m.detect_cycles(true);
+    assert_eq!(m.start().ok(), Some(EmuStatus::Stopped));
+    let cy = m.cycle().unwrap();
+    // 前两步消耗 c，之后 a 与 b 交替
+    assert_eq!((cy.first_seen, cy.step, cy.length), (2, 4, 2));
+    let mut rules = cy.rules.clone();
+    rules.sort();
+    assert_eq!(rules, vec![1, 2]);
+    assert_eq!(m.evolve(), EmuStatus::Stopped);
+}
+
+#[test]
+pub fn hierarchy_cycle_test() {
+    let src = "
+    def main() {
+        @mu = [[]'2]'1;
+        @ms(1) = a;
+        [a --> (b, in 2)]'1;
+        [b --> (a, out)]'2;
+    }";
+    let mut sys = PliImporter::new().import::<u32>(src).unwrap().into_system();
+    sys.detect_cycles(true);
+    assert_eq!(sys.start().ok(), Some(EmuStatus::Stopped));
+    let cy = sys.cycle().unwrap();
+    assert_eq!((cy.first_seen, cy.length), (0, 2));
+    assert_eq!(cy.rules, vec![(0, 0), (1, 0)]);
+}
+
+#[test]
+pub fn fingerprint_collision_test() {
+    // 指纹相同但快照不同的格局不构成环
+    let mut d = CycleDetector::<u32, Vec<u32>>::new();
+    d.start(|| (7, vec![1]));
+    assert!(d.record(7, vec![2], vec![0]).is_none());
+    assert!(d.record(8, vec![3], vec![1]).is_none());
+    let cy = d.record(7, vec![2], vec![2]).unwrap();
+    assert_eq!((cy.first_seen, cy.step, cy.length), (1, 3, 2));
+    assert_eq!(cy.rules, vec![1, 2]);
+}
+
+#[test]
+pub fn cycle_window_test() {
+    // 比窗口长的环在第二次重复时确认
+    let mut d = CycleDetector::<u32, Vec<u32>>::new().window(1);
+    d.start(|| (1, vec![1]));
+    assert!(d.record(2, vec![2], vec![0]).is_none());
+    assert!(d.record(1, vec![1], vec![1]).is_none());
+    assert!(d.record(2, vec![2], vec![0]).is_none());
+    let cy = d.record(1, vec![1], vec![1]).unwrap();
+    assert_eq!((cy.first_seen, cy.step, cy.length), (2, 4, 2));
+    assert_eq!(cy.rules, vec![0, 1]);
+
+    // 窗口外指纹相同但快照不同的格局不构成环
+    let mut d = CycleDetector::<u32, Vec<u32>>::new().window(1);
+    d.start(|| (1, vec![1]));
+    assert!(d.record(2, vec![2], vec![0]).is_none());
+    assert!(d.record(1, vec![3], vec![1]).is_none());
+    assert!(d.record(2, vec![4], vec![0]).is_none());
+    assert!(d.record(1, vec![5], vec![1]).is_none());
+}
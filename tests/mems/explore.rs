@@ -0,0 +1,150 @@
+// Copyright 2024 Junshuang Hu
+use meme::core::{IMem, IUntaggedStore};
+use meme::formats::pli::PliImporter;
+use meme::mems::{basic::BasicMem, explore::Explorer, stochastic::EvolveMode};
+use meme::tagged;
+
+use crate::rules::TestRuleA;
+
+#[test]
+pub fn explore_hierarchy_test() {
+    let src = "
+    def main() {
+        @mu = [[]'2]'1;
+        @ms(2) = a;
+        [a --> (b, out)]'2;
+        [a --> (c, out)]'2;
+        [b --> (b, out)]'1;
+    }";
+    let model = PliImporter::new().import::<u32>(src).unwrap();
+    let (b, c) = (model.obj_type("b").unwrap().clone(), model.obj_type("c").unwrap().clone());
+    let g = Explorer::new().explore_hierarchy(model.system()).unwrap();
+    assert!(g.is_complete());
+    assert_eq!(g.len(), 4);
+    assert_eq!(g.successors(0).len(), 2);
+    assert_eq!(g.halting().len(), 2);
+    let mut outs = g.halting_outputs(|cfg| (cfg.environment_amount(&b), cfg.amount_in(0, &c)));
+    outs.sort();
+    assert_eq!(outs, vec![(0, 1), (1, 0)]);
+
+    // 模拟的结果是穷举的结果之一
+    let mut sys = model.into_system();
+    sys.run();
+    let emulated = (sys.environment().get_u(&b.tid).unwrap_or(0), sys.mem(0).unwrap().objs().get_u(&c.tid).unwrap_or(0));
+    assert!(outs.contains(&emulated));
+}
+
+#[test]
+pub fn explore_mem_test() {
+    let src = "
+    def main() {
+        @mu = []'1;
+        @ms(1) = a*3;
+        [a --> b*2]'1;
+    }";
+    let model = PliImporter::new().import::<u32>(src).unwrap();
+    let b = model.obj_type("b").unwrap().clone();
+    // 导入的膜按极大多重集演化，一步用完所有 a
+    let g = Explorer::new().explore_mem(model.system().mem(0).unwrap()).unwrap();
+    assert!(g.is_complete());
+    assert_eq!(g.len(), 2);
+    assert_eq!(g.halting_outputs(|cfg| cfg.amount_in(0, &b)), vec![6]);
+    assert!(!Explorer::new().max_configs(1).explore_mem(model.system().mem(0).unwrap()).unwrap().is_complete());
+
+    // 默认方式下每条规则每步最多执行一次
+    let mut sys = model.into_system();
+    let m = sys.mem_mut(0).unwrap();
+    m.set_mode(EvolveMode::MaximallyParallel);
+    let g = Explorer::new().explore_mem(m).unwrap();
+    assert_eq!(g.len(), 4);
+    assert_eq!(g.halting_outputs(|cfg| cfg.amount_in(0, &b)), vec![6]);
+
+    m.set_mode(EvolveMode::Gillespie);
+    assert!(Explorer::new().explore_mem(m).is_err());
+
+    let mut m = BasicMem::<u32, i32>::new(0, false);
+    m.init(Vec::new(), Vec::new(), vec![tagged!(TestRuleA::new(7, 1))]);
+    assert!(Explorer::new().explore_mem(&m).is_err_and(|e| e.data == Some(7)));
+}
+
+#[test]
+pub fn explore_multiset_test() {
+    let src = "
+    def main() {
+        @mu = []'1;
+        @ms(1) = a*2;
+        [a --> b]'1;
+        [a --> c]'1;
+    }";
+    let model = PliImporter::new().import::<u32>(src).unwrap();
+    let (b, c) = (model.obj_type("b").unwrap().clone(), model.obj_type("c").unwrap().clone());
+    let g = Explorer::new().explore_mem(model.system().mem(0).unwrap()).unwrap();
+    assert_eq!(g.len(), 4);
+    assert_eq!(g.successors(0).len(), 3);
+    let mut outs = g.halting_outputs(|cfg| (cfg.amount_in(0, &b), cfg.amount_in(0, &c)));
+    outs.sort();
+    assert_eq!(outs, vec![(0, 2), (1, 1), (2, 0)]);
+
+    // 优先关系在步开始时筛选规则
+    let src = "
+    def main() {
+        @mu = []'1;
+        @ms(1) = a*2;
+        [a --> b]'1 > [a --> c]'1;
+    }";
+    let model = PliImporter::new().import::<u32>(src).unwrap();
+    let b = model.obj_type("b").unwrap().clone();
+    let g = Explorer::new().explore_mem(model.system().mem(0).unwrap()).unwrap();
+    assert_eq!(g.len(), 2);
+    assert_eq!(g.halting_outputs(|cfg| cfg.amount_in(0, &b)), vec![2]);
+}
+
+#[test]
+pub fn temporal_test() {
+    let src = "
+    def main() {
+        @mu = [[]'2]'1;
+        @ms(2) = a;
+        [a --> (b, out)]'2;
+        [a --> (c, out)]'2;
+        [b --> (b, out)]'1;
+    }";
+    let model = PliImporter::new().import::<u32>(src).unwrap();
+    let (b, c) = (model.obj_type("b").unwrap().clone(), model.obj_type("c").unwrap().clone());
+    let g = Explorer::new().explore_hierarchy(model.system()).unwrap();
+
+    let v = g.check_halting_exists(|cfg| cfg.environment_amount(&b) == 1);
+    assert_eq!(v.holds, Some(true));
+    let t = v.trace.unwrap();
+    assert_eq!(t.steps, vec![vec![(1, 0)], vec![(0, 0)]]);
+    assert_eq!(g.check_halting_exists(|cfg| cfg.environment_amount(&b) == 2).holds, Some(false));
+    assert_eq!(g.check_always(|cfg| cfg.total(&c) <= 1).holds, Some(true));
+
+    let v = g.check_eventually_always(|cfg| cfg.environment_amount(&b) > 0);
+    assert_eq!(v.holds, Some(false));
+    let t = v.trace.unwrap();
+    assert_eq!(t.steps, vec![vec![(1, 1)]]);
+    assert_eq!(t.cycle_start, Some(1));
+
+    let src = "
+    def main() {
+        @mu = []'1;
+        @ms(1) = a;
+        [a --> b]'1;
+        [b --> a]'1;
+    }";
+    let model = PliImporter::new().import::<u32>(src).unwrap();
+    let a = model.obj_type("a").unwrap().clone();
+    let g = Explorer::new().explore_mem(model.system().mem(0).unwrap()).unwrap();
+    assert_eq!(g.check_halting_exists(|_| true).holds, Some(false));
+    assert_eq!(g.check_always(|cfg| cfg.total(&a) <= 1).holds, Some(true));
+    let v = g.check_always(|cfg| cfg.total(&a) == 1);
+    assert_eq!(v.holds, Some(false));
+    assert_eq!(v.trace.unwrap().steps, vec![vec![(0, 0)]]);
+    let v = g.check_eventually_always(|cfg| cfg.total(&a) > 0);
+    assert_eq!(v.holds, Some(false));
+    let t = v.trace.unwrap();
+    assert_eq!(t.configs, vec![0, 1, 0, 1]);
+    assert_eq!(t.cycle_start, Some(1));
+    assert_eq!(g.check_eventually_always(|cfg| cfg.total(&a) <= 1).holds, Some(true));
+}
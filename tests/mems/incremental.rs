@@ -0,0 +1,98 @@
+// Copyright 2024 Junshuang Hu
+use std::any::TypeId;
+
+use meme::core::{EmuStatus, IMem, IObjStat, IRuleStat, ITaggedStore, IUntaggedStore, MemTarget, ObjType};
+use meme::mems::basic::{BasicMem, PBasicRule};
+use meme::objs::BasicObjStore;
+use meme::rules::multiset::MultisetRule;
+use meme::rules::BasicRuleStore;
+use meme::tagged;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use crate::objs::{TestObjA, TestObjB, TestObjC};
+
+#[test]
+pub fn change_tracking_test() {
+    let (ta, tb) = (TypeId::of::<TestObjA>(), TypeId::of::<TestObjB>());
+    let mut os = BasicObjStore::<i32>::new();
+    assert!(!os.modified());
+    os.add_or_update(1, Box::new(TestObjA::new(1, 0.0)));
+    os.increase(&tb, 2).unwrap();
+    assert!(os.changes().touches_tag(&1) && os.changes().touches_type(&ta) && os.changes().touches_type(&tb));
+    os.dismiss();
+    assert!(!os.modified());
+    os.get_mut(&1);
+    assert!(os.changes().touches_tag(&1) && !os.changes().touches_type(&tb));
+    os.dismiss();
+    os.iter_mut().for_each(|_| {});
+    assert!(os.changes().is_all() && os.changes().touches_tag(&7));
+}
+
+#[test]
+pub fn incremental_check_test() {
+    let (ta, tb) = (ObjType::default_group::<TestObjA>(), ObjType::default_group::<TestObjB>());
+    let mut os = BasicObjStore::<u32>::new();
+    os.increase(&ta.tid, 1).unwrap();
+    let mut rst = BasicRuleStore::new();
+    rst.add_or_update(0, tagged!(MultisetRule::<u32>::new(0, &[(ta.clone(), 1)], &[], false, 0)));
+    for i in 1..=50 {
+        rst.add_or_update(i, tagged!(MultisetRule::<u32>::new(i, &[(tb.clone(), i)], &[], false, 0)));
+    }
+    let mut rng = StdRng::seed_from_u64(0);
+    let fired = |rst: &mut BasicRuleStore<u32, u32>, os: &mut BasicObjStore<u32>, rng: &mut StdRng| {
+        let res = rst.check_on_changes_with(os, os.changes(), false, rng);
+        os.dismiss();
+        let mut v = res.parallel_executable.into_iter().flatten()
+            .chain(res.conflict_executable.into_iter().flatten())
+            .map(|e| e.rule_index)
+            .collect::<Vec<_>>();
+        v.sort();
+        v
+    };
+    assert_eq!(fired(&mut rst, &mut os, &mut rng), vec![0]);
+    assert_eq!(rst.cached_unsatisfied(), 50);
+
+    // 只有 a 变化，依赖 b 的规则不再检查
+    os.increase(&ta.tid, 1).unwrap();
+    assert_eq!(fired(&mut rst, &mut os, &mut rng), vec![0]);
+    assert_eq!(rst.cached_unsatisfied(), 50);
+
+    os.increase(&tb.tid, 3).unwrap();
+    assert_eq!(fired(&mut rst, &mut os, &mut rng), vec![0, 1, 2, 3]);
+    assert_eq!(rst.cached_unsatisfied(), 47);
+
+    // 规则的加入与移除保持缓存与规则的位置对应
+    rst.remove(&1);
+    rst.add_or_update(99, tagged!(MultisetRule::<u32>::new(99, &[(tb.clone(), 1)], &[], false, 0)));
+    let res = fired(&mut rst, &mut os, &mut rng);
+    assert_eq!(res.len(), 4);
+    assert!(res.iter().all(|i| rst.tag_at(*i).is_some_and(|t| t == 0 || t == 2 || t == 3 || t == 99)));
+}
+
+#[test]
+pub fn incremental_mem_test() {
+    let (ta, tb, tc) = (ObjType::default_group::<TestObjA>(), ObjType::default_group::<TestObjB>(), ObjType::default_group::<TestObjC>());
+    let run = |incremental: bool| {
+        let mut m = BasicMem::<u32, u32>::new(0, false);
+        let mut rules: Vec<PBasicRule<u32, u32, u32>> = vec![
+            tagged!(MultisetRule::<u32>::new(0, &[(ta.clone(), 1)], &[(tb.clone(), 1, MemTarget::Here)], false, 0)),
+            tagged!(MultisetRule::<u32>::new(1, &[(ta.clone(), 1)], &[], false, 0)),
+            tagged!(MultisetRule::<u32>::new(2, &[(tb.clone(), 2)], &[(tc.clone(), 1, MemTarget::Here)], false, 0)),
+        ];
+        rules.extend((3..40).map(|i| tagged!(MultisetRule::<u32>::new(i, &[(tc.clone(), i)], &[], false, 0)) as _));
+        m.init(Vec::new(), vec![(TypeId::of::<TestObjA>(), 30)], rules);
+        m.set_incremental_check(incremental);
+        m.set_seed(4);
+        let mut trace = Vec::new();
+        while m.evolve() == EmuStatus::Continue {
+            let mut fired = m.last_fired().to_vec();
+            fired.sort();
+            trace.push((fired, m.objs().amount_of(&tb), m.objs().amount_of(&tc)));
+        }
+        (trace, m.rules().cached_unsatisfied())
+    };
+    let (trace, cached) = run(true);
+    assert!(cached > 30);
+    assert_eq!(run(false).0, trace);
+}
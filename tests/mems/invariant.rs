@@ -0,0 +1,35 @@
+// Copyright 2024 Junshuang Hu
+use std::any::TypeId;
+
+use meme::core::{EmuStatus, IMem, IObjStat, MemTarget, ObjType};
+use meme::mems::{basic::BasicMem, invariant::Invariant};
+use meme::rules::multiset::MultisetRule;
+use meme::tagged;
+
+use crate::objs::{TestObjA, TestObjB, TestObjC};
+
+#[test]
+pub fn invariant_test() {
+    let (a, b, c) = (ObjType::default_group::<TestObjA>(), ObjType::default_group::<TestObjB>(), ObjType::default_group::<TestObjC>());
+    let mut m = BasicMem::<u32, u32>::new(0, false);
+    m.init(
+        Vec::new(),
+        vec![(TypeId::of::<TestObjA>(), 3)],
+        vec![
+            tagged!(MultisetRule::<u32>::new(0, &[(a.clone(), 1)], &[(b.clone(), 1, MemTarget::Here)], false, 0)),
+            tagged!(MultisetRule::<u32>::new(1, &[(b.clone(), 1)], &[(c.clone(), 1, MemTarget::Here)], false, 0)),
+        ]
+    );
+    m.add_invariant(Invariant::holds("a never grows", move |os| os.amount_of(&a).is_some_and(|n| n <= 3)));
+    m.add_invariant(Invariant::constant_sum("a + b", &[ObjType::default_group::<TestObjA>(), b]));
+
+    assert_eq!(m.start().ok(), Some(EmuStatus::EmuError));
+    let v = m.violation().unwrap();
+    assert_eq!(v.step, 1);
+    assert_eq!(v.invariant, "a + b");
+    let mut fired = v.fired.clone();
+    fired.sort();
+    assert_eq!(fired, vec![0, 1]);
+    assert_eq!(m.evolve(), EmuStatus::EmuError);
+    assert_eq!(m.steps(), 2);
+}
@@ -1 +1,13 @@
-pub mod test_mem;
\ No newline at end of file
+pub mod test_mem;
+pub mod analysis;
+pub mod invariant;
+pub mod explore;
+pub mod cycle;
+pub mod stats;
+pub mod seed;
+pub mod conflict;
+pub mod tags;
+pub mod stochastic;
+pub mod time;
+pub mod probability;
+pub mod incremental;
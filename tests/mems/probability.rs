@@ -0,0 +1,110 @@
+// Copyright 2024 Junshuang Hu
+use std::any::TypeId;
+
+use meme::core::{EmuStatus, IMem, IObjStat, IRuleStat, ITaggedStore, IUntaggedStore, MemTarget, ObjType};
+use meme::mems::basic::BasicMem;
+use meme::mems::explore::Explorer;
+use meme::mems::stats::FailReason;
+use meme::mems::stochastic::EvolveMode;
+use meme::objs::BasicObjStore;
+use meme::rules::multiset::MultisetRule;
+use meme::rules::BasicRuleStore;
+use meme::tagged;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use crate::objs::{TestObjA, TestObjB};
+
+#[test]
+pub fn probabilistic_rule_test() {
+    let (ta, tb) = (ObjType::default_group::<TestObjA>(), ObjType::default_group::<TestObjB>());
+
+    // 0 与 1 争夺唯一的 a，1 永远不会被抽中，因此释放需要的 a，0 可以并行执行
+    let mut os = BasicObjStore::<u32>::new();
+    os.increase(&ta.tid, 1).unwrap();
+    let mut rst = BasicRuleStore::new();
+    rst.add_or_update(0, tagged!(MultisetRule::<u32>::new(0, &[(ta.clone(), 1)], &[], false, 0)));
+    rst.add_or_update(1, tagged!(MultisetRule::<u32>::new(1, &[(ta.clone(), 1)], &[], false, 0).with_probability(0.0)));
+    assert_eq!(rst.probability_at(1), Some(0.0));
+    let mut rng = StdRng::seed_from_u64(3);
+    for _ in 0..10 {
+        let res = rst.check_on_with(&os, &mut rng);
+        assert!(res.conflict_executable.is_none());
+        assert!(res.parallel_executable.is_some_and(|v| v.len() == 1 && v[0].rule_index == 0));
+    }
+
+    // 每个 a 每步以 0.5 的概率变为 b
+    let run = |seed: u64| {
+        let mut m = BasicMem::<u32, u32>::new(0, false);
+        m.init(
+            Vec::new(),
+            vec![(TypeId::of::<TestObjA>(), 1)],
+            (0..200).map(|i| tagged!(MultisetRule::<u32>::new(i, &[(ta.clone(), 1)], &[(tb.clone(), 1, MemTarget::Here)], false, 0).with_probability(0.5)) as _).collect()
+        );
+        m.objs_mut().increase(&ta.tid, 199).unwrap();
+        m.set_seed(seed);
+        m.collect_stats(true);
+        m.evolve();
+        m
+    };
+    let m = run(11);
+    let b = m.objs().amount_of(&tb).unwrap();
+    assert!((70..130).contains(&b), "{}", b);
+    assert_eq!(m.objs().amount_of(&ta).unwrap() + b, 200);
+    assert_eq!(m.last_fired().len() as u32, b);
+    let missed = m.all_rule_stats().filter(|(_, s)| s.last_failure == Some(FailReason::NotDrawn)).count();
+    assert_eq!(missed as u32, 200 - b);
+    assert_eq!(run(11).objs().amount_of(&tb), Some(b));
+
+    let mut m = run(11);
+    while m.evolve() == EmuStatus::Continue {}
+    assert_eq!(m.objs().amount_of(&tb), Some(200));
+    assert!(m.objs().is_empty());
+
+    // 状态空间穷举不支持概率规则
+    assert!(Explorer::new().explore_mem(&m).is_err());
+}
+
+#[test]
+pub fn seeded_untagged_check_test() {
+    let ta = ObjType::default_group::<TestObjA>();
+    let mut os = BasicObjStore::<TypeId>::new();
+    os.increase(&ta.tid, 20).unwrap();
+    let mut rst = BasicRuleStore::new();
+    for i in 0..20 {
+        rst.add_or_update(i, tagged!(MultisetRule::<u32, TypeId>::new(i, &[(ta.clone(), 1)], &[], false, 0).with_probability(0.5)));
+    }
+    let drawn = |seed: u64| {
+        let res = rst.check_on_untagged_with(&os, &mut StdRng::seed_from_u64(seed));
+        res.parallel_executable.into_iter().chain(res.conflict_executable)
+            .flatten()
+            .map(|e| e.rule_index)
+            .collect::<Vec<_>>()
+    };
+    let first = drawn(5);
+    assert!(!first.is_empty() && first.len() < 20);
+    assert_eq!(first, drawn(5));
+}
+
+#[test]
+pub fn draw_before_priority_test() {
+    // 概率在优先级筛选之前抽取：没有抽中的高优先级规则不会压制低优先级规则
+    let (ta, tb) = (ObjType::default_group::<TestObjA>(), ObjType::default_group::<TestObjB>());
+    for mode in [EvolveMode::MaximallyParallel, EvolveMode::MaximalMultiset] {
+        let mut m = BasicMem::<u32, u32>::new(0, false);
+        m.init(
+            Vec::new(),
+            vec![(TypeId::of::<TestObjA>(), 1)],
+            vec![
+                tagged!(MultisetRule::<u32>::new(0, &[(ta.clone(), 1)], &[], false, 1).with_probability(0.0)),
+                tagged!(MultisetRule::<u32>::new(1, &[(ta.clone(), 1)], &[(tb.clone(), 1, MemTarget::Here)], false, 0)),
+            ]
+        );
+        m.set_mode(mode);
+        m.collect_stats(true);
+        assert_eq!(m.evolve(), EmuStatus::Continue);
+        assert_eq!(m.last_fired(), &[1]);
+        assert_eq!(m.objs().amount_of(&tb), Some(1));
+        assert_eq!(m.rule_stats(&0).unwrap().last_failure, Some(FailReason::NotDrawn));
+    }
+}
@@ -0,0 +1,69 @@
+// Copyright 2024 Junshuang Hu
+use std::any::TypeId;
+
+use meme::core::{EmuStatus, IMem, ITaggedStore};
+use meme::helpers;
+use meme::mems::basic::BasicMem;
+use meme::rules::{BasicCondition, BasicEffect};
+use meme::tagged;
+use meme_derive::*;
+
+use crate::objs::{TestObjA, TestObjB};
+
+#[derive(IObj, IRule, Debug)]
+pub struct TestRuleEat {
+    #[tag]
+    tag: u32,
+    #[effect]
+    eff: BasicEffect<i32>,
+    #[condition]
+    cond: BasicCondition<i32>
+}
+
+impl TestRuleEat {
+    pub fn new(tag: u32) -> Self {
+        Self {
+            tag,
+
+            cond: helpers::condition_builder()
+                .some_untagged::<TestObjB>(1)
+                .rand_tagged::<TestObjA>(1).by_take()
+                .build(),
+
+            eff: helpers::effect_builder()
+                .decrease_untagged::<TestObjB>(1)
+                .build(),
+        }
+    }
+}
+
+type Trace = Vec<(Vec<u32>, Vec<i32>)>;
+
+fn run(seed: u64) -> Trace {
+    let mut m = BasicMem::<u32, i32>::new(0, false);
+    m.init(
+        (0..10).map(|i| tagged!(TestObjA::new(i, 0.0)) as _).collect(),
+        vec![(TypeId::of::<TestObjB>(), 5)],
+        (0..3).map(|i| tagged!(TestRuleEat::new(i)) as _).collect()
+    );
+    m.set_seed(seed);
+    let mut trace = Vec::new();
+    while m.evolve() == EmuStatus::Continue {
+        let mut fired = m.last_fired().to_vec();
+        fired.sort();
+        let mut left = m.objs().iter().map(|o| *o.obj_tag()).collect::<Vec<_>>();
+        left.sort();
+        trace.push((fired, left));
+    }
+    trace
+}
+
+#[test]
+pub fn seeded_run_test() {
+    let trace = run(7);
+    assert_eq!(trace.last().map(|(_, left)| left.len()), Some(5));
+    for _ in 0..5 {
+        assert_eq!(run(7), trace);
+    }
+    assert!((0..10).map(run).any(|t| t != trace));
+}
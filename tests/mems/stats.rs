@@ -0,0 +1,121 @@
+// Copyright 2024 Junshuang Hu
+use std::any::TypeId;
+
+use meme::core::{EmuStatus, IMem, IObjStat, IRule, MemTarget, ObjType};
+use meme::mems::basic::BasicMem;
+use meme::mems::stats::FailReason;
+use meme::mems::stochastic::{propensity, EvolveMode};
+use meme::rules::multiset::MultisetRule;
+use meme::tagged;
+
+use crate::objs::{TestObjA, TestObjB, TestObjC};
+
+#[test]
+pub fn rule_stats_test() {
+    let (a, b, c) = (ObjType::default_group::<TestObjA>(), ObjType::default_group::<TestObjB>(), ObjType::default_group::<TestObjC>());
+    let mut m = BasicMem::<u32, u32>::new(0, false);
+    m.init(
+        Vec::new(),
+        vec![(TypeId::of::<TestObjA>(), 1), (TypeId::of::<TestObjC>(), 1)],
+        vec![
+            tagged!(MultisetRule::<u32>::new(0, &[(a.clone(), 1)], &[(b.clone(), 1, MemTarget::Here)], false, 0)),
+            tagged!(MultisetRule::<u32>::new(1, &[(a.clone(), 1)], &[], false, 0)),
+            tagged!(MultisetRule::<u32>::new(2, &[(b.clone(), 2)], &[], false, 0)),
+            tagged!(MultisetRule::<u32>::new(3, &[(c, 1)], &[], false, 1)),
+        ]
+    );
+    m.collect_stats(true);
+    assert!(m.ready());
+
+    // 第一步只执行优先级最高的 3
+    m.evolve();
+    let s = m.rule_stats(&3).unwrap();
+    assert_eq!((s.checked, s.applicable, s.fired_parallel), (1, 1, 1));
+    assert_eq!(m.rule_stats(&0).unwrap().last_failure, Some(FailReason::LowerPriority));
+    assert_eq!(
+        m.rule_stats(&2).unwrap().last_failure,
+        Some(FailReason::InsufficientAmount { ty: b, needed: 2, present: 0 })
+    );
+
+    // 第二步 0 与 1 争夺 a，只有一个能执行
+    m.evolve();
+    let (s0, s1) = (m.rule_stats(&0).unwrap(), m.rule_stats(&1).unwrap());
+    assert_eq!((s0.checked, s0.applicable, s1.applicable), (2, 2, 2));
+    assert_eq!(s0.fired_conflict + s1.fired_conflict, 1);
+    assert_eq!(s0.lost_conflict + s1.lost_conflict, 1);
+    let loser = if s0.fired() == 0 { s0 } else { s1 };
+    assert_eq!(loser.last_failure, Some(FailReason::LostConflict));
+    assert_eq!(m.all_rule_stats().count(), 4);
+}
+
+#[test]
+pub fn untagged_condition_test() {
+    // tagged 的 b 不计入 untagged 条件
+    let b = ObjType::default_group::<TestObjB>();
+    let rule = || MultisetRule::<u32, i32>::new(0, &[(b.clone(), 2)], &[], false, 0);
+    let mut m = BasicMem::<u32, i32>::new(0, false);
+    m.init(
+        vec![tagged!(TestObjB::new(1))],
+        vec![(TypeId::of::<TestObjB>(), 1)],
+        vec![tagged!(rule())]
+    );
+    assert_eq!(m.objs().amount_of(&b), Some(2));
+    assert_eq!(propensity(rule().condition(), m.objs(), 1.0), 0.0);
+    m.collect_stats(true);
+    assert_eq!(m.evolve(), EmuStatus::Pause);
+    assert!(m.last_fired().is_empty());
+    assert_eq!(m.objs().amount_of(&b), Some(2));
+    assert_eq!(
+        m.rule_stats(&0).unwrap().last_failure,
+        Some(FailReason::InsufficientAmount { ty: b, needed: 2, present: 1 })
+    );
+}
+
+#[test]
+pub fn multiset_stats_test() {
+    // 极大多重集语义下 0 执行两次只计一步，1 被优先级压制
+    let (a, c) = (ObjType::default_group::<TestObjA>(), ObjType::default_group::<TestObjC>());
+    let mut m = BasicMem::<u32, u32>::new(0, false);
+    m.set_mode(EvolveMode::MaximalMultiset);
+    m.init(
+        Vec::new(),
+        vec![(TypeId::of::<TestObjA>(), 2), (TypeId::of::<TestObjC>(), 1)],
+        vec![
+            tagged!(MultisetRule::<u32>::new(0, &[(a.clone(), 1)], &[], false, 1)),
+            tagged!(MultisetRule::<u32>::new(1, &[(c.clone(), 1)], &[], false, 0)),
+        ]
+    );
+    m.collect_stats(true);
+    assert_eq!(m.evolve(), EmuStatus::Continue);
+    let (s0, s1) = (m.rule_stats(&0).unwrap(), m.rule_stats(&1).unwrap());
+    assert_eq!((s0.checked, s0.applicable, s0.fired_parallel), (1, 1, 1));
+    assert_eq!((s1.checked, s1.applicable, s1.fired()), (1, 1, 0));
+    assert_eq!(s1.last_failure, Some(FailReason::LowerPriority));
+    assert_eq!(m.objs().amount_of(&a), Some(0));
+}
+
+#[test]
+pub fn stochastic_stats_test() {
+    // 随机模式中倾向函数大于零的规则视为条件满足，没有速率的规则倾向函数为零
+    let a = ObjType::default_group::<TestObjA>();
+    for mode in [EvolveMode::Gillespie, EvolveMode::TauLeap(Default::default())] {
+        let mut m = BasicMem::<u32, u32>::new(0, false);
+        m.init(
+            Vec::new(),
+            vec![(TypeId::of::<TestObjA>(), 100)],
+            vec![
+                tagged!(MultisetRule::<u32>::new(0, &[(a.clone(), 1)], &[], false, 0).with_rate(1.0)),
+                tagged!(MultisetRule::<u32>::new(1, &[(a.clone(), 1)], &[], false, 0)),
+            ]
+        );
+        m.set_mode(mode);
+        m.set_seed(3);
+        m.collect_stats(true);
+        while m.evolve() == EmuStatus::Continue {}
+        let (s0, s1) = (m.rule_stats(&0).unwrap(), m.rule_stats(&1).unwrap());
+        assert!(s0.fired_parallel > 0);
+        assert_eq!(s0.checked, s1.checked);
+        assert_eq!(s0.applicable, s0.checked - 1);
+        assert_eq!((s1.applicable, s1.fired()), (0, 0));
+    }
+}
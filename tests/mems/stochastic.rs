@@ -0,0 +1,110 @@
+// Copyright 2024 Junshuang Hu
+use std::any::TypeId;
+
+use meme::core::{EmuStatus, IMem, IObjStat, IRule, ITaggedStore, IUntaggedStore, MemTarget, ObjType};
+use meme::helpers;
+use meme::mems::basic::BasicMem;
+use meme::mems::stochastic::{binomial, propensity, EvolveMode, TauLeap};
+use meme::objs::BasicObjStore;
+use meme::rules::multiset::MultisetRule;
+use meme::rules::BasicCondition;
+use meme::tagged;
+
+use crate::objs::{TestObjA, TestObjB};
+
+fn decay_mem(a: u32, seed: u64) -> BasicMem<u32, u32> {
+    let (ta, tb) = (ObjType::default_group::<TestObjA>(), ObjType::default_group::<TestObjB>());
+    let mut m = BasicMem::<u32, u32>::new(0, false);
+    m.init(
+        Vec::new(),
+        vec![(TypeId::of::<TestObjA>(), a)],
+        vec![
+            tagged!(MultisetRule::<u32>::new(0, &[(ta.clone(), 1)], &[], false, 0).with_rate(1.0)),
+            // 没有速率的规则在随机模拟模式中不会执行
+            tagged!(MultisetRule::<u32>::new(1, &[(ta, 1)], &[(tb, 1, MemTarget::Here)], false, 0)),
+        ]
+    );
+    m.set_mode(EvolveMode::Gillespie);
+    m.set_seed(seed);
+    m
+}
+
+#[test]
+pub fn gillespie_test() {
+    assert_eq!(binomial(10.0, 2.0), 45.0);
+    let ta = ObjType::default_group::<TestObjA>();
+    let r = MultisetRule::<u32>::new(0, &[(ta.clone(), 2)], &[], false, 0).with_rate(0.5);
+    let mut os = BasicObjStore::<u32>::new();
+    os.increase(&ta.tid, 10).unwrap();
+    assert_eq!(propensity(r.condition(), &os, r.rate().unwrap()), 22.5);
+
+    // 衰变 A -> ∅，E[A(t)] = A(0)·e^(-t)
+    let mut m = decay_mem(1000, 5);
+    while m.time() < 1.0 {
+        assert_eq!(m.evolve(), EmuStatus::Continue);
+        assert_eq!(m.last_fired(), &[0]);
+    }
+    let left = m.objs().amount_of(&ta).unwrap();
+    assert!((300..440).contains(&left), "{}", left);
+    assert_eq!(m.steps() as u32, 1000 - left);
+
+    while m.evolve() == EmuStatus::Continue {}
+    assert_eq!(m.steps(), 1000);
+    assert!(m.time() > 3.0 && m.time() < 15.0);
+    assert_eq!(m.objs().amount_of(&ObjType::default_group::<TestObjB>()), None);
+
+    let mut again = decay_mem(1000, 5);
+    while again.evolve() == EmuStatus::Continue {}
+    assert_eq!(again.time(), m.time());
+}
+
+#[test]
+pub fn tau_leap_test() {
+    let (ta, tb) = (ObjType::default_group::<TestObjA>(), ObjType::default_group::<TestObjB>());
+    let run = |seed: u64| {
+        let mut m = BasicMem::<u32, u32>::new(0, false);
+        m.init(
+            Vec::new(),
+            vec![(TypeId::of::<TestObjA>(), 100_000)],
+            vec![tagged!(MultisetRule::<u32>::new(0, &[(ta.clone(), 1)], &[(tb.clone(), 1, MemTarget::Here)], false, 0).with_rate(1.0))]
+        );
+        m.set_mode(EvolveMode::TauLeap(TauLeap::default()));
+        m.set_seed(seed);
+        while m.time() < 1.0 {
+            assert_eq!(m.evolve(), EmuStatus::Continue);
+        }
+        m
+    };
+    let m = run(9);
+    let (a, b) = (m.objs().amount_of(&ta).unwrap(), m.objs().amount_of(&tb).unwrap());
+    assert_eq!(a + b, 100_000);
+    // E[A(t)] = A(0)·e^(-t)，步数远少于直接法
+    let expected = 100_000.0 * (-m.time()).exp();
+    assert!((a as f64 - expected).abs() < 0.02 * expected, "{} {}", a, expected);
+    assert!(m.steps() < 1000);
+    assert_eq!(run(9).objs().amount_of(&ta), Some(a));
+
+    // 数量很少时退回直接法，数量不会变为负数
+    let mut small = decay_mem(20, 1);
+    small.set_mode(EvolveMode::TauLeap(TauLeap::default()));
+    while small.evolve() == EmuStatus::Continue {}
+    assert_eq!(small.steps(), 20);
+    assert_eq!(small.objs().amount_of(&ta), Some(0));
+}
+
+#[test]
+pub fn propensity_rand_tags_test() {
+    // 随机选择的需求按同类型且满足筛选条件的对象的组合数计算
+    let mut os = BasicObjStore::<i32>::new();
+    for i in 0..4 {
+        os.add_or_update(i, tagged!(TestObjA::new(i, i as f32)));
+    }
+    os.add_or_update(4, tagged!(TestObjB::new(4)));
+    let cond = |n: usize| helpers::condition_builder().rand_tagged::<TestObjA>(n).build::<BasicCondition<i32>>();
+    assert_eq!(propensity(&cond(2), &os, 0.5), 3.0);
+    assert_eq!(propensity(&cond(5), &os, 0.5), 0.0);
+    let filtered = helpers::condition_builder()
+        .rand_tagged::<TestObjA>(2).filter_by(|o| o.as_any().downcast_ref::<TestObjA>().is_some_and(|a| a.get_inner() > 1.0))
+        .build::<BasicCondition<i32>>();
+    assert_eq!(propensity(&filtered, &os, 1.0), 1.0);
+}
@@ -0,0 +1,99 @@
+// Copyright 2024 Junshuang Hu
+use std::any::TypeId;
+
+use meme::core::{EmuStatus, IMem, ITaggedStore, IUntaggedStore};
+use meme::helpers::{self, TagGen};
+use meme::mems::basic::BasicMem;
+use meme::rules::{BasicCondition, BasicEffect};
+use meme::tagged;
+use meme_derive::*;
+
+use crate::objs::{TestObjA, TestObjB};
+
+#[derive(IObj, IRule, Debug)]
+pub struct TestRuleSpawn {
+    #[tag]
+    tag: u32,
+    #[effect]
+    eff: BasicEffect<i32>,
+    #[condition]
+    cond: BasicCondition<i32>
+}
+
+impl TestRuleSpawn {
+    pub fn new(tag: u32) -> Self {
+        Self {
+            tag,
+
+            cond: helpers::condition_builder()
+                .some_untagged::<TestObjB>(1)
+                .build(),
+
+            eff: helpers::effect_builder()
+                .decrease_untagged::<TestObjB>(1)
+                .crate_obj(|req| Box::new(TestObjA::new(req.next_tag(), 1.0)))
+                .produces::<TestObjA>()
+                .build(),
+        }
+    }
+}
+
+fn spawning_mem(b: u32, existing: Vec<i32>) -> BasicMem<u32, i32> {
+    let mut m = BasicMem::<u32, i32>::new(0, false);
+    m.init(
+        existing.into_iter().map(|t| tagged!(TestObjA::new(t, 0.0)) as _).collect(),
+        vec![(TypeId::of::<TestObjB>(), b)],
+        (0..3).map(|i| tagged!(TestRuleSpawn::new(i)) as _).collect()
+    );
+    m
+}
+
+fn tags_of(m: &BasicMem<u32, i32>) -> Vec<i32> {
+    let mut tags = m.objs().iter().map(|o| *o.obj_tag()).collect::<Vec<_>>();
+    tags.sort();
+    tags
+}
+
+#[test]
+pub fn tag_gen_test() {
+    let mut g = TagGen::starting_at(10);
+    let mut lanes = vec![g.lane(0, 2), g.lane(1, 2)];
+    assert_eq!((lanes[0].next_u64(), lanes[0].next_u64(), lanes[1].next_tag::<u32>()), (10, 12, 11));
+    g.advance(&lanes);
+    assert_eq!(g.peek(), 13);
+    g.reset();
+    assert_eq!(g.peek(), 10);
+
+    // 每个膜的 tag 只取决于自身的运行
+    for _ in 0..2 {
+        let mut m = spawning_mem(4, Vec::new());
+        while m.evolve() == EmuStatus::Continue {}
+        assert_eq!(tags_of(&m), vec![0, 1, 2, 3]);
+        assert_eq!(m.tag_gen().peek(), 4);
+    }
+
+    let mut m = spawning_mem(3, vec![1]);
+    m.check_tag_collisions(true);
+    while m.evolve() == EmuStatus::Continue {}
+    assert_eq!(m.tag_collisions(), &[1]);
+    assert_eq!(tags_of(&m), vec![0, 1, 2]);
+    assert_eq!(m.objs().get(&1).and_then(|o| o.as_any().downcast_ref::<TestObjA>()).map(|a| a.get_inner()), Some(0.0));
+}
+
+#[test]
+pub fn try_add_test() {
+    let mut m = spawning_mem(0, vec![1]);
+    let os = m.objs_mut();
+    os.set_collision_check(true);
+    // add_or_update 总是替换
+    assert!(os.add_or_update(1, Box::new(TestObjA::new(1, 2.0))).is_some());
+    assert!(os.try_add(1, Box::new(TestObjA::new(1, 3.0))).is_err());
+    assert!(os.try_add(2, Box::new(TestObjA::new(2, 3.0))).is_ok());
+    assert_eq!(os.get(&1).and_then(|o| o.as_any().downcast_ref::<TestObjA>()).map(|a| a.get_inner()), Some(2.0));
+    assert!(os.collisions().is_empty());
+
+    // 数量会溢出时拒绝
+    os.increase(&TypeId::of::<TestObjB>(), u32::MAX).unwrap();
+    assert!(os.try_add(3, Box::new(TestObjB::new(3))).is_err());
+    assert!(!os.contains(&3));
+}
@@ -1,7 +1,7 @@
 // Copyright 2024 Junshuang Hu
-use std::thread;
+use std::{any::TypeId, thread};
 
-use meme::{core::IMem, helpers, mems::basic::BasicMem, objs::com::{ObjChannel, SendMsg, SendWrapper}, rules::{com::SendReceiveRule, BasicCondition, BasicEffect}, tagged};
+use meme::{core::{EmuStatus, IMem, IObj, IObjStat, IUntaggedStore, ObjType, PObj}, helpers, mems::basic::BasicMem, objs::com::{ObjChannel, SendMsg, SendWrapper}, rules::{com::SendReceiveRule, BasicCondition, BasicEffect}, tagged};
 use meme_derive::*;
 use crate::{objs::{TestObjA, TestObjB}, rules::{TestRuleA, TestRuleB, TestRuleC, TestRuleD}};
 
@@ -47,6 +47,23 @@ pub struct TestComRule {
     cond: BasicCondition<i32>
 }
 
+/// 通过 `ch` 通道发送一个 `TestObjA`，并加入一个 untagged `StopObj`
+fn com_effect() -> BasicEffect<i32> {
+    helpers::effect_builder()
+        .crate_obj(|req| {
+            let co = req.set_ref(0).unwrap();
+            let ct: &i32 = co.obj_tag();
+            let v = vec![
+                SendWrapper::new(Box::new(TestObjA::new(helpers::IdGen::next_i32_id(), 555.555)), *ct)
+            ];
+            Box::new(SendMsg::<i32>::new(helpers::IdGen::next_i32_id(), v))
+        })
+        .produces::<SendMsg<i32>>()
+        //.crate_obj(|_| Box::new(StopObj { tag: helpers::IdGen::next_i32_id() }))
+        .increase_untagged::<StopObj>(1)
+        .build()
+}
+
 impl TestComRule {
     pub fn new(tag: u32, to_ch: i32) -> Self {
         Self {
@@ -57,28 +74,29 @@ impl TestComRule {
                 .the_tagged(to_ch).by_ref()
                 .build(),
 
-            eff: helpers::effect_builder()
-                .crate_obj(|req| {
-                    let co = req.set_ref(0).unwrap();
-                    let ct: &i32 = co.obj_tag();
-                    let v = vec![
-                        SendWrapper::new(Box::new(TestObjA::new(helpers::IdGen::next_i32_id(), 555.555)), ct.clone())
-                    ];
-                    let pobj = Box::new(SendMsg::<i32>::new(helpers::IdGen::next_i32_id(), v));
-                    pobj
-                })
-                //.crate_obj(|_| Box::new(StopObj { tag: helpers::IdGen::next_i32_id() }))
-                .increase_untagged::<StopObj>(1)
+            eff: com_effect(),
+        }
+    }
+
+    /// 需要一个 tagged `TestObjB`，tagged 对象不满足 [`helpers::ConditionBuilder::some_untagged`]
+    pub fn new_tagged(tag: u32, to_ch: i32) -> Self {
+        Self {
+            tag,
+
+            cond: helpers::condition_builder()
+                .rand_tagged::<TestObjB>(1).by_ref()
+                .the_tagged(to_ch).by_ref()
                 .build(),
+
+            eff: com_effect(),
         }
     }
 }
 
-#[test]
-pub fn basics() {
+fn run_basics(untagged_b: u32, com: fn(u32, i32) -> TestComRule) {
     let mut m = BasicMem::<u32, i32>::new(100, false);
     let mut ids = Vec::new();
-    ids.resize_with(4, || helpers::IdGen::next_i32_id());
+    ids.resize_with(4, helpers::IdGen::next_i32_id);
     let (ca, cb) = ObjChannel::<i32>::new_pair(ids[2], ids[3]);
     m.init(
         vec![
@@ -86,13 +104,13 @@ pub fn basics() {
             tagged!(TestObjA::new(ids[1], 2.2)),
             tagged!(ca)
         ],
-        Default::default(),
+        vec![(TypeId::of::<TestObjB>(), untagged_b)],
         vec![
             tagged!(TestRuleA::new(0, ids[0])),
             tagged!(TestRuleB::new(1)),
             tagged!(TestRuleStop::new(2)),
             tagged!(SendReceiveRule::new(3, vec![ids[2]])),
-            tagged!(TestComRule::new(4, ids[2])),
+            tagged!(com(4, ids[2])),
             tagged!(TestRuleC::new(5)),
             tagged!(TestRuleD::new(6))
         ]
@@ -108,3 +126,95 @@ pub fn basics() {
     assert!(got_a.is_some());
     assert_eq!(got_a.unwrap().get_inner(),  555.555);
 }
+
+#[test]
+pub fn basics() {
+    run_basics(1, TestComRule::new);
+}
+
+/// 只有 `TestRuleB` 生成的 tagged `TestObjB` 时使用 tagged 条件
+#[test]
+pub fn basics_tagged() {
+    run_basics(0, TestComRule::new_tagged);
+}
+
+/// untagged 条件忽略 tagged 对象：只有 tagged `TestObjB` 时 `TestComRule` 不可执行
+#[test]
+pub fn untagged_condition_ignores_tagged() {
+    let mut m = BasicMem::<u32, i32>::new(100, false);
+    let (ca, cb) = ObjChannel::<i32>::new_pair(helpers::IdGen::next_i32_id(), helpers::IdGen::next_i32_id());
+    let ch = *ca.obj_tag();
+    let mut objs: Vec<PObj<i32, u32>> = vec![tagged!(ca)];
+    objs.extend((0..3).map(|_| tagged!(TestObjB::new(helpers::IdGen::next_i32_id())) as PObj<i32, u32>));
+    m.init(objs, Vec::new(), vec![tagged!(TestComRule::new(0, ch)), tagged!(TestRuleStop::new(1))]);
+    assert_eq!(m.objs().amount_of_t(&ObjType::default_group::<TestObjB>()), Some(3));
+    assert!(!m.objs().contains_u(&TypeId::of::<TestObjB>()));
+    assert_ne!(m.evolve(), EmuStatus::Continue);
+    assert!(!m.objs().contains_u(&TypeId::of::<StopObj>()));
+    assert!(cb.try_receive().is_err());
+}
+
+#[derive(IObj, IRule, Debug)]
+pub struct TestRuleUndeclared {
+    #[tag]
+    tag: u32,
+    #[effect]
+    eff: BasicEffect<i32>,
+    #[condition]
+    cond: BasicCondition<i32>
+}
+
+impl TestRuleUndeclared {
+    pub fn new(tag: u32) -> Self {
+        Self::with_effect(
+            tag,
+            helpers::effect_builder()
+                .crate_obj(|_| Box::new(TestObjB::new(helpers::IdGen::next_i32_id())))
+                .produces::<TestObjA>()
+                .build()
+        )
+    }
+
+    pub fn with_effect(tag: u32, eff: BasicEffect<i32>) -> Self {
+        Self {
+            tag,
+
+            cond: helpers::condition_builder()
+                .some_untagged::<StopObj>(1)
+                .build(),
+
+            eff,
+        }
+    }
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "undeclared type")]
+pub fn undeclared_product() {
+    let mut m = BasicMem::<u32, i32>::new(101, false);
+    m.init(Vec::new(), vec![(TypeId::of::<StopObj>(), 1)], vec![tagged!(TestRuleUndeclared::new(0))]);
+    m.evolve();
+}
+
+/// 没有声明的创建操作与延迟创建的对象同样被检查
+#[test]
+#[cfg(debug_assertions)]
+pub fn undeclared_creation() {
+    let effs: [fn() -> BasicEffect<i32>; 2] = [
+        || helpers::effect_builder().crate_obj(|_| Box::new(TestObjB::new(helpers::IdGen::next_i32_id()))).build(),
+        || helpers::effect_builder()
+            .crate_obj_after(|_| Box::new(TestObjB::new(helpers::IdGen::next_i32_id())), 1.0)
+            .produces::<TestObjA>()
+            .build(),
+    ];
+    for eff in effs {
+        let res = std::panic::catch_unwind(|| {
+            let mut m = BasicMem::<u32, i32>::new(102, false);
+            m.init(Vec::new(), vec![(TypeId::of::<StopObj>(), 1)], vec![tagged!(TestRuleUndeclared::with_effect(0, eff()))]);
+            m.evolve();
+        });
+        let msg = res.unwrap_err().downcast::<String>().map(|s| *s).unwrap_or_default();
+        assert!(msg.contains("undeclared type"));
+    }
+}
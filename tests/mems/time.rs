@@ -0,0 +1,132 @@
+// Copyright 2024 Junshuang Hu
+use std::any::TypeId;
+
+use meme::core::{EmuStatus, IMem, IObjStat, ITaggedStore, MemTarget, ObjType};
+use meme::helpers;
+use meme::mems::basic::BasicMem;
+use meme::mems::hierarchy::MemHierarchy;
+use meme::rules::multiset::MultisetRule;
+use meme::rules::{BasicCondition, BasicEffect};
+use meme::tagged;
+use meme_derive::*;
+
+use crate::objs::{TestObjA, TestObjB, TestObjC};
+
+#[derive(IObj, IRule, Debug)]
+pub struct TestRuleDelay {
+    #[tag]
+    tag: u32,
+    #[duration]
+    duration: f64,
+    #[effect]
+    eff: BasicEffect<i32>,
+    #[condition]
+    cond: BasicCondition<i32>
+}
+
+impl TestRuleDelay {
+    pub fn new(tag: u32) -> Self {
+        Self {
+            tag,
+            duration: 0.5,
+
+            cond: helpers::condition_builder()
+                .some_untagged::<TestObjA>(1)
+                .build(),
+
+            eff: helpers::effect_builder()
+                .decrease_untagged::<TestObjA>(1)
+                .increase_untagged_after::<TestObjB>(2, 5.0)
+                .crate_obj_after(|_| Box::new(TestObjC::new(7)), 3.0)
+                .produces::<TestObjC>()
+                .build(),
+        }
+    }
+}
+
+#[test]
+pub fn duration_test() {
+    let (ta, tb, tc) = (ObjType::default_group::<TestObjA>(), ObjType::default_group::<TestObjB>(), ObjType::default_group::<TestObjC>());
+    let mut m = BasicMem::<u32, u32>::new(0, false);
+    m.init(
+        Vec::new(),
+        vec![(ta.tid, 2), (tb.tid, 1)],
+        vec![
+            tagged!(MultisetRule::<u32>::new(0, &[(ta.clone(), 1)], &[], false, 0).with_duration(2.0)),
+            tagged!(MultisetRule::<u32>::new(1, &[(tb.clone(), 1)], &[(tc, 1, MemTarget::Here)], false, 0).with_duration(0.5)),
+        ]
+    );
+    // 一步的时长为本步执行了的规则的最长时长
+    assert_eq!(m.evolve(), EmuStatus::Continue);
+    assert_eq!(m.time(), 2.0);
+    assert_eq!(m.evolve(), EmuStatus::Continue);
+    assert_eq!(m.time(), 4.0);
+    assert_eq!(m.evolve(), EmuStatus::Pause);
+    assert_eq!(m.time(), 4.0);
+}
+
+#[test]
+pub fn schedule_test() {
+    let (tb, tc) = (ObjType::default_group::<TestObjB>(), ObjType::default_group::<TestObjC>());
+    let mut m = BasicMem::<u32, i32>::new(0, false);
+    m.init(Vec::new(), vec![(TypeId::of::<TestObjA>(), 1)], vec![tagged!(TestRuleDelay::new(0))]);
+
+    assert_eq!(m.evolve(), EmuStatus::Continue);
+    assert_eq!(m.time(), 0.5);
+    assert_eq!(m.agenda().len(), 2);
+    assert_eq!(m.agenda().next_time(), Some(3.0));
+    assert_eq!(m.objs().amount_of(&tb), None);
+
+    // 没有规则可以执行时跳到下一个对象出现的时间
+    assert_eq!(m.evolve(), EmuStatus::Continue);
+    assert_eq!(m.time(), 3.0);
+    assert!(m.objs().contains(&7) && m.objs().amount_of(&tc) == Some(1));
+    assert_eq!(m.objs().amount_of(&tb), None);
+    assert_eq!(m.run_until_time(4.0), EmuStatus::Continue);
+    assert_eq!(m.time(), 5.0);
+    assert_eq!(m.objs().amount_of(&tb), Some(2));
+    assert!(m.agenda().is_empty());
+    assert_eq!(m.run_until_time(10.0), EmuStatus::Pause);
+}
+
+#[test]
+pub fn hierarchy_time_test() {
+    let (ta, tb) = (ObjType::default_group::<TestObjA>(), ObjType::default_group::<TestObjB>());
+    let mut root = BasicMem::<u32, u32>::new(0, false);
+    root.init(
+        Vec::new(),
+        vec![(ta.tid, 2)],
+        vec![tagged!(MultisetRule::<u32>::new(0, &[(ta.clone(), 1)], &[], false, 0).with_duration(3.0))]
+    );
+    let mut child = BasicMem::<u32, u32>::new(1, false);
+    child.init(
+        Vec::new(),
+        vec![(tb.tid, 4)],
+        vec![tagged!(MultisetRule::<u32>::new(0, &[(tb.clone(), 1)], &[], false, 0))]
+    );
+    let mut h = MemHierarchy::new(0, root, 0);
+    h.add_child(0, child, 1);
+
+    // 每步结束时所有膜的时间对齐到最晚的时间
+    assert_eq!(h.evolve(), EmuStatus::Continue);
+    assert_eq!(h.time(), 3.0);
+    assert!(h.alive().all(|i| h.mem(i).unwrap().time() == 3.0));
+    assert_eq!(h.run_until_time(7.0), EmuStatus::Continue);
+    assert_eq!(h.time(), 7.0);
+    assert_eq!(h.mem(1).unwrap().objs().amount_of(&tb), Some(1));
+}
+
+#[test]
+pub fn run_until_time_stalled_test() {
+    // a -> a 的时长为零，每一步都停留在同一时间
+    let ta = ObjType::default_group::<TestObjA>();
+    let mut m = BasicMem::<u32, u32>::new(0, false);
+    m.init(
+        Vec::new(),
+        vec![(ta.tid, 1)],
+        vec![tagged!(MultisetRule::<u32>::new(0, &[(ta.clone(), 1)], &[(ta, 1, MemTarget::Here)], false, 0).with_duration(0.0))]
+    );
+    assert_eq!(m.run_until_time(1.0), EmuStatus::Stopped);
+    assert_eq!(m.time(), 0.0);
+    assert_eq!(m.steps(), 1);
+}
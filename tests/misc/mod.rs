@@ -15,7 +15,7 @@ pub fn index_map_test() {
     m.insert(4, 'd');
     assert_eq!(*m.get(&1).unwrap(), 'a');
     assert_eq!(m.index_of(&2).unwrap(), 1);
-    let v1 = vec!['a', 'b', 'c', 'd'];
+    let v1 = ['a', 'b', 'c', 'd'];
     assert!(v1.iter().zip(m.vals()).all(|(a, b)| {
         a == b
     }));
@@ -23,11 +23,21 @@ pub fn index_map_test() {
     *r = 'e';
     assert_eq!(*m.get(&4).unwrap(), 'e');
     assert_eq!(m.remove(&3).unwrap(), 'c');
-    let v2 = vec!['a',  'b',  'e'];
+    let v2 = ['a',  'b',  'e'];
     assert!(v2.iter().zip(m.vals()).all(|(a, b)| {
         a == b
     }));
     assert_eq!(m.index_of(&1), Some(0));
+
+    // 交换删除：最后一个元素移到被移除的位置
+    m.insert(5, 'f');
+    m.insert(6, 'g');
+    assert_eq!(m.remove_full(&2), Some((1, 'b')));
+    assert_eq!(m.keys().copied().collect::<Vec<_>>(), vec![1, 6, 4, 5]);
+    assert!(m.keys().enumerate().all(|(i, k)| m.index_of(k) == Some(i) && m.get_key(i) == Some(k)));
+    assert_eq!(m.remove_batch(&[1, 7, 5]).into_iter().map(|kv| kv.map(|(k, _)| k)).collect::<Vec<_>>(), vec![Some(1), None, Some(5)]);
+    assert_eq!(m.keys().copied().collect::<Vec<_>>(), vec![4, 6]);
+    assert_eq!((m.at(0), m.at(1), m.len()), (Some(&'e'), Some(&'g'), 2));
 }
 
 #[test]
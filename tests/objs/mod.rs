@@ -1,9 +1,18 @@
 // Copyright 2024 Junshuang Hu
 use std::any::TypeId;
 
-use meme::core::{IObjStat, ITaggedStore, TypeGroup};
+use ahash::AHashSet;
+use meme::core::{choose_tagged, EmuStatus, IMem, IObjStat, ITaggedStore, IUntaggedStore, ObjType, PObj, TypeGroup};
+use meme::errors::AmountError;
+use meme::helpers::{self, IdGen};
+use meme::mems::basic::BasicMem;
+use meme::objs::uid::UuidTag;
 use meme::objs::BasicObjStore;
-use meme_derive::IObj;
+use meme::rules::{BasicCondition, BasicEffect};
+use meme::tagged;
+use meme_derive::{IObj, IRule};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
 #[derive(IObj, Debug)]
 #[obj_type(TypeGroup::Normal)]
@@ -83,14 +92,14 @@ pub fn basic_obj_store_test() {
 
     let pat = st.get(&2).unwrap();
     let pbt = st.get(&3).unwrap();
-    let tys = vec![pat.obj_type(), pbt.obj_type()];
+    let tys = [pat.obj_type(), pbt.obj_type()];
 
     assert!(st.remove(&1).is_some());
-    assert_eq!(*st.amounts().collect::<Vec<_>>(), vec![&1, &2]);
+    assert_eq!(st.amounts().collect::<Vec<_>>(), vec![1, 2]);
     assert_eq!(st.amount_of(&tys[0]), Some(1));
     assert_eq!(st.amount_of(&tys[1]), Some(2));
     
-    assert_eq!(st.amount_of_many(&tys[..]), vec![&1, &2]);
+    assert_eq!(st.amount_of_many(&tys[..]), vec![1, 2]);
 
     let mut ite = st.objs();
     ite.next();
@@ -100,4 +109,245 @@ pub fn basic_obj_store_test() {
 
     assert_eq!(*st.tid_at(0).unwrap(), TypeId::of::<TestObjA>());
 
-}
\ No newline at end of file
+}
+#[derive(IObj, Debug)]
+pub struct TestObjU {
+    #[tag]
+    tag: UuidTag
+}
+
+#[derive(IObj, IRule, Debug)]
+pub struct TestRuleU {
+    #[tag]
+    tag: u32,
+    #[effect]
+    eff: BasicEffect<UuidTag>,
+    #[condition]
+    cond: BasicCondition<UuidTag>
+}
+
+#[test]
+pub fn uuid_tag_test() {
+    let tags = (0..3).map(|_| IdGen::next_uuid()).collect::<Vec<_>>();
+    let mut ost = BasicObjStore::new();
+    for t in tags.iter() {
+        assert!(ost.add_or_update(*t, Box::new(TestObjU { tag: *t })).is_none());
+    }
+    assert_eq!(ost.amount_of(&ObjType::default_group::<TestObjU>()), Some(3));
+    assert!(ost.get(&tags[1]).is_some_and(|o| o.obj_tag() == &tags[1]));
+    assert_eq!(tags[2].to_string().parse::<UuidTag>().ok(), Some(tags[2]));
+
+    let (mut r1, mut r2) = (StdRng::seed_from_u64(3), StdRng::seed_from_u64(3));
+    assert_eq!(UuidTag::from_rng(&mut r1), UuidTag::from_rng(&mut r2));
+
+    assert_eq!(UuidTag::default(), UuidTag::nil());
+
+    // 由膜的种子生成的 uuid 可以复现
+    let run = |seed: u64| {
+        let mut m = BasicMem::<u32, UuidTag>::new(0, false);
+        m.set_seed(seed);
+        m.init(
+            Vec::new(),
+            vec![(TypeId::of::<TestObjB>(), 3)],
+            vec![tagged!(TestRuleU {
+                tag: 0,
+                cond: helpers::condition_builder().some_untagged::<TestObjB>(1).build(),
+                eff: helpers::effect_builder()
+                    .decrease_untagged::<TestObjB>(1)
+                    .crate_obj(|req| Box::new(TestObjU { tag: req.next_uuid() }))
+                    .produces::<TestObjU>()
+                    .build()
+            })]
+        );
+        while m.evolve() == EmuStatus::Continue {}
+        let mut tags = m.objs().objs().map(|o| *o.obj_tag()).collect::<Vec<_>>();
+        tags.sort();
+        tags
+    };
+    let tags = run(7);
+    assert_eq!(tags.len(), 3);
+    assert!(!tags.contains(&UuidTag::nil()));
+    assert_eq!(tags, run(7));
+    assert_ne!(tags, run(8));
+}
+
+#[test]
+pub fn type_index_test() {
+    let (ta, tb) = (ObjType::default_group::<TestObjA>(), ObjType::default_group::<TestObjB>());
+    let mut st = BasicObjStore::new();
+    for i in 0..10 {
+        st.add_or_update(i, Box::new(TestObjA::new(i, 0.0)));
+    }
+    st.add_or_update(10, Box::new(TestObjB::new(10)));
+    st.add_or_update(3, Box::new(TestObjB::new(3)));
+    st.remove(&0);
+    st.remove_batch_skip(&[9, 10]);
+    let mut a_tags = st.tags_of(&ta.tid).unwrap().to_vec();
+    a_tags.sort();
+    assert_eq!(a_tags, vec![1, 2, 4, 5, 6, 7, 8]);
+    assert_eq!(st.tags_of(&tb.tid), Some(&[3][..]));
+    assert_eq!(st.tags_of(&TypeId::of::<TestObjC>()), Some(&[][..]));
+
+    let mut rng = StdRng::seed_from_u64(1);
+    let choosed = [1, 2, 4].into_iter().collect::<AHashSet<_>>();
+    let mut seen = AHashSet::new();
+    for _ in 0..50 {
+        let picked = choose_tagged(&st, &ta, 3, &choosed, None, &mut rng);
+        assert_eq!(picked.len(), 3);
+        assert!(picked.iter().all(|t| !choosed.contains(t) && a_tags.contains(t)));
+        assert_eq!(picked.iter().collect::<AHashSet<_>>().len(), 3);
+        seen.extend(picked);
+    }
+    assert_eq!(seen.len(), 4);
+    assert_eq!(choose_tagged(&st, &ta, 5, &choosed, None, &mut rng).len(), 4);
+    assert!(choose_tagged(&st, &tb, 1, &[3].into_iter().collect(), None, &mut rng).is_empty());
+}
+
+#[derive(IObj, Debug)]
+pub struct TestObjW {
+    #[tag]
+    tag: i32,
+    #[amount]
+    weight: u32
+}
+
+#[test]
+pub fn amount_accounting_test() {
+    let (tb, tw) = (ObjType::default_group::<TestObjB>(), ObjType::default_group::<TestObjW>());
+    let mut st = BasicObjStore::<i32, u32>::new();
+    st.add_or_update(0, Box::new(TestObjB::new(0)));
+    st.add_or_update(1, Box::new(TestObjW { tag: 1, weight: 5 }));
+    assert_eq!((st.amount_of_t(&tw), st.amount_of_u(&tw), st.amount_of(&tw)), (Some(5), Some(0), Some(5)));
+    assert!(!st.contains_u(&tb.tid));
+    // tagged 对象不能当作 untagged 对象减少
+    assert_eq!(st.decrease(&tb.tid, 1), Err(AmountError::Underflow { ty: tb.tid, present: 0, requested: 1 }));
+    assert_eq!(st.decrease(&TypeId::of::<TestObjC>(), 1), Err(AmountError::Missing(TypeId::of::<TestObjC>())));
+    assert_eq!(st.increase(&tw.tid, 3), Ok(3));
+    assert_eq!(st.increase(&tw.tid, u32::MAX - 5), Err(AmountError::Overflow { ty: tw.tid, present: 8, added: u32::MAX - 5 }));
+    assert_eq!(st.amount_of(&tw), Some(8));
+    assert_eq!(st.remove_u(&tw.tid), Some(3));
+    assert_eq!(st.amount_of(&tw), Some(5));
+    st.remove(&1);
+    assert_eq!(st.remove_u(&tw.tid), Some(0));
+    assert_eq!(st.amount_of(&tw), None);
+    assert_eq!(st.remove_u(&tw.tid), None);
+    assert_eq!(st.amounts_t().collect::<Vec<_>>(), vec![1]);
+}
+
+#[test]
+pub fn amount_boundary_test() {
+    let ty = TypeId::of::<TestObjC>();
+    let mut st = BasicObjStore::<i32, u64>::new();
+    assert_eq!(st.increase(&ty, u64::MAX - 1), Ok(u64::MAX - 1));
+    assert_eq!(st.increase(&ty, 1), Ok(u64::MAX));
+    assert_eq!(st.increase(&ty, 1), Err(AmountError::Overflow { ty, present: u64::MAX, added: 1 }));
+    assert_eq!(st.decrease(&ty, u64::MAX), Ok(0));
+
+    let mut st = BasicObjStore::<i32, i8>::new();
+    assert_eq!(st.increase(&ty, 127), Ok(127));
+    assert_eq!(st.increase(&ty, -128), Ok(-1));
+    assert!(st.increase(&ty, -128).is_err());
+
+    let mut st = BasicObjStore::<i32, f32>::new();
+    assert_eq!(st.increase(&ty, f32::MAX), Ok(f32::MAX));
+    assert!(st.increase(&ty, f32::MAX).is_err());
+    assert_eq!(st.increase(&ty, 1.0), Ok(f32::MAX));
+
+    // 加入或替换 tagged 对象同样检查溢出，溢出时交回新对象且不修改存储
+    let tw = ObjType::default_group::<TestObjW>();
+    let mut st = BasicObjStore::<i32, u32>::new();
+    st.add_or_update(0, Box::new(TestObjW { tag: 0, weight: u32::MAX - 1 }));
+    let back = st.add_or_update(1, Box::new(TestObjW { tag: 1, weight: 2 }));
+    assert_eq!(back.map(|o| *o.obj_tag()), Some(1));
+    assert!(!st.contains(&1));
+    assert_eq!(st.amount_of_t(&tw), Some(u32::MAX - 1));
+    st.add_or_update(0, Box::new(TestObjW { tag: 0, weight: u32::MAX }));
+    assert_eq!(st.amount_of_t(&tw), Some(u32::MAX));
+    // 修改对象数量后未重新计数，移除时报告 underflow 而不是把数量置为 0
+    st.add_or_update(0, Box::new(TestObjW { tag: 0, weight: 5 }));
+    st.get_mut(&0).unwrap().as_any_mut().downcast_mut::<TestObjW>().unwrap().weight = 10;
+    st.remove(&0);
+    assert_eq!(st.amount_of_t(&tw), Some(5));
+    st.recount_tagged();
+    assert_eq!(st.amounts_of(&tw.tid).and_then(|a| a.total()), Some(0));
+}
+
+/// 随机的操作序列下，各数量视图与对象实例保持一致，失败的操作不修改数量
+#[test]
+pub fn amount_consistency_test() {
+    let tys = [ObjType::default_group::<TestObjB>(), ObjType::default_group::<TestObjW>(), ObjType::default_group::<TestObjC>()];
+    for seed in 0..20 {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut st = BasicObjStore::<i32, u32>::new();
+        let mut untagged = [0u32; 3];
+        for _ in 0..300 {
+            let i = rng.gen_range(0..3);
+            let ty = tys[i].tid;
+            let mut failed = false;
+            let before = (st.amounts_t().collect::<Vec<_>>(), st.amounts_u().collect::<Vec<_>>());
+            match rng.gen_range(0..6) {
+                0 | 1 => {
+                    let tag = rng.gen_range(0..20);
+                    let o: PObj<i32, u32> = if i == 0 { Box::new(TestObjB::new(tag)) } else { Box::new(TestObjW { tag, weight: rng.gen_range(0..10) }) };
+                    let (exists, added) = (st.contains(&tag), o.obj_amount());
+                    let total = st.amounts_of(&o.obj_type().tid).and_then(|a| a.total()).unwrap_or(0);
+                    if st.try_add(tag, o).is_err() {
+                        failed = true;
+                        assert!(exists || total.checked_add(added).is_none());
+                    }
+                },
+                2 => { st.remove(&rng.gen_range(0..20)); },
+                3 => {
+                    let a = if rng.gen_bool(0.05) { u32::MAX } else { rng.gen_range(0..10) };
+                    let total = st.amounts_of(&ty).and_then(|a| a.total()).unwrap_or(0);
+                    match st.increase(&ty, a) {
+                        Ok(n) => {
+                            untagged[i] += a;
+                            assert_eq!(n, untagged[i]);
+                        },
+                        Err(e) => {
+                            failed = true;
+                            assert_eq!(e, AmountError::Overflow { ty, present: total, added: a });
+                            assert!(total.checked_add(a).is_none());
+                        }
+                    }
+                },
+                4 => {
+                    let a = rng.gen_range(0..10);
+                    let present = st.get_u(&ty);
+                    match st.decrease(&ty, a) {
+                        Ok(n) => {
+                            untagged[i] -= a;
+                            assert_eq!(n, untagged[i]);
+                        },
+                        Err(e) => {
+                            failed = true;
+                            match present {
+                                None => assert_eq!(e, AmountError::Missing(ty)),
+                                Some(_) => assert_eq!(e, AmountError::Underflow { ty, present: untagged[i], requested: a }),
+                            }
+                        }
+                    }
+                },
+                _ => {
+                    let present = st.get_u(&ty);
+                    assert_eq!(st.remove_u(&ty), present);
+                    untagged[i] = 0;
+                }
+            }
+            if failed {
+                assert_eq!((st.amounts_t().collect::<Vec<_>>(), st.amounts_u().collect::<Vec<_>>()), before);
+            }
+            for (j, ot) in tys.iter().enumerate() {
+                let tags = st.tags_of(&ot.tid).unwrap();
+                let tagged = st.get_batch_skip(tags).iter().map(|o| o.obj_amount()).sum::<u32>();
+                assert_eq!(st.amount_of_t(ot).unwrap_or(0), tagged);
+                assert_eq!(st.amount_of_u(ot).unwrap_or(0), untagged[j]);
+                assert_eq!(st.amount_of(ot).unwrap_or(0), tagged + untagged[j]);
+                assert_eq!(st.contains_u(&ot.tid), untagged[j] > 0);
+            }
+            assert!(st.amounts().zip(st.amounts_t().zip(st.amounts_u())).all(|(a, (t, u))| a == t + u));
+            assert_eq!(st.objs().map(|o| o.obj_amount()).sum::<u32>(), st.amounts_t().sum::<u32>());
+        }
+    }
+}
@@ -1,5 +1,7 @@
 // Copyright 2024 Junshuang Hu
-use meme::{core::{IObj, IRuleStat, ITaggedStore}, helpers, objs::BasicObjStore, rules::{BasicCondition, BasicEffect, BasicRuleStore}};
+use std::any::TypeId;
+
+use meme::{core::{ICondition, IObj, IRuleEffect, IRuleStat, ITaggedStore, ObjType}, helpers, objs::BasicObjStore, rules::{conflict::ConflictOn, multiset::MultisetRule, BasicCondition, BasicEffect, BasicRuleStore}};
 use meme_derive::{IObj, IRule};
 
 use crate::objs::{TestObjA, TestObjB, TestObjC};
@@ -32,6 +34,7 @@ impl TestRuleA {
                     }
                     Box::new(TestObjA::new(helpers::IdGen::next_i32_id(), new_inner))
                 })
+                .produces::<TestObjA>()
                 .build(),
         }
     }
@@ -53,15 +56,16 @@ impl TestRuleB {
             t: tag,
 
             cond: helpers::condition_builder()
-            .some_untagged::<TestObjA>(10)
             .rand_tagged::<TestObjA>(10).by_ref()
             .build(),
 
             eff: helpers::effect_builder()
             .crate_obj(|_| Box::new(TestObjB::new(helpers::IdGen::next_i32_id())))
+            .produces::<TestObjB>()
             .remove_objs(|req| {
-                req.rand_refs(0).unwrap().iter().map(|o| o.obj_tag().clone()).collect::<Vec<_>>()
+                req.rand_refs(0).unwrap().iter().map(|o| *o.obj_tag()).collect::<Vec<_>>()
             })
+            .consumes::<TestObjA>()
             .build(),
         }
     }
@@ -87,6 +91,7 @@ impl TestRuleC {
 
             eff: helpers::effect_builder()
             .crate_obj(|_| Box::new(TestObjC::new(helpers::IdGen::next_i32_id())))
+            .produces::<TestObjC>()
             .build(),
         }
     }
@@ -117,12 +122,13 @@ impl TestRuleD {
                 assert!(req.take.is_some());
                 if let Some(took) = req.take.as_mut().and_then(|t|t.rand_at_mut(0)) {
                     assert!(took.len() == 1);
-                    if took.len() != 0 {
+                    if !took.is_empty() {
                         return took.pop().unwrap();
                     }
                 }
-                return Box::new(TestObjC::new(helpers::IdGen::next_i32_id()));
+                Box::new(TestObjC::new(helpers::IdGen::next_i32_id()))
             })
+            .produces::<TestObjC>()
             .build(),
         }
     }
@@ -131,17 +137,17 @@ impl TestRuleD {
 #[test]
 pub fn basic_rule_store_test() {
     let mut ids = Vec::new();
-    ids.resize_with(4, || helpers::IdGen::next_i32_id() );
+    ids.resize_with(4, helpers::IdGen::next_i32_id );
 
     let a1 = Box::new(TestObjA::new(ids[0], 1.1));
     let a2 = Box::new(TestObjA::new(ids[1], 2.2));
     let b1 = Box::new(TestObjB::new(ids[2]));
     let b2 = Box::new(TestObjB::new(ids[3]));
     let mut ost = BasicObjStore::new();
-    ost.add_or_update(a1.obj_tag().clone(), a1);
-    ost.add_or_update(a2.obj_tag().clone(), a2);
-    ost.add_or_update(b1.obj_tag().clone(), b1);
-    ost.add_or_update(b2.obj_tag().clone(), b2);
+    ost.add_or_update(*a1.obj_tag(), a1);
+    ost.add_or_update(*a2.obj_tag(), a2);
+    ost.add_or_update(*b1.obj_tag(), b1);
+    ost.add_or_update(*b2.obj_tag(), b2);
 
     let ra = Box::new(TestRuleA::new(0, ids[0]));
     let rb = Box::new(TestRuleB::new(1));
@@ -161,4 +167,140 @@ pub fn basic_rule_store_test() {
     assert!(check_res_new.conflict_executable.is_none() && check_res_new.parallel_executable.is_none());
 
     // todo: 测试规则的执行
-}
\ No newline at end of file
+}
+#[test]
+pub fn conflict_graph_test() {
+    let mut rst = BasicRuleStore::new();
+    rst.add_or_update(0, Box::new(TestRuleA::new(0, 7)));
+    rst.add_or_update(1, Box::new(TestRuleB::new(1)));
+    rst.add_or_update(2, Box::new(TestRuleA::new(2, 7)));
+    rst.add_or_update(3, Box::new(TestRuleC::new(3)));
+    rst.add_or_update(4, Box::new(TestRuleB::new(4)));
+    rst.add_or_update(5, Box::new(MultisetRule::<u32, i32>::new(5, &[(ObjType::default_group::<TestObjB>(), 1)], &[], false, 0)));
+
+    let g = rst.conflict_graph();
+    assert!(g.conflicts(&0, &2) && g.conflicts(&1, &4));
+    assert!(!g.conflicts(&0, &1) && !g.conflicts(&0, &0));
+    assert_eq!(g.users_of(&ConflictOn::Tag(7)).len(), 2);
+    assert_eq!(g.resources_of(&1).unwrap(), &[ConflictOn::RandTagged(TypeId::of::<TestObjA>())]);
+    assert_eq!(g.resources_of(&5).unwrap(), &[ConflictOn::Untagged(TypeId::of::<TestObjB>())]);
+    let mut isolated = g.isolated().collect::<Vec<_>>();
+    isolated.sort();
+    assert_eq!(isolated, vec![&3, &5]);
+    assert_eq!(g.edges().len(), 2);
+    let mut groups = g.components().into_iter().map(|mut c| { c.sort(); c }).collect::<Vec<_>>();
+    groups.sort();
+    assert_eq!(groups, vec![vec![0, 2], vec![1, 4], vec![3], vec![5]]);
+
+    rst.remove(&2);
+    rst.add_or_update(4, Box::new(TestRuleA::new(4, 8)));
+    let g = rst.conflict_graph();
+    assert!(g.neighbors(&0).is_empty() && g.neighbors(&1).is_empty());
+    assert_eq!(g.len(), 5);
+}
+
+#[test]
+pub fn effect_signature_test() {
+    let eff: BasicEffect<i32> = helpers::effect_builder()
+        .crate_obj(|_| Box::new(TestObjB::new(helpers::IdGen::next_i32_id())))
+        .produces::<TestObjB>()
+        .increase_untagged::<TestObjA>(2)
+        .decrease_untagged::<TestObjC>(1)
+        .stop_mem()
+        .build();
+    let sigs = eff.signatures();
+    assert_eq!(sigs.len(), 4);
+    assert!(sigs[0].declares_product(&TypeId::of::<TestObjB>()));
+    assert!(sigs[1].declares_product(&TypeId::of::<TestObjA>()));
+    assert!(!sigs[3].is_declared());
+    assert_eq!(eff.produces(), vec![ObjType::default_group::<TestObjB>(), ObjType::default_group::<TestObjA>()]);
+    assert_eq!(eff.consumes(), vec![ObjType::default_group::<TestObjC>()]);
+}
+
+#[derive(IObj, IRule, Debug)]
+pub struct TestRuleSelect {
+    #[tag]
+    t: u32,
+    #[condition]
+    cond: BasicCondition<i32>,
+    #[effect]
+    eff: BasicEffect<i32>
+}
+
+fn inner_of(o: &dyn IObj<Tag = i32, Unit = u32>) -> f32 {
+    o.as_any().downcast_ref::<TestObjA>().map_or(0.0, |a| a.get_inner())
+}
+
+#[test]
+pub fn filtered_selection_test() {
+    let mut ost = BasicObjStore::new();
+    for i in 0..10 {
+        ost.add_or_update(i, Box::new(TestObjA::new(i, i as f32)));
+    }
+    let mut rst = BasicRuleStore::new();
+    rst.add_or_update(0, Box::new(TestRuleSelect {
+        t: 0,
+        cond: helpers::condition_builder()
+            .rand_tagged::<TestObjA>(3).filter_by(|o| inner_of(o) > 5.0).by_ref()
+            .build(),
+        eff: helpers::effect_empty()
+    }));
+    rst.add_or_update(1, Box::new(TestRuleSelect {
+        t: 1,
+        cond: helpers::condition_builder()
+            .rand_tagged::<TestObjA>(1).weight_by(|o| if inner_of(o) == 2.0 { 1.0 } else { 0.0 }).by_ref()
+            .build(),
+        eff: helpers::effect_empty()
+    }));
+    rst.add_or_update(2, Box::new(TestRuleSelect {
+        t: 2,
+        cond: helpers::condition_builder()
+            .rand_tagged::<TestObjA>(5).filter_by(|o| inner_of(o) > 5.0)
+            .build(),
+        eff: helpers::effect_empty()
+    }));
+
+    for _ in 0..20 {
+        let res = rst.check_on(&ost);
+        let infos = res.parallel_executable.into_iter().flatten()
+            .chain(res.conflict_executable.into_iter().flatten())
+            .collect::<Vec<_>>();
+        // 满足条件的只有 4 个对象，规则 2 不能执行
+        assert_eq!(infos.len(), 2);
+        for e in infos {
+            let chosen = e.rand_tags.unwrap().pop_front().unwrap();
+            match e.rule_index {
+                0 => assert!(chosen.len() == 3 && chosen.iter().all(|t| *t > 5)),
+                1 => assert_eq!(chosen, vec![2]),
+                _ => unreachable!(),
+            }
+        }
+    }
+}
+
+#[test]
+pub fn rule_store_removal_test() {
+    let (ta, tb) = (ObjType::default_group::<TestObjA>(), ObjType::default_group::<TestObjB>());
+    let mut rst = BasicRuleStore::new();
+    for i in 0..6u32 {
+        let ty = if i % 2 == 0 { ta.clone() } else { tb.clone() };
+        rst.add_or_update(i, Box::new(MultisetRule::<u32>::new(i, &[(ty, i + 1)], &[], false, i as i32)));
+    }
+    rst.remove(&1);
+    rst.remove_batch(&[4, 9]);
+    assert_eq!(rst.remove_batch_skip(&[0, 0]).len(), 1);
+    assert_eq!(rst.len(), 3);
+    assert_eq!(rst.conditions_count(), rst.conditions().count());
+    // 位置、tag、条件与优先级在移除后仍然一致
+    for (i, c) in rst.conditions().enumerate() {
+        let t = rst.tag_at(i).unwrap();
+        assert_eq!(rst.pos_of(&t), Some(i));
+        assert_eq!(rst.priority_at(i), Some(t as i32));
+        assert_eq!(c.untagged().as_ref().unwrap()[0].amount, t + 1);
+        assert_eq!(rst.get(&t).unwrap().obj_tag(), &t);
+    }
+    assert_eq!(rst.req_of_types().get(&ta.tid), Some(&3));
+    assert_eq!(rst.req_of_types().get(&tb.tid), Some(&10));
+    let g = rst.conflict_graph();
+    assert!(g.conflicts(&3, &5) && !g.conflicts(&2, &3) && g.len() == 3);
+}
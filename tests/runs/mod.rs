@@ -0,0 +1,114 @@
+// Copyright 2024 Junshuang Hu
+use std::any::TypeId;
+
+use meme::core::{EmuStatus, IObjStat, MemTarget, ObjType};
+use meme::mems::basic::BasicMem;
+use meme::rules::multiset::MultisetRule;
+use meme::runs::ensemble::{self, Ensemble};
+use meme::runs::sweep::{Grid, Sweep};
+use meme::tagged;
+
+use crate::mems::test_mem::{StopObj, TestRuleStop};
+use crate::objs::{TestObjA, TestObjB, TestObjC};
+
+#[derive(Debug, Clone)]
+struct Params {
+    a: u32,
+    rate: u32
+}
+
+fn conversion(p: &Params) -> BasicMem<u32, u32> {
+    let (a, b) = (ObjType::default_group::<TestObjA>(), ObjType::default_group::<TestObjB>());
+    let mut m = BasicMem::new(0, false);
+    m.init(
+        Vec::new(),
+        vec![(TypeId::of::<TestObjA>(), p.a)],
+        vec![tagged!(MultisetRule::<u32>::new(0, &[(a, 1)], &[(b, p.rate, MemTarget::Here)], false, 0))]
+    );
+    m
+}
+
+#[test]
+pub fn sweep_test() {
+    let params = Grid::new(Params { a: 0, rate: 0 })
+        .axis(&[2, 4], |p, v| p.a = *v)
+        .axis(&[1, 3], |p, v| p.rate = *v)
+        .build();
+    assert_eq!(params.iter().map(|p| (p.a, p.rate)).collect::<Vec<_>>(), vec![(2, 1), (2, 3), (4, 1), (4, 3)]);
+
+    let b = ObjType::default_group::<TestObjB>();
+    let table = Sweep::new(conversion)
+        .observe("b", move |m| m.objs().amount_of(&b).unwrap_or(0) as f64)
+        .run(&params);
+    assert_eq!(table.column("b").unwrap(), vec![2.0, 6.0, 4.0, 12.0]);
+    assert!(table.rows.iter().all(|r| r.status == EmuStatus::Pause && r.steps == r.params.a as usize));
+
+    let limited = Sweep::new(conversion).max_steps(3).observe("steps", |m| m.steps() as f64).run(&params[2..]);
+    assert!(limited.rows.iter().all(|r| r.status == EmuStatus::Continue && r.steps == 3));
+    let csv = limited.to_csv(&["a", "rate"], |p| vec![p.a.to_string(), p.rate.to_string()]);
+    assert_eq!(csv.lines().next(), Some("a,rate,steps,time,status,steps"));
+    assert_eq!(csv.lines().nth(1), Some("4,1,3,3,Continue,3"));
+
+    let quoted = Sweep::new(conversion).observe("b, \"x\"", |m| m.steps() as f64).run(&params[..1]);
+    let csv = quoted.to_csv(&["a\nrate"], |p| vec![format!("{},{}", p.a, p.rate)]);
+    assert_eq!(csv, "\"a\nrate\",steps,time,status,\"b, \"\"x\"\"\"\n\"2,1\",2,2,Pause,2\n");
+}
+
+#[test]
+pub fn ensemble_test() {
+    let (a, b, c) = (ObjType::default_group::<TestObjA>(), ObjType::default_group::<TestObjB>(), ObjType::default_group::<TestObjC>());
+    let (ra, rb, rc) = (a.clone(), b.clone(), c.clone());
+    let ens = Ensemble::new(move |seed| {
+            let mut m = BasicMem::<u32, u32>::new(0, false);
+            m.set_seed(seed);
+            m.init(
+                Vec::new(),
+                vec![(TypeId::of::<TestObjA>(), 1)],
+                vec![
+                    tagged!(MultisetRule::<u32>::new(0, &[(ra.clone(), 1)], &[(rb.clone(), 1, MemTarget::Here)], false, 0)),
+                    tagged!(MultisetRule::<u32>::new(1, &[(ra.clone(), 1)], &[(rc.clone(), 2, MemTarget::Here)], false, 0)),
+                ]
+            );
+            m
+        })
+        .replicas(200)
+        .max_steps(10)
+        .track(a)
+        .track(b);
+    let res = ens.run();
+
+    assert_eq!(res.columns, vec!["TestObjA", "TestObjB"]);
+    assert_eq!(res.steps(), 2);
+    let b_col = res.column_of("TestObjB").unwrap();
+    assert_eq!(res.mean(0), vec![1.0, 0.0]);
+    let mb = res.mean(b_col)[1];
+    assert!(mb > 0.2 && mb < 0.8);
+    assert!(res.variance(b_col)[1] > 0.0);
+    assert_eq!(ens.run().values_at(b_col, 1), res.values_at(b_col, 1));
+    assert_eq!(res.quantile(b_col, 0.0)[1], 0.0);
+    assert_eq!(res.quantile(b_col, 1.0)[1], 1.0);
+    assert_eq!(res.halting_steps().into_iter().collect::<Vec<_>>(), vec![(1, 200)]);
+    let outs = res.outputs(b_col);
+    assert_eq!(outs.iter().map(|o| o.0).collect::<Vec<_>>(), vec![0.0, 1.0]);
+    assert_eq!(outs.iter().map(|o| o.1).sum::<usize>(), 200);
+
+    let mut v = vec![4.0, 1.0, 3.0, 2.0];
+    assert_eq!(ensemble::mean(&v), 2.5);
+    assert!((ensemble::variance(&v) - 5.0 / 3.0).abs() < 1e-12);
+    assert_eq!(ensemble::quantile(&mut v, 0.5), 2.5);
+}
+
+/// 执行停止操作的一步产生新格局，扫描与系综的步数一致
+#[test]
+pub fn stop_step_test() {
+    let stopping = |_: &u64| {
+        let mut m = BasicMem::<u32, i32>::new(0, false);
+        m.init(Vec::new(), vec![(TypeId::of::<StopObj>(), 1)], vec![tagged!(TestRuleStop::new(0))]);
+        m
+    };
+    let table = Sweep::new(stopping).run(&[0]);
+    assert_eq!((table.rows[0].status, table.rows[0].steps), (EmuStatus::Stopped, 1));
+    let res = Ensemble::new(move |seed| stopping(&seed)).run();
+    assert_eq!(res.runs[0].status, EmuStatus::Stopped);
+    assert_eq!(res.runs[0].halted_at, Some(table.rows[0].steps));
+}